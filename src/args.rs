@@ -1,27 +1,346 @@
-use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
+use crate::batch::OutputTemplate;
+use crate::codec::StringEncoding;
+use crate::setting::{Assignment, Predicate, SettingPath};
+use crate::types::FactorioVersion;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use std::path::{Path, PathBuf};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug)]
 #[command(author, version, about)]
+// the flags that do something other than convert, of which only one can be given
+#[command(group(ArgGroup::new("action").multiple(false).args([
+    "self_test",
+    "dump_types_table",
+    "encode_single",
+    "encode_version_only",
+    "kv",
+    "count_bytes",
+    "inventory",
+    "output_template",
+    "list_mods",
+    "passthrough",
+    "has",
+    "get",
+    "set",
+    "extract_bytes",
+    "compat_check",
+    "find_value",
+    "find_duplicates",
+    "diff",
+    "dot",
+    "length_stats",
+    "schema_validate",
+    "lint",
+])))]
+#[command(group(ArgGroup::new("explained").multiple(true).args([
+    "encode_single",
+    "extract_bytes",
+])))]
+// checked by parse_args, since --kind has a default
+#[command(group(ArgGroup::new(MOD_SETTINGS_ONLY).multiple(true).args([
+    "lint",
+    "find_value",
+    "get",
+    "set",
+    "find_duplicates",
+    "schema_validate",
+    "lenient_bools",
+    "spellcheck",
+    "resolve_defaults",
+    "diff",
+    "overlay",
+    "target_version",
+    "group_by_mod",
+    "toml_section_counts",
+    "omit_none",
+    "trim_strings",
+    "stats",
+    "size_report",
+    "order_template",
+    "verify",
+    "allow_keys",
+    "deny_keys",
+    "rename",
+    "where_",
+])))]
 pub struct Args {
-    /// Whether to encode or decode the input. If not provided, will attempt to infer based on output type, or input type, in that order.
+    /// Whether to encode or decode the input. If not provided, will attempt to infer based on output type, input type, then the input's content, in that order.
     #[arg(short, long)]
     pub mode: Option<Mode>,
-    /// The format for the serialized input/output. If omitted, will attempt to infer based on mode and input or output
+    /// The format for the serialized input/output. If omitted, will attempt to infer based on mode and input or output, then the input's content
     #[arg(short, long)]
     pub format: Option<Format>,
-    /// The input path to read binary settings from. Use "-" for stdin
-    pub input: PathBuf,
+    /// The format of the input, overriding --format. `dat` reads binary settings, to decode
+    #[arg(long, value_name = "FORMAT")]
+    pub input_format: Option<SideFormat>,
+    /// The format of the output, overriding --format. `dat` writes binary settings, to encode
+    #[arg(long, value_name = "FORMAT")]
+    pub output_format: Option<SideFormat>,
+    /// The kind of settings file. Map settings keep their own root structure and are written as the raw property tree
+    #[arg(long, value_enum, default_value_t = Kind::ModSettings)]
+    pub kind: Kind,
+    /// Instead of converting, check the input settings for invalid values and report every problem found
+    #[arg(long)]
+    pub lint: bool,
+    /// Instead of converting, check that the input has the structure and value types of the --schema file and list every property that doesn't, without writing any settings. Exits with 1 if any don't match. With --format json, the mismatches are printed as a JSON array
+    #[arg(long, requires = "schema")]
+    pub schema_validate: bool,
+    /// The schema for --schema-validate and --lenient-bools, as written by --schema-only in JSON or TOML
+    #[arg(long, value_name = "FILE")]
+    pub schema: Option<PathBuf>,
+    /// When encoding JSON, accept the numbers 0 and 1 as false and true for settings the --schema says are bools, for tools that write bools as numbers. Without a schema, bools must be written as true or false
+    #[arg(long, requires = "schema", conflicts_with = "source_lines")]
+    pub lenient_bools: bool,
+    /// Instead of converting, exit with 0 if the input contains this setting (section/key) and 1 if not. Binary input is only read as far as the setting
+    #[arg(long, value_name = "SETTING")]
+    pub has: Option<SettingPath>,
+    /// Instead of converting, print the value of this setting (section/key) alone: strings as written, colors as r,g,b,a or as #rrggbbaa with --color-format hex, and None as an empty line. Exits with 1 if the input doesn't contain it
+    #[arg(long, value_name = "SETTING")]
+    pub get: Option<SettingPath>,
+    /// Instead of converting, change this setting (section/key=value or section/key:type=value) in the binary input and encode it back to the input file, or to OUTPUT if given. Without a type, the value keeps the setting's current type when it can. Every other setting is written as it was
+    #[arg(long, value_name = "ASSIGNMENT")]
+    pub set: Option<Assignment>,
+    /// With --set, add the setting if the input doesn't contain it rather than failing
+    #[arg(long, requires = "set")]
+    pub create: bool,
+    /// Instead of converting, print the bytes that encode this setting (section/key) in the binary input, as hex. Given an OUTPUT path, write the raw bytes there instead. Add --explain to annotate them
+    #[arg(long, value_name = "SETTING")]
+    pub extract_bytes: Option<SettingPath>,
+    /// Instead of converting, list the paths of settings whose value equals this bool, number or string. With --format json, the paths are printed as a JSON array
+    #[arg(long, value_name = "VALUE")]
+    pub find_value: Option<String>,
+    /// Instead of converting, list groups of settings in the same section that have equal values, other than true and false. With --format json, the groups are printed as a JSON array
+    #[arg(long)]
+    pub find_duplicates: bool,
+    /// Instead of converting, list the mods enabled in the mod-list.json in the same directory as the input. The settings file doesn't record its mods, so this is Factorio's own list from the mods directory. With --format json, the names are printed as a JSON array
+    #[arg(long)]
+    pub list_mods: bool,
+    /// Instead of converting, compare the input against this settings file (.dat, .json or .toml). Exits with 3 if they differ
+    #[arg(long, value_name = "FILE")]
+    pub diff: Option<PathBuf>,
+    /// How to print differences found by --diff. The unified diff compares the settings serialized as --format, TOML by default
+    #[arg(long, value_enum, default_value_t = DiffFormat::Summary)]
+    pub diff_format: DiffFormat,
+    /// Instead of converting, decode the binary input and encode it again as is, without simplifying it
+    #[arg(long)]
+    pub passthrough: bool,
+    /// With --passthrough, write nothing and exit with 3 if encoding would change the file
+    #[arg(long, requires = "passthrough")]
+    pub check: bool,
+    /// Instead of converting, decode the input and report how many bytes each type contributes
+    #[arg(long)]
+    pub count_bytes: bool,
+    /// Instead of converting, decode the binary input and write its property tree as a GraphViz DOT graph, with a node per property labeled with its key and type. Render it with `dot -Tsvg`
+    #[arg(long)]
+    pub dot: bool,
+    /// Instead of converting, decode the input and report how many string lengths used the 1-byte and 5-byte forms
+    #[arg(long)]
+    pub length_stats: bool,
+    /// Summarize many .dat files: the input is a directory or a pattern like `servers/*.dat`. Lists each file's version, setting counts and content hash, as a table or with `-f json`
+    #[arg(long)]
+    pub inventory: bool,
+    /// Decode each .dat file matched by INPUT, a directory or a pattern as with --inventory, to a path from this template. The placeholders are {stem}, {version}, {format} and {date}, as in `{version}/{stem}.{format}`. Paths are relative to OUTPUT if given
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_template: Option<OutputTemplate>,
+    /// With several input files, stop at the first that fails rather than processing them all and listing the failures at the end
+    #[arg(long)]
+    pub fail_fast: bool,
+    /// Write this Factorio version's header when encoding, warning about settings it may not support. With --compat-check, only report those
+    #[arg(long, value_name = "VERSION")]
+    pub target_version: Option<FactorioVersion>,
+    /// Report settings the --target-version may not support, without converting anything. Exits with 3 if there are any
+    #[arg(long, requires = "target_version")]
+    pub compat_check: bool,
+    /// Recovery: skip the version header and decode as if the file were this version, e.g. 1.1.82
+    #[arg(long, value_name = "VERSION")]
+    pub assume_version: Option<FactorioVersion>,
+    /// Recovery: the offset in bytes at which the root property begins. Used with --assume-version
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "assume_version",
+        default_value_t = 0
+    )]
+    pub body_offset: u64,
+    /// When decoding, write only the structure of the file, with the type of each value in place of the value
+    #[arg(long)]
+    pub schema_only: bool,
+    /// When decoding, group settings by the mod prefix of their keys ({mod: {section: {key: value}}}). Keys without a prefix are grouped under "unknown-mod". The output can't be encoded back
+    #[arg(long)]
+    pub group_by_mod: bool,
+    /// When decoding, add each setting's default beside its value. The defaults are read from the settings.lua of each mod in this mods directory, zipped or not, or from a settings file (.dat, .json or .toml). The output can't be encoded back
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["group_by_mod", "schema_only"])]
+    pub resolve_defaults: Option<PathBuf>,
+    /// After writing OUTPUT, also write OUTPUT.sha256 holding the SHA-256 of the written bytes, in the format `sha256sum -c` checks. Nothing is written when the output goes to stdout. With --set and no OUTPUT, the checksum is of the input file it rewrites
+    #[arg(long)]
+    pub emit_checksum: bool,
+    /// When decoding, print to stderr how deeply the property tree nests: the root is at depth 1, so mod settings are 4 deep, or 5 with colors
+    #[arg(long)]
+    pub max_depth_report: bool,
+    /// Leave settings whose value is None out of decoded or converted text. Encoding keeps them, and this can't be given when encoding
+    #[arg(long)]
+    pub omit_none: bool,
+    /// When encoding, trim leading and trailing whitespace from String values, such as the stray spaces left by pasting from a spreadsheet, warning about each value trimmed. Keys are left alone
+    #[arg(long)]
+    pub trim_strings: bool,
+    /// Warn about settings whose key is one or two edits away from a known key, and so likely a typo that Factorio would ignore. The known keys are those declared in a mods directory's settings.lua files, those in a settings file (.dat, .json or .toml), or a text file listing one key per line
+    #[arg(long, value_name = "REFERENCE")]
+    pub spellcheck: Option<PathBuf>,
+    /// When decoding, print to stderr how many settings were simplified, how many needed fallback handling, and how many warnings were emitted
+    #[arg(long)]
+    pub stats: bool,
+    /// When decoding, print to stderr a table of the input's size as read and as pretty JSON, compact JSON and TOML as written, with each size as a multiple of the binary one, to help choose a format to archive in
+    #[arg(long)]
+    pub size_report: bool,
+    /// When decoding, write compact JSON with every object's keys sorted, for comparing and caching as a build artifact. The sorted order isn't the file's, so don't encode it back expecting the same bytes
+    #[arg(long)]
+    pub reproducible: bool,
+    /// When decoding, write text suited to version control, so regenerating it gives minimal diffs: every object's keys sorted as with --reproducible but still pretty-printed, LF line endings and exactly one trailing newline. Floats are always written in their shortest exact form
+    #[arg(long, conflicts_with_all = ["reproducible", "no_trailing_newline"])]
+    pub vcs: bool,
+    /// How to write color values in TOML output: inline, as `value = { r = 1.0, g = 0.5, b = 0.0, a = 1.0 }`, or as a `[section.key.value]` table of their own
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = TomlColors::Inline)]
+    pub toml_colors: TomlColors,
+    /// In TOML output, put a comment before each section saying how many settings it holds, such as `# startup: 42 settings`. Comments are ignored when the file is encoded again
+    #[arg(long)]
+    pub toml_section_counts: bool,
+    /// How to write color values in decoded or converted text: as an `{r, g, b, a}` object, an `[r, g, b, a]` array, or a `"#rrggbbaa"` hex string. Input may use any of them. Colors whose channels aren't whole steps of 1/255 between 0 and 1 stay objects in hex, so no precision is lost
+    #[arg(long, value_enum, value_name = "FORM", default_value_t = ColorFormat::Object)]
+    pub color_format: ColorFormat,
+    /// When decoding, reuse output cached in this directory for identical input and options, and cache new output there. The newest 64 entries are kept
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Apply the settings in this file (.dat, .json or .toml) over the input, adding or replacing keys. Repeat to layer several files; later ones win. A binary input can be merged straight into a binary output
+    #[arg(long, value_name = "FILE")]
+    pub overlay: Vec<PathBuf>,
+    /// Write the Factorio version of the last --overlay rather than the input's
+    #[arg(long, requires = "overlay")]
+    pub take_version_from_overlay: bool,
+    /// Compress the output with gzip. Output paths ending in .gz are compressed without it, and compressed input is always decompressed
+    #[arg(long)]
+    pub gzip: bool,
+    /// End decoded or converted text without a newline. By default it ends with exactly one
+    #[arg(long)]
+    pub no_trailing_newline: bool,
+    /// When the input is TOML, cite the line and column of the settings named by --lint and by encoding errors and warnings
+    #[arg(long)]
+    pub source_lines: bool,
+    /// How to write warnings and other messages on stderr. As JSON, every message is an object on its own line with a "level" such as "warn", "info", "error" or "stats", followed by a summary object with the warning count
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = WarningsFormat::Text)]
+    pub warnings_format: WarningsFormat,
+    /// Report more detail on stderr, such as which overlay set each setting
+    #[arg(short, long)]
+    pub verbose: bool,
+    /// When encoding, rename settings by a map with one `old = new` per line. Prefix `old` with a section as in `startup/old`, and end both sides with `*` to rename a prefix
+    #[arg(long, value_name = "FILE")]
+    pub rename: Option<PathBuf>,
+    /// When encoding, reorder each section's keys to match this settings file (.dat, .json or .toml). Keys it doesn't contain are placed last
+    #[arg(long, value_name = "FILE")]
+    pub order_template: Option<PathBuf>,
+    /// When encoding, decode the result and check it matches the input before writing any output
+    #[arg(long)]
+    pub verify: bool,
+    /// When encoding, only write settings whose keys match a glob pattern in this file (one per line, optionally prefixed with section/)
+    #[arg(long, value_name = "FILE")]
+    pub allow_keys: Option<PathBuf>,
+    /// When encoding, drop settings whose keys match a glob pattern in this file, even if allowed
+    #[arg(long, value_name = "FILE")]
+    pub deny_keys: Option<PathBuf>,
+    /// When encoding, only write settings whose value satisfies a condition such as `startup/difficulty>2`: an optional section, a key (glob), one of = != < <= > >=, and a value. Repeat to keep settings matching any
+    #[arg(long = "where", value_name = "CONDITION")]
+    pub where_: Vec<Predicate>,
+    /// How to read strings in binary input that aren't valid UTF-8. Valid UTF-8 is always read as is
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = StringEncodingArg::Utf8)]
+    pub string_encoding: StringEncodingArg,
+    /// The Unix permissions for the output file in octal, e.g. 0644, including the input file rewritten by --set and the files written by --output-template. Ignored when writing to stdout. By default a replaced file keeps its permissions
+    #[arg(long, value_name = "MODE", value_parser = parse_file_mode)]
+    pub file_mode: Option<u32>,
+    /// Reject input larger than this many bytes. Hex, base64 and hexdump text is measured as it's read, and compressed input once decompressed
+    #[arg(long, value_name = "BYTES")]
+    pub max_input_size: Option<u64>,
+    /// Give up decoding after this many milliseconds, for input that can't be trusted. The time is only checked between dictionary and list entries, so it's a best-effort limit rather than a hard one; pair it with --max-input-size
+    #[arg(long, value_name = "MS")]
+    pub timeout: Option<u64>,
+    /// Treat INPUT as the binary settings written in hex rather than a path. With "-", the hex is read from stdin
+    #[arg(long)]
+    pub hex: bool,
+    /// Read INPUT, a file or "-" for stdin, as a hexdump of the binary settings in the layout of `xxd` or `hexdump -C`, as pasted into bug reports. Offsets and the ASCII gutter are ignored
+    #[arg(long, conflicts_with = "hex")]
+    pub hexdump: bool,
+    /// Treat INPUT as the binary settings written in base64 (standard or URL-safe, padding optional) rather than a path. With "-", the base64 is read from stdin
+    #[arg(long, conflicts_with_all = ["hex", "hexdump"])]
+    pub base64: bool,
+    /// Read the input from the system clipboard rather than INPUT, through pbpaste on macOS, PowerShell on Windows, and wl-paste, xclip or xsel elsewhere. Binary settings pasted as hex or base64 are recognized, as are JSON and TOML. Output goes to stdout
+    #[arg(long, conflicts_with_all = ["input", "hex", "hexdump", "base64"])]
+    pub clipboard: bool,
+    /// Instead of converting, encode a file containing only this setting (section/key=value or section/key:type=value) and print it as hex
+    #[arg(long, value_name = "ASSIGNMENT", requires = "factorio_version")]
+    pub encode_single: Option<Assignment>,
+    /// Instead of converting, write a settings file with no settings for the --factorio-version to stdout, as Factorio writes when every setting is at its default
+    #[arg(long, requires = "factorio_version", conflicts_with = "input")]
+    pub encode_version_only: bool,
+    /// Instead of converting, write a settings file holding only these settings to stdout, each given as section/key=value or section/key:type=value. Repeat for each setting
+    #[arg(
+        long,
+        value_name = "ASSIGNMENT",
+        requires = "factorio_version",
+        conflicts_with = "input"
+    )]
+    pub kv: Vec<Assignment>,
+    /// Check that decoding and encoding the bundled fixtures reproduces them exactly
+    #[arg(long, hide = true)]
+    pub self_test: bool,
+    /// List each property type byte and whether this build encodes and decodes it, checked by round tripping a value of each type
+    #[arg(long, hide = true)]
+    pub dump_types_table: bool,
+    /// With --extract-bytes or --encode-single, annotate the printed bytes with what each part of the encoding represents
+    #[arg(long, requires = "explained")]
+    pub explain: bool,
+    /// The Factorio version to write when creating settings without an input file, e.g. 1.1.82
+    #[arg(long, value_name = "VERSION")]
+    pub factorio_version: Option<FactorioVersion>,
+    /// The input path to read binary settings from. Use "-" for stdin. When encoding, this can be a directory of section files such as `startup.json`
+    #[arg(required_unless_present_any = [
+        "encode_single",
+        "encode_version_only",
+        "kv",
+        "self_test",
+        "dump_types_table",
+        "clipboard",
+    ])]
+    pub input: Option<PathBuf>,
     /// The output file. Overwrites if present. Stdout if omitted.
     pub output: Option<PathBuf>,
 }
 
-#[derive(Debug, Copy, Clone, ValueEnum)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
 pub enum Format {
     #[value(alias("t"))]
     Toml,
     #[value(alias("j"))]
     Json,
+    /// Newline-delimited JSON, one setting record per line. Only read, when encoding
+    #[value(alias("jsonl"))]
+    Ndjson,
+    /// A self-contained HTML page with a table per section. Only written, when decoding
+    Html,
+}
+
+/// A format for one side of the conversion, which unlike --format can be the binary file
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum SideFormat {
+    /// Binary settings, as in mod-settings.dat
+    #[value(alias("binary"))]
+    Dat,
+    #[value(alias("t"))]
+    Toml,
+    #[value(alias("j"))]
+    Json,
+    #[value(alias("jsonl"))]
+    Ndjson,
+    Html,
 }
 
 #[derive(Debug, Copy, Clone, ValueEnum)]
@@ -30,8 +349,139 @@ pub enum Mode {
     Decode,
     #[value(alias("e"))]
     Encode,
+    /// Convert between text formats without an intermediate binary file
+    #[value(alias("c"))]
+    Convert,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum Kind {
+    /// mod-settings.dat, with its startup, runtime-global and runtime-per-user sections
+    ModSettings,
+    /// Other property tree files such as map settings, with any root structure
+    MapSettings,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum TomlColors {
+    Inline,
+    Table,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum ColorFormat {
+    Object,
+    Array,
+    Hex,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum WarningsFormat {
+    /// A line starting with "Warning:"
+    Text,
+    /// {"level": "warn", "key": "startup/foo", "message": "..."}, where key is only present for warnings about one setting
+    Json,
 }
 
+/// The library's [`StringEncoding`] as a command line value
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum StringEncodingArg {
+    /// Reject them
+    Utf8,
+    /// Read them as Latin-1, where each byte is one character
+    Latin1,
+    /// Replace invalid sequences with U+FFFD
+    Lossy,
+}
+
+impl From<StringEncodingArg> for StringEncoding {
+    fn from(arg: StringEncodingArg) -> StringEncoding {
+        match arg {
+            StringEncodingArg::Utf8 => StringEncoding::Utf8,
+            StringEncodingArg::Latin1 => StringEncoding::Latin1,
+            StringEncodingArg::Lossy => StringEncoding::Lossy,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+pub enum DiffFormat {
+    /// A git-style diff of the serialized settings
+    Unified,
+    /// A patch listing set and remove operations
+    Json,
+    /// A sentence describing each change
+    Summary,
+}
+
+impl SideFormat {
+    /// The text format, or None for binary settings
+    pub fn text(self) -> Option<Format> {
+        match self {
+            SideFormat::Dat => None,
+            SideFormat::Toml => Some(Format::Toml),
+            SideFormat::Json => Some(Format::Json),
+            SideFormat::Ndjson => Some(Format::Ndjson),
+            SideFormat::Html => Some(Format::Html),
+        }
+    }
+}
+
+impl Format {
+    /// The file extension for the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Toml => "toml",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Html => "html",
+        }
+    }
+}
+
+impl Args {
+    /// The input path. Modes that require an input are enforced by the parser, so this only falls
+    /// back to stdin for modes that don't read one.
+    pub fn input(&self) -> &Path {
+        self.input.as_deref().unwrap_or(Path::new("-"))
+    }
+
+    /// Whether the input is binary settings written out as text
+    pub fn inline_binary(&self) -> bool {
+        self.hex || self.base64 || self.hexdump
+    }
+}
+
+fn parse_file_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("Expected an octal file mode such as 0644, found {:?}", s))
+}
+
+const MOD_SETTINGS_ONLY: &str = "mod_settings_only";
+
 pub fn parse_args() -> Args {
-    Args::parse()
+    let mut command = Args::command();
+    let matches = command.get_matches_mut();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if args.kind == Kind::MapSettings {
+        let group = command
+            .get_groups()
+            .find(|group| group.get_id() == MOD_SETTINGS_ONLY)
+            .expect("the group is declared on Args");
+        let given = group
+            .get_args()
+            .find(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine));
+        if let Some(id) = given {
+            let long = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .and_then(|arg| arg.get_long())
+                .unwrap_or(id.as_str());
+            let message = format!("--{} only applies to --kind mod-settings", long);
+            command.error(ErrorKind::ArgumentConflict, message).exit();
+        }
+    }
+    args
 }
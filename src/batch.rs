@@ -0,0 +1,160 @@
+use crate::types::FactorioVersion;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A path for each file of a batch, with placeholders filled in per file: `{stem}` is the input's
+/// name without its extension, `{version}` the Factorio version it was written by, `{format}` the
+/// extension of the output format and `{date}` today's date as YYYY-MM-DD.
+#[derive(Clone, Debug)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Field {
+    Stem,
+    Version,
+    Format,
+    Date,
+}
+
+/// The values for one file's placeholders
+pub struct Fields<'a> {
+    pub stem: &'a str,
+    /// Only read when the template uses `{version}`
+    pub version: Option<FactorioVersion>,
+    pub format: &'a str,
+    pub date: &'a str,
+}
+
+impl FromStr for OutputTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_owned()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unclosed {{ in output template {:?}", s))?;
+            let field = match &rest[open + 1..open + close] {
+                "stem" => Field::Stem,
+                "version" => Field::Version,
+                "format" => Field::Format,
+                "date" => Field::Date,
+                other => anyhow::bail!(
+                    "Unknown placeholder {{{}}} in output template, expected one of {{stem}}, {{version}}, {{format}}, {{date}}",
+                    other
+                ),
+            };
+            parts.push(Part::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if rest.contains('}') {
+            anyhow::bail!("Unmatched }} in output template {:?}", s);
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        if parts.is_empty() {
+            anyhow::bail!("Output template is empty");
+        }
+        Ok(OutputTemplate { parts })
+    }
+}
+
+impl OutputTemplate {
+    /// Whether expanding the template needs the file's version, which means decoding its header
+    pub fn uses_version(&self) -> bool {
+        self.parts.contains(&Part::Field(Field::Version))
+    }
+
+    pub fn expand(&self, fields: &Fields) -> String {
+        let mut path = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push_str(text),
+                Part::Field(Field::Stem) => path.push_str(fields.stem),
+                Part::Field(Field::Version) => {
+                    if let Some(version) = fields.version {
+                        let _ = write!(path, "{}", version);
+                    }
+                }
+                Part::Field(Field::Format) => path.push_str(fields.format),
+                Part::Field(Field::Date) => path.push_str(fields.date),
+            }
+        }
+        path
+    }
+}
+
+/// Today's date in UTC, as YYYY-MM-DD
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    date_of(seconds / 86_400)
+}
+
+/// The calendar date a number of days after 1970-01-01
+fn date_of(days: u64) -> String {
+    // Howard Hinnant's civil_from_days, for dates after the epoch
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{date_of, Fields, OutputTemplate};
+    use crate::types::FactorioVersion;
+
+    #[test]
+    fn expands_placeholders() {
+        let template: OutputTemplate = "{version}/{stem}-{date}.{format}".parse().expect("parsing");
+        assert!(template.uses_version());
+        let path = template.expand(&Fields {
+            stem: "server",
+            version: Some(FactorioVersion::new(1, 1, 82, 4)),
+            format: "json",
+            date: "2024-01-31",
+        });
+        assert_eq!(path, "1.1.82.4/server-2024-01-31.json");
+        assert!(!"{stem}.json"
+            .parse::<OutputTemplate>()
+            .expect("parsing")
+            .uses_version());
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        for template in ["{name}.json", "{stem", "stem}.json", ""] {
+            assert!(template.parse::<OutputTemplate>().is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date_of(0), "1970-01-01");
+        assert_eq!(date_of(19_753), "2024-01-31");
+        assert_eq!(date_of(19_782), "2024-02-29");
+    }
+}
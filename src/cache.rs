@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The most entries kept in a cache directory. Storing a new entry beyond this removes the least
+/// recently modified ones.
+pub const MAX_ENTRIES: usize = 64;
+
+const EXTENSION: &str = "cached";
+
+/// An on-disk cache of decoded output, keyed by a hash of the input's content and the options
+/// that affect the output. A changed input hashes to a new key, so stale entries are never
+/// returned; they're eventually evicted once the directory holds more than [`MAX_ENTRIES`].
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Cache {
+        Cache {
+            dir: dir.to_owned(),
+        }
+    }
+
+    /// The key for input data decoded with the described options
+    pub fn key(data: &[u8], options: &str) -> String {
+        format!(
+            "{:016x}-{:016x}-{}",
+            fnv1a(data),
+            fnv1a(options.as_bytes()),
+            data.len()
+        )
+    }
+
+    /// A hash of data that's stable across runs and Rust releases
+    pub fn hash(data: &[u8]) -> u64 {
+        fnv1a(data)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, EXTENSION))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path(key)).ok()
+    }
+
+    pub fn put(&self, key: &str, value: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // written beside the entry and renamed, so a concurrent reader never sees part of it
+        let temp = self
+            .dir
+            .join(format!(".{}.{}.tmp", key, std::process::id()));
+        let mut file = File::create(&temp)?;
+        file.write_all(value)?;
+        drop(file);
+        std::fs::rename(&temp, self.path(key))?;
+        self.evict()
+    }
+
+    fn evict(&self) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect::<Vec<_>>();
+        if entries.len() <= MAX_ENTRIES {
+            return Ok(());
+        }
+        entries.sort();
+        for (_, path) in &entries[..entries.len() - MAX_ENTRIES] {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The 64-bit FNV-1a hash, which unlike the std hasher is stable across Rust releases
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fnv1a, Cache, MAX_ENTRIES};
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn keys_depend_on_content_and_options() {
+        let key = Cache::key(b"data", "json");
+        assert_eq!(key, Cache::key(b"data", "json"));
+        assert_ne!(key, Cache::key(b"date", "json"));
+        assert_ne!(key, Cache::key(b"data", "toml"));
+    }
+
+    #[test]
+    fn stores_and_evicts() {
+        let dir =
+            std::env::temp_dir().join(format!("factorio-settings-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cache::new(&dir);
+        assert_eq!(cache.get("missing"), None);
+        for i in 0..=MAX_ENTRIES {
+            cache
+                .put(&format!("key-{}", i), i.to_string().as_bytes())
+                .expect("storing");
+        }
+        assert_eq!(cache.get("key-3"), Some(b"3".to_vec()));
+        let count = std::fs::read_dir(&dir).expect("listing").count();
+        assert_eq!(count, MAX_ENTRIES);
+        std::fs::remove_dir_all(&dir).expect("cleaning up");
+    }
+}
@@ -1,9 +1,12 @@
-use crate::simple::{ModSettings, ModSettingsValue};
+use crate::simple::{ModSetting, ModSettings, ModSettingsValue};
 use crate::types::FactorioVersion;
 use anyhow::anyhow;
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::io::{Read, Write};
+use std::time::Instant;
 
 const TYPE_NONE: u8 = 0;
 const TYPE_BOOL: u8 = 1;
@@ -12,6 +15,110 @@ const TYPE_STRING: u8 = 3;
 const TYPE_LIST: u8 = 4;
 const TYPE_DICTIONARY: u8 = 5;
 const TYPE_INTEGER: u8 = 6;
+/// Every property type byte, in order
+pub const TYPE_BYTES: [u8; 7] = [
+    TYPE_NONE,
+    TYPE_BOOL,
+    TYPE_DOUBLE,
+    TYPE_STRING,
+    TYPE_LIST,
+    TYPE_DICTIONARY,
+    TYPE_INTEGER,
+];
+
+/// How to read string bytes that aren't valid UTF-8
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum StringEncoding {
+    /// Reject them
+    #[default]
+    Utf8,
+    /// Read them as Latin-1, where each byte is one character
+    Latin1,
+    /// Replace invalid sequences with U+FFFD
+    Lossy,
+}
+
+/// Options for decoding, threaded through the property tree
+#[derive(Debug, Default)]
+pub struct DecodeOptions {
+    pub strings: StringEncoding,
+    /// How many strings weren't valid UTF-8 and were read with `strings`
+    pub transcoded: Cell<usize>,
+    /// How often each form of the optimized length encoding was read
+    pub lengths: LengthStats,
+    /// Bytes read so far, for locating errors. Starts at the offset decoding begins from.
+    pub position: Cell<u64>,
+    /// How deep in the property tree the property being read is; the root is at depth 1
+    pub depth: Cell<usize>,
+    /// The deepest any property read was
+    pub max_depth: Cell<usize>,
+    /// When to give up. It's checked before each dictionary or list entry, so a single huge
+    /// string can still run past it.
+    pub deadline: Option<Instant>,
+    /// When present, the bytes read for each kind of data, such as "string" or "dictionary key"
+    pub bytes: Option<RefCell<IndexMap<&'static str, u64>>>,
+}
+
+impl DecodeOptions {
+    /// Adds the bytes read since `start` to a kind of data, when bytes are being counted
+    fn count(&self, kind: &'static str, start: u64) {
+        if let Some(bytes) = &self.bytes {
+            *bytes.borrow_mut().entry(kind).or_default() += self.position.get() - start;
+        }
+    }
+
+    fn check_deadline(&self) -> anyhow::Result<()> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(anyhow!(
+                "Decoding timed out at offset {}",
+                self.position.get()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A reader that advances a position as it's read from
+struct Tracked<'a, R> {
+    inner: R,
+    position: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for Tracked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.position.set(self.position.get() + count as u64);
+        Ok(count)
+    }
+}
+
+/// Counts of the forms of optimized lengths read: one byte for lengths below 255, or 0xff
+/// followed by the length as 4 bytes
+#[derive(Debug, Default)]
+pub struct LengthStats {
+    pub short: Cell<usize>,
+    pub long: Cell<usize>,
+    /// Lengths in the long form that would fit the short one, which Factorio doesn't write
+    pub long_but_short: Cell<usize>,
+}
+
+impl LengthStats {
+    fn record(&self, long: bool, value: u32) {
+        let count = match (long, value < 0xff) {
+            (false, _) => &self.short,
+            (true, false) => &self.long,
+            (true, true) => {
+                self.long.set(self.long.get() + 1);
+                &self.long_but_short
+            }
+        };
+        count.set(count.get() + 1);
+    }
+}
+
+/// Upper bound on entries to preallocate for a dictionary, so a corrupt length can't cause a huge
+/// allocation before the entries themselves fail to decode
+const MAX_PREALLOCATED_ENTRIES: usize = 1024;
 
 impl Codec for FactorioVersion {
     fn decode(input: &mut impl Read) -> anyhow::Result<FactorioVersion> {
@@ -20,12 +127,7 @@ impl Codec for FactorioVersion {
             input.read_u16_into::<LE>(&mut vers)?;
             vers
         };
-        Ok(FactorioVersion {
-            major,
-            minor,
-            patch,
-            build,
-        })
+        Ok(FactorioVersion::new(major, minor, patch, build))
     }
 
     fn encode(&self, writer: &mut impl Write) -> anyhow::Result<()> {
@@ -37,24 +139,78 @@ impl Codec for FactorioVersion {
     }
 }
 
-#[derive(Clone, Debug)]
+/// A node of the property tree. Serializes as its type and value with the any-flag alongside,
+/// which is omitted when false.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Property {
+    #[serde(default, skip_serializing_if = "is_false")]
     pub any_flag: bool,
+    #[serde(flatten)]
     pub value: PropertyValue,
 }
 
-#[derive(Clone, Debug)]
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// An item of a list property. Each item is stored after a key, which Factorio leaves empty and
+/// is omitted from serialized output when it is.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ListItem {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key: String,
+    #[serde(flatten)]
+    pub property: Property,
+}
+
+impl From<Property> for ListItem {
+    fn from(property: Property) -> ListItem {
+        ListItem {
+            key: String::new(),
+            property,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
 pub enum PropertyValue {
     None,
     Bool(bool),
     Double(f64),
     String(String),
-    List(Vec<Property>),
+    List(Vec<ListItem>),
     Dictionary(IndexMap<String, Property>),
     Integer(i64),
 }
 
 impl PropertyValue {
+    /// The byte identifying this value's type in the encoding
+    pub fn type_byte(&self) -> u8 {
+        match self {
+            Self::None => TYPE_NONE,
+            Self::Bool(_) => TYPE_BOOL,
+            Self::Double(_) => TYPE_DOUBLE,
+            Self::String(_) => TYPE_STRING,
+            Self::List(_) => TYPE_LIST,
+            Self::Dictionary(_) => TYPE_DICTIONARY,
+            Self::Integer(_) => TYPE_INTEGER,
+        }
+    }
+
+    /// A lowercase name for this value's type
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Bool(_) => "bool",
+            Self::Double(_) => "double",
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Dictionary(_) => "dictionary",
+            Self::Integer(_) => "integer",
+        }
+    }
+
     #[allow(unused)]
     pub fn as_bool(&self) -> Option<&bool> {
         match self {
@@ -80,7 +236,7 @@ impl PropertyValue {
     }
 
     #[allow(unused)]
-    pub fn as_list(&self) -> Option<&Vec<Property>> {
+    pub fn as_list(&self) -> Option<&Vec<ListItem>> {
         match self {
             Self::List(l) => Some(l),
             _ => None,
@@ -104,27 +260,66 @@ impl PropertyValue {
     }
 }
 
+impl Property {
+    /// Decodes the remainder of a property whose type and any-flag bytes were already read
+    fn decode_body(
+        vtype: u8,
+        any_flag: u8,
+        input: &mut impl Read,
+        options: &DecodeOptions,
+    ) -> anyhow::Result<Property> {
+        let depth = options.depth.get() + 1;
+        options.depth.set(depth);
+        options.max_depth.set(options.max_depth.get().max(depth));
+        let start = options.position.get();
+        // lists and dictionaries count their own lengths and keys, and their entries count the rest
+        let (value, kind) = match vtype {
+            TYPE_NONE => (PropertyValue::None, None),
+            TYPE_BOOL => (PropertyValue::Bool(Codec::decode(input)?), Some("bool")),
+            TYPE_DOUBLE => (PropertyValue::Double(Codec::decode(input)?), Some("double")),
+            TYPE_STRING => (
+                PropertyValue::String(Codec::decode_with(input, options)?),
+                Some("string"),
+            ),
+            TYPE_LIST => (
+                PropertyValue::List(Codec::decode_with(input, options)?),
+                None,
+            ),
+            TYPE_DICTIONARY => (
+                PropertyValue::Dictionary(Codec::decode_with(input, options)?),
+                None,
+            ),
+            TYPE_INTEGER => (
+                PropertyValue::Integer(Codec::decode(input)?),
+                Some("integer"),
+            ),
+            other => return Err(anyhow!("Unknown type: {:#x}", other)),
+        };
+        if let Some(kind) = kind {
+            options.count(kind, start);
+        }
+        options.depth.set(depth - 1);
+        Ok(Property {
+            any_flag: loose_bool(any_flag),
+            value,
+        })
+    }
+}
+
 impl Codec for Property {
     fn decode(input: &mut impl Read) -> anyhow::Result<Property> {
+        Self::decode_with(input, &DecodeOptions::default())
+    }
+
+    fn decode_with(input: &mut impl Read, options: &DecodeOptions) -> anyhow::Result<Property> {
+        let start = options.position.get();
         let [vtype, any_flag] = {
             let mut tree_header = [0; 2];
             input.read_exact(&mut tree_header)?;
             tree_header
         };
-        let value = match vtype {
-            TYPE_NONE => PropertyValue::None,
-            TYPE_BOOL => PropertyValue::Bool(Codec::decode(input)?),
-            TYPE_DOUBLE => PropertyValue::Double(Codec::decode(input)?),
-            TYPE_STRING => PropertyValue::String(Codec::decode(input)?),
-            TYPE_LIST => PropertyValue::List(Codec::decode(input)?),
-            TYPE_DICTIONARY => PropertyValue::Dictionary(Codec::decode(input)?),
-            TYPE_INTEGER => PropertyValue::Integer(Codec::decode(input)?),
-            other => return Err(anyhow!("Unknown type: {:#x}", other)),
-        };
-        Ok(Property {
-            any_flag: loose_bool(any_flag),
-            value,
-        })
+        options.count("property header", start);
+        Self::decode_body(vtype, any_flag, input, options)
     }
 
     fn encode(&self, writer: &mut impl Write) -> anyhow::Result<()> {
@@ -168,8 +363,10 @@ impl Codec for Property {
     }
 }
 
-#[derive(Clone, Debug)]
+/// The settings file as its raw property tree
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Settings {
+    #[serde(rename = "factorio_version")]
     pub version: FactorioVersion,
     pub properties: Property,
 }
@@ -179,64 +376,78 @@ impl Settings {
         Self::decode(reader)
     }
 
+    pub fn from_reader_with(
+        reader: &mut impl Read,
+        options: &DecodeOptions,
+    ) -> anyhow::Result<Settings> {
+        Self::decode_with(reader, options)
+    }
+
     pub fn encode_to_writer(&self, writer: &mut impl Write) -> anyhow::Result<()> {
         self.encode(writer)
     }
 
-    fn convert_simple_index_map(map: &IndexMap<String, ModSettingsValue>) -> Property {
-        let mut properties = IndexMap::with_capacity(map.len());
-        for (key, value) in map {
-            let prop_value = match value {
+    /// Decodes a file whose header is missing or unreadable, where the reader is positioned at the
+    /// root property and the version is assumed rather than read.
+    pub fn from_body_reader(
+        reader: &mut impl Read,
+        version: FactorioVersion,
+        options: &DecodeOptions,
+    ) -> anyhow::Result<Settings> {
+        let reader = &mut Tracked {
+            inner: reader,
+            position: &options.position,
+        };
+        Ok(Settings {
+            version,
+            properties: Property::decode_with(reader, options)?,
+        })
+    }
+
+    /// Converts a section's settings into the dictionary property that stores them. Taking owned
+    /// entries lets an owned `ModSettings` move its keys and strings into the tree rather than
+    /// cloning them.
+    fn convert_simple_index_map(
+        entries: impl ExactSizeIterator<Item = (String, ModSetting)>,
+    ) -> Property {
+        let mut properties = IndexMap::with_capacity(entries.len());
+        for (key, setting) in entries {
+            let prop_value = match setting.value {
                 ModSettingsValue::None => PropertyValue::None,
-                ModSettingsValue::Bool(b) => PropertyValue::Bool(*b),
-                ModSettingsValue::Double(f) => PropertyValue::Double(*f),
-                ModSettingsValue::String(s) => PropertyValue::String(s.clone()),
-                ModSettingsValue::Color { r, g, b, a } => {
-                    let mut color_map = IndexMap::with_capacity(4);
-                    color_map.insert(
-                        "r".to_owned(),
-                        Property {
-                            any_flag: false,
-                            value: PropertyValue::Double(*r),
-                        },
-                    );
-                    color_map.insert(
-                        "g".to_owned(),
-                        Property {
-                            any_flag: false,
-                            value: PropertyValue::Double(*g),
-                        },
-                    );
-                    color_map.insert(
-                        "b".to_owned(),
-                        Property {
-                            any_flag: false,
-                            value: PropertyValue::Double(*b),
-                        },
-                    );
-                    color_map.insert(
-                        "a".to_owned(),
-                        Property {
-                            any_flag: false,
-                            value: PropertyValue::Double(*a),
-                        },
-                    );
-                    PropertyValue::Dictionary(color_map)
-                }
-                ModSettingsValue::Integer(i) => PropertyValue::Integer(*i),
+                ModSettingsValue::Bool(b) => PropertyValue::Bool(b),
+                ModSettingsValue::Double(f) => PropertyValue::Double(f),
+                ModSettingsValue::String(s) => PropertyValue::String(s),
+                ModSettingsValue::Color { r, g, b, a } => PropertyValue::Dictionary(
+                    [("r", r), ("g", g), ("b", b), ("a", a)]
+                        .into_iter()
+                        .map(|(channel, value)| {
+                            (
+                                channel.to_owned(),
+                                Property {
+                                    any_flag: false,
+                                    value: PropertyValue::Double(value),
+                                },
+                            )
+                        })
+                        .collect(),
+                ),
+                ModSettingsValue::Integer(i) => PropertyValue::Integer(i),
             };
-            let mut inner_props_map = IndexMap::with_capacity(1);
+            let mut inner_props_map = IndexMap::with_capacity(1 + setting.extra.len());
+            let mut extra = setting.extra.into_iter();
+            inner_props_map.extend(extra.by_ref().take(setting.value_index));
             inner_props_map.insert(
                 "value".to_owned(),
                 Property {
-                    any_flag: false,
+                    any_flag: setting.any_flag.value,
                     value: prop_value,
                 },
             );
+            inner_props_map.extend(extra);
             properties.insert(
-                key.clone(),
+                key,
                 Property {
-                    any_flag: false,
+                    any_flag: setting.any_flag.setting,
                     value: PropertyValue::Dictionary(inner_props_map),
                 },
             );
@@ -247,15 +458,16 @@ impl Settings {
         }
     }
 
-    pub fn from_simple(simple: &ModSettings) -> Settings {
-        let startup_properties = Self::convert_simple_index_map(&simple.startup);
-        let runtime_properties = Self::convert_simple_index_map(&simple.runtime_global);
-        let runtime_per_user_properties = Self::convert_simple_index_map(&simple.runtime_per_user);
-
-        let mut root_map = IndexMap::new();
-        root_map.insert("startup".to_owned(), startup_properties);
-        root_map.insert("runtime-global".to_owned(), runtime_properties);
-        root_map.insert("runtime-per-user".to_owned(), runtime_per_user_properties);
+    fn from_simple_sections(
+        version: FactorioVersion,
+        startup: Property,
+        runtime_global: Property,
+        runtime_per_user: Property,
+    ) -> Settings {
+        let mut root_map = IndexMap::with_capacity(3);
+        root_map.insert("startup".to_owned(), startup);
+        root_map.insert("runtime-global".to_owned(), runtime_global);
+        root_map.insert("runtime-per-user".to_owned(), runtime_per_user);
 
         let root = Property {
             any_flag: false,
@@ -263,18 +475,64 @@ impl Settings {
         };
         Settings {
             properties: root,
-            version: simple.factorio_version,
+            version,
         }
     }
+
+    pub fn from_simple(simple: &ModSettings) -> Settings {
+        let convert = |map: &IndexMap<String, ModSetting>| {
+            Self::convert_simple_index_map(map.iter().map(|(k, v)| (k.clone(), v.clone())))
+        };
+        Self::from_simple_sections(
+            simple.factorio_version,
+            convert(&simple.startup),
+            convert(&simple.runtime_global),
+            convert(&simple.runtime_per_user),
+        )
+    }
+}
+
+/// Converts without cloning, for when the simplified settings are no longer needed
+impl From<ModSettings> for Settings {
+    fn from(simple: ModSettings) -> Self {
+        Self::from_simple_sections(
+            simple.factorio_version,
+            Self::convert_simple_index_map(simple.startup.into_iter()),
+            Self::convert_simple_index_map(simple.runtime_global.into_iter()),
+            Self::convert_simple_index_map(simple.runtime_per_user.into_iter()),
+        )
+    }
 }
 
 impl Codec for Settings {
     fn decode(input: &mut impl Read) -> anyhow::Result<Settings> {
-        let version = FactorioVersion::decode(input)?;
+        Self::decode_with(input, &DecodeOptions::default())
+    }
+
+    fn decode_with(input: &mut impl Read, options: &DecodeOptions) -> anyhow::Result<Settings> {
+        let input = &mut Tracked {
+            inner: input,
+            position: &options.position,
+        };
+        let offset = options.position.get();
+        let mut start = [0; 2];
+        input.read_exact(&mut start)?;
+        if start == [TYPE_DICTIONARY, 0] {
+            // Files from before 0.17 have no version header and begin directly with the root
+            // dictionary. A header starting this way would claim major version 5.
+            options.count("property header", offset);
+            let settings = Property::decode_body(TYPE_DICTIONARY, 0, input, options)?;
+            return Ok(Self {
+                version: FactorioVersion::LEGACY,
+                properties: settings,
+            });
+        }
+        let version = FactorioVersion::decode(&mut start.as_slice().chain(&mut *input))?;
         if input.read_u8()? != 0 {
             return Err(anyhow!("Byte at 0x8 should be false"));
         }
-        let settings = Property::decode(input)?;
+        options.count("file header", offset);
+        let settings = Property::decode_with(input, options)?;
         Ok(Self {
             version,
             properties: settings,
@@ -282,15 +540,109 @@ impl Codec for Settings {
     }
 
     fn encode(&self, writer: &mut impl Write) -> anyhow::Result<()> {
-        self.version.encode(writer)?;
-        writer.write_u8(0)?;
+        if self.version.has_header() {
+            self.version.encode(writer)?;
+            writer.write_u8(0)?;
+        } else if self.version != FactorioVersion::LEGACY {
+            // without a header the version can't be written, and would read back as LEGACY
+            return Err(anyhow!(
+                "Factorio {} predates the version header, so the file can't record it; use {} for a file without one",
+                self.version,
+                FactorioVersion::LEGACY
+            ));
+        }
         self.properties.encode(writer)?;
         Ok(())
     }
 }
 
-trait Codec: Sized {
+/// Whether data begins with a version header followed by the root dictionary, or with a
+/// headerless root dictionary
+pub fn starts_like_settings(data: &[u8]) -> bool {
+    data.starts_with(&[TYPE_DICTIONARY, 0]) || data.get(8..10) == Some(&[0, TYPE_DICTIONARY])
+}
+
+/// Whether data looks like an encoded settings file rather than text: it either starts like one,
+/// with a version header followed by the root dictionary or with a headerless root dictionary, or
+/// isn't valid UTF-8. A character cut off at the end is allowed, so this also works on the first
+/// bytes of a file.
+pub fn looks_encoded(data: &[u8]) -> bool {
+    starts_like_settings(data)
+        || std::str::from_utf8(data).is_err_and(|err| err.error_len().is_some())
+}
+
+/// Whether a settings file contains the setting, reading only as far as needed to find it.
+/// Properties before it are skipped over without being built.
+pub fn has_setting(input: &mut impl Read, section: &str, key: &str) -> anyhow::Result<bool> {
+    let mut start = [0; 2];
+    input.read_exact(&mut start)?;
+    if start != [TYPE_DICTIONARY, 0] {
+        // the rest of the version and the false byte at 0x8, then the root's type
+        skip_bytes(input, 7)?;
+        input.read_exact(&mut start)?;
+        if start[0] != TYPE_DICTIONARY {
+            return Err(anyhow!("Main properties is not a dictionary"));
+        }
+    }
+    for _ in 0..input.read_u32::<LE>()? {
+        if String::decode(input)? != section {
+            skip_property(input)?;
+            continue;
+        }
+        let mut section_start = [0; 2];
+        input.read_exact(&mut section_start)?;
+        if section_start[0] != TYPE_DICTIONARY {
+            return Err(anyhow!("{} settings is not a dictionary", section));
+        }
+        for _ in 0..input.read_u32::<LE>()? {
+            if String::decode(input)? == key {
+                return Ok(true);
+            }
+            skip_property(input)?;
+        }
+        return Ok(false);
+    }
+    Ok(false)
+}
+
+fn skip_bytes(input: &mut impl Read, count: u64) -> anyhow::Result<()> {
+    if std::io::copy(&mut input.take(count), &mut std::io::sink())? != count {
+        return Err(anyhow!("Input ended unexpectedly"));
+    }
+    Ok(())
+}
+
+fn skip_property(input: &mut impl Read) -> anyhow::Result<()> {
+    let vtype = input.read_u8()?;
+    input.read_u8()?;
+    match vtype {
+        TYPE_NONE => Ok(()),
+        TYPE_BOOL => skip_bytes(input, 1),
+        TYPE_DOUBLE | TYPE_INTEGER => skip_bytes(input, 8),
+        TYPE_STRING => {
+            if !loose_bool(input.read_u8()?) {
+                let length = read_optimized_u32(input, &LengthStats::default())?;
+                skip_bytes(input, length as u64)?;
+            }
+            Ok(())
+        }
+        TYPE_DICTIONARY | TYPE_LIST => {
+            for _ in 0..input.read_u32::<LE>()? {
+                String::decode(input)?;
+                skip_property(input)?;
+            }
+            Ok(())
+        }
+        other => Err(anyhow!("Unknown type: {:#x}", other)),
+    }
+}
+
+pub trait Codec: Sized {
     fn decode(reader: &mut impl Read) -> anyhow::Result<Self>;
+    /// Decodes with options, for types that contain strings. Other types ignore them.
+    fn decode_with(reader: &mut impl Read, _options: &DecodeOptions) -> anyhow::Result<Self> {
+        Self::decode(reader)
+    }
     fn encode(&self, writer: &mut impl Write) -> anyhow::Result<()>;
 }
 
@@ -321,13 +673,39 @@ impl Codec for f64 {
 
 impl Codec for String {
     fn decode(reader: &mut impl Read) -> anyhow::Result<Self> {
+        Self::decode_with(reader, &DecodeOptions::default())
+    }
+
+    fn decode_with(reader: &mut impl Read, options: &DecodeOptions) -> anyhow::Result<Self> {
+        let offset = options.position.get();
         let empty_byte = reader.read_u8()?;
         if !loose_bool(empty_byte) {
             // if not empty
-            let length = read_optimized_u32(reader)?;
-            let mut vec = vec![0; length as usize];
-            reader.read_exact(&mut vec[..])?;
-            Ok(String::from_utf8(vec)?)
+            let length = read_optimized_u32(reader, &options.lengths)?;
+            // read through `take` rather than into a buffer of the declared length, so a corrupt
+            // length can't cause a huge allocation
+            let mut vec = Vec::new();
+            reader.take(length as u64).read_to_end(&mut vec)?;
+            if vec.len() < length as usize {
+                return Err(anyhow!(
+                    "String at offset {} claims {} bytes but only {} remain",
+                    offset,
+                    length,
+                    vec.len()
+                ));
+            }
+            match (String::from_utf8(vec), options.strings) {
+                (Ok(string), _) => Ok(string),
+                (Err(err), StringEncoding::Utf8) => Err(err.into()),
+                (Err(err), StringEncoding::Latin1) => {
+                    options.transcoded.set(options.transcoded.get() + 1);
+                    Ok(err.as_bytes().iter().map(|&b| b as char).collect())
+                }
+                (Err(err), StringEncoding::Lossy) => {
+                    options.transcoded.set(options.transcoded.get() + 1);
+                    Ok(String::from_utf8_lossy(err.as_bytes()).into_owned())
+                }
+            }
         } else {
             Ok(String::new())
         }
@@ -343,23 +721,54 @@ impl Codec for String {
     }
 }
 
-impl Codec for Vec<Property> {
-    fn decode(_reader: &mut impl Read) -> anyhow::Result<Self> {
-        todo!()
+/// Lists are stored like dictionaries, each item after its key
+impl Codec for Vec<ListItem> {
+    fn decode(reader: &mut impl Read) -> anyhow::Result<Self> {
+        Self::decode_with(reader, &DecodeOptions::default())
     }
 
-    fn encode(&self, _writer: &mut impl Write) -> anyhow::Result<()> {
-        todo!()
+    fn decode_with(reader: &mut impl Read, options: &DecodeOptions) -> anyhow::Result<Self> {
+        let start = options.position.get();
+        let count = reader.read_u32::<LE>()?;
+        options.count("list length", start);
+        let mut list = Vec::with_capacity((count as usize).min(MAX_PREALLOCATED_ENTRIES));
+        for _ in 0..count {
+            options.check_deadline()?;
+            let start = options.position.get();
+            let key = String::decode_with(reader, options)?;
+            options.count("list key", start);
+            let property = Property::decode_with(reader, options)?;
+            list.push(ListItem { key, property });
+        }
+        Ok(list)
+    }
+
+    fn encode(&self, writer: &mut impl Write) -> anyhow::Result<()> {
+        writer.write_u32::<LE>(self.len() as u32)?;
+        for item in self {
+            item.key.encode(writer)?;
+            item.property.encode(writer)?;
+        }
+        Ok(())
     }
 }
 
 impl Codec for IndexMap<String, Property> {
     fn decode(reader: &mut impl Read) -> anyhow::Result<Self> {
+        Self::decode_with(reader, &DecodeOptions::default())
+    }
+
+    fn decode_with(reader: &mut impl Read, options: &DecodeOptions) -> anyhow::Result<Self> {
+        let start = options.position.get();
         let count = reader.read_u32::<LE>()?;
-        let mut map = IndexMap::with_capacity(count as usize);
+        options.count("dictionary length", start);
+        let mut map = IndexMap::with_capacity((count as usize).min(MAX_PREALLOCATED_ENTRIES));
         for _ in 0..count {
-            let name = String::decode(reader)?;
-            let value = Property::decode(reader)?;
+            options.check_deadline()?;
+            let start = options.position.get();
+            let name = String::decode_with(reader, options)?;
+            options.count("dictionary key", start);
+            let value = Property::decode_with(reader, options)?;
             map.insert(name, value);
         }
         Ok(map)
@@ -397,11 +806,13 @@ const fn loose_bool_byte(input: bool) -> u8 {
 }
 
 #[inline]
-fn read_optimized_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
-    Ok(match reader.read_u8()? {
-        0xff => reader.read_u32::<LE>()?,
-        byte => byte as u32,
-    })
+fn read_optimized_u32(reader: &mut impl Read, stats: &LengthStats) -> anyhow::Result<u32> {
+    let (long, value) = match reader.read_u8()? {
+        0xff => (true, reader.read_u32::<LE>()?),
+        byte => (false, byte as u32),
+    };
+    stats.record(long, value);
+    Ok(value)
 }
 
 #[inline]
@@ -417,14 +828,212 @@ fn write_optimized_u32(writer: &mut impl Write, value: u32) -> anyhow::Result<()
 
 #[cfg(test)]
 mod tests {
-    use super::{Codec, Property, PropertyValue, Settings};
-    use crate::simple::ModSettings;
+    use super::{
+        has_setting, looks_encoded, Codec, DecodeOptions, Property, PropertyValue, Settings,
+        StringEncoding, TYPE_DICTIONARY,
+    };
+    use crate::simple::{AnyFlags, ModSetting, ModSettings, ModSettingsValue};
     use crate::types::FactorioVersion;
     use hex_literal::hex;
     use indexmap::IndexMap;
     use std::fs::File;
     use std::io::{BufReader, Cursor, Read};
     use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn non_dictionary_root() {
+        let data = hex!("01 00 01 00 52 00 04 00 00 03 00 00 03 61 62 63");
+        let settings = Settings::from_reader(&mut &data[..]).expect("decoding");
+        assert_eq!(
+            settings.properties.value,
+            PropertyValue::String("abc".to_owned())
+        );
+        let mut encoded = Vec::new();
+        settings.encode_to_writer(&mut encoded).expect("encoding");
+        assert_eq!(encoded, data);
+
+        let json = serde_json::to_string(&settings).expect("serializing");
+        let read_back: Settings = serde_json::from_str(&json).expect("deserializing");
+        assert_eq!(read_back.properties, settings.properties);
+
+        let err = ModSettings::try_from(&settings).expect_err("not simple settings");
+        assert!(
+            err.to_string().contains("not a dictionary but a string"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn truncated_string() {
+        let data = hex!(
+            "01 00 01 00 52 00 04 00 00"
+            "05 00 01 00 00 00"
+            "00 07 73 74 61 72 74 75 70"
+            "03 00 00 0a 64 65 61 64"
+        );
+        let err = Settings::from_reader(&mut &data[..]).expect_err("truncated");
+        assert_eq!(
+            err.to_string(),
+            "String at offset 26 claims 10 bytes but only 4 remain"
+        );
+
+        // offsets count from where decoding began
+        let options = DecodeOptions {
+            position: 9.into(),
+            ..Default::default()
+        };
+        let err = Settings::from_body_reader(
+            &mut &data[9..],
+            crate::types::FactorioVersion::new(1, 1, 82, 4),
+            &options,
+        )
+        .expect_err("truncated");
+        assert!(
+            err.to_string().starts_with("String at offset 26 "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn string_encodings() {
+        // "caf\xe9" in Latin-1
+        let data = hex!("00 04 63 61 66 e9");
+        assert!(String::decode(&mut Cursor::new(data)).is_err());
+        let decode = |strings| {
+            let options = DecodeOptions {
+                strings,
+                ..Default::default()
+            };
+            let string = String::decode_with(&mut Cursor::new(data), &options).expect("decoding");
+            (string, options.transcoded.get())
+        };
+        assert_eq!(decode(StringEncoding::Latin1), ("caf\u{e9}".to_owned(), 1));
+        assert_eq!(decode(StringEncoding::Lossy), ("caf\u{fffd}".to_owned(), 1));
+
+        let utf8 = hex!("00 05 63 61 66 c3 a9");
+        let options = DecodeOptions {
+            strings: StringEncoding::Latin1,
+            ..Default::default()
+        };
+        assert_eq!(
+            String::decode_with(&mut Cursor::new(utf8), &options).expect("decoding"),
+            "caf\u{e9}"
+        );
+        assert_eq!(options.transcoded.get(), 0);
+    }
+
+    #[test]
+    fn max_depth() {
+        let options = DecodeOptions::default();
+        let root = hex!("01 00 01 00 52 00 04 00 00 03 00 00 03 61 62 63");
+        Settings::from_reader_with(&mut &root[..], &options).expect("decoding");
+        assert_eq!(options.max_depth.get(), 1);
+
+        // a dictionary of two dictionaries, one holding an empty dictionary and one a bool
+        let nested = hex!(
+            "05 00 02 00 00 00 00 01 61 05 00 01 00 00 00 00 01 62 05 00 00 00 00 00"
+            "00 01 63 05 00 01 00 00 00 00 01 64 01 00 01"
+        );
+        let options = DecodeOptions::default();
+        Property::decode_with(&mut &nested[..], &options).expect("decoding");
+        assert_eq!(options.max_depth.get(), 3);
+        assert_eq!(options.depth.get(), 0);
+    }
+
+    #[test]
+    fn deadline() {
+        let data = std::fs::read("test_data/complex-settings.dat").expect("reading file");
+        let passed = DecodeOptions {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let err = Settings::from_reader_with(&mut data.as_slice(), &passed).expect_err("late");
+        assert_eq!(err.to_string(), "Decoding timed out at offset 15");
+
+        let ahead = DecodeOptions {
+            deadline: Some(Instant::now() + Duration::from_secs(60)),
+            ..Default::default()
+        };
+        Settings::from_reader_with(&mut data.as_slice(), &ahead).expect("decoding in time");
+    }
+
+    #[test]
+    fn length_stats() {
+        let options = DecodeOptions::default();
+        let short = hex!("00 01 61");
+        let long = hex!("00 ff 01 00 00 00 61");
+        let mut long_string = vec![0x00, 0xff, 0xff, 0x00, 0x00, 0x00];
+        long_string.extend([0x61; 0xff]);
+        for data in [&short[..], &long[..], &long_string[..]] {
+            String::decode_with(&mut Cursor::new(data), &options).expect("decoding");
+        }
+        assert_eq!(options.lengths.short.get(), 1);
+        assert_eq!(options.lengths.long.get(), 2);
+        assert_eq!(options.lengths.long_but_short.get(), 1);
+
+        // the encoder switches forms at the same threshold
+        let mut encoded = Vec::new();
+        "a".repeat(0xfe).encode(&mut encoded).expect("encoding");
+        assert_eq!(encoded[1], 0xfe);
+        encoded.clear();
+        "a".repeat(0xff).encode(&mut encoded).expect("encoding");
+        assert_eq!(encoded[1..6], [0xff, 0xff, 0, 0, 0]);
+    }
+
+    #[test]
+    fn recognizes_encoded_data() {
+        for path in [
+            "test_data/complex-settings.dat",
+            "test_data/list-settings.dat",
+            "test_data/settings-2.0.dat",
+        ] {
+            assert!(
+                looks_encoded(&std::fs::read(path).expect("reading")),
+                "{}",
+                path
+            );
+        }
+        assert!(looks_encoded(&[TYPE_DICTIONARY, 0, 0, 0, 0, 0]));
+        assert!(looks_encoded(b"{\"key\": \"\xff\"}"));
+        assert!(!looks_encoded(b"{}"));
+        assert!(!looks_encoded(b"[startup]\nkey = 1\n"));
+        assert!(!looks_encoded(b""));
+    }
+
+    #[test]
+    fn has_setting_streams() {
+        let data = std::fs::read("test_data/complex-settings.dat").expect("reading file");
+        let settings = ModSettings::try_from(
+            &Settings::decode(&mut Cursor::new(&data)).expect("decoding settings"),
+        )
+        .expect("simplifying");
+        for (section, map) in settings.sections() {
+            for key in map.keys().step_by(25) {
+                assert!(has_setting(&mut Cursor::new(&data), section, key).expect("scanning"));
+            }
+            assert!(
+                !has_setting(&mut Cursor::new(&data), section, "no-such-key").expect("scanning")
+            );
+        }
+        assert!(!has_setting(&mut Cursor::new(&data), "settings", "a").expect("scanning"));
+
+        // stops reading once the first key is found
+        let mut cursor = Cursor::new(&data);
+        let (section, map) = settings.sections()[0];
+        let first = map.keys().next().expect("a startup setting");
+        assert!(has_setting(&mut cursor, section, first).expect("scanning"));
+        assert!((cursor.position() as usize) < data.len() / 10);
+    }
+
+    #[test]
+    fn has_setting_in_legacy_file() {
+        let data = hex!("05 00 01 00 00 00 00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 00 01 61 05 00 00 00 00 00");
+        assert!(has_setting(&mut Cursor::new(data), "startup", "a").expect("scanning"));
+        assert!(!has_setting(&mut Cursor::new(data), "startup", "b").expect("scanning"));
+    }
 
     #[test]
     fn simple_encoded() {
@@ -433,12 +1042,7 @@ mod tests {
         let settings = Settings::decode(&mut cursor).expect("decoding settings");
         assert_eq!(
             settings.version,
-            FactorioVersion {
-                major: 1,
-                minor: 1,
-                patch: 82,
-                build: 4
-            },
+            FactorioVersion::new(1, 1, 82, 4),
             "version"
         );
         assert!(!settings.properties.any_flag, "should be false");
@@ -457,6 +1061,163 @@ mod tests {
         }
     }
 
+    #[test]
+    fn legacy_headerless() {
+        // the simple_encoded file without its version header, as written before 0.17
+        let data = hex!("05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 00 11 6D 79 2D 73 74 72 69 6E 67 2D 73 65 74 74 69 6E 67 05 00 01 00 00 00 00 05 76 61 6C 75 65 03 00 00 08 64 65 61 64 62 65 65 66 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00");
+        let settings = Settings::decode(&mut Cursor::new(data)).expect("decoding settings");
+        assert_eq!(settings.version, FactorioVersion::LEGACY, "version");
+        let simple = ModSettings::try_from(&settings).expect("to modsettings");
+        assert_eq!(simple.startup.len(), 1);
+
+        let mut encoded = Vec::new();
+        settings.encode(&mut encoded).expect("encoding settings");
+        assert_eq!(
+            &data[..],
+            &encoded[..],
+            "legacy files re-encode without a header"
+        );
+
+        let older = Settings {
+            version: FactorioVersion::new(0, 16, 51, 0),
+            ..settings
+        };
+        let err = older
+            .encode(&mut Vec::new())
+            .expect_err("unrecordable version");
+        assert!(
+            err.to_string().contains("predates the version header"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn headered_0_17_decodes_identically() {
+        let data = hex!("00 00 11 00 4F 00 00 00 00 05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 00 11 6D 79 2D 73 74 72 69 6E 67 2D 73 65 74 74 69 6E 67 05 00 01 00 00 00 00 05 76 61 6C 75 65 03 00 00 08 64 65 61 64 62 65 65 66 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00");
+        let settings = Settings::decode(&mut Cursor::new(data)).expect("decoding settings");
+        assert_eq!(
+            settings.version,
+            FactorioVersion::new(0, 17, 79, 0),
+            "version"
+        );
+        let simple = ModSettings::try_from(&settings).expect("to modsettings");
+        assert_eq!(simple.startup.len(), 1);
+
+        let mut encoded = Vec::new();
+        settings.encode(&mut encoded).expect("encoding settings");
+        assert_eq!(&data[..], &encoded[..]);
+    }
+
+    #[test]
+    fn nested_empty_dictionaries() {
+        let data = hex!("01 00 01 00 52 00 04 00 00 05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 02 00 00 00 00 01 61 05 00 01 00 00 00 00 05 76 61 6C 75 65 05 00 00 00 00 00 00 01 62 05 00 00 00 00 00 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 01 00 00 00 00 01 63 05 00 01 00 00 00 00 05 76 61 6C 75 65 05 00 01 00 00 00 00 06 6E 65 73 74 65 64 05 00 00 00 00 00");
+        let settings = Settings::decode(&mut Cursor::new(data)).expect("decoding settings");
+        let root = get_map(&settings.properties);
+        let startup = get_map(root.get("startup").expect("missing startup"));
+        let a = get_map(startup.get("a").expect("missing a"));
+        assert!(get_map(a.get("value").expect("missing value")).is_empty());
+        assert!(get_map(startup.get("b").expect("missing b")).is_empty());
+        assert!(get_map(root.get("runtime-global").expect("missing runtime-global")).is_empty());
+        let per_user = get_map(
+            root.get("runtime-per-user")
+                .expect("missing runtime-per-user"),
+        );
+        let c = get_map(per_user.get("c").expect("missing c"));
+        let c_value = get_map(c.get("value").expect("missing value"));
+        assert!(get_map(c_value.get("nested").expect("missing nested")).is_empty());
+
+        let mut encoded = Vec::new();
+        settings.encode(&mut encoded).expect("encoding settings");
+        assert_eq!(&data[..], &encoded[..]);
+    }
+
+    #[test]
+    fn empty_root_dictionary() {
+        let data = hex!("01 00 01 00 52 00 04 00 00 05 00 00 00 00 00");
+        let settings = Settings::decode(&mut Cursor::new(data)).expect("decoding settings");
+        assert!(get_map(&settings.properties).is_empty());
+        let mut encoded = Vec::new();
+        settings.encode(&mut encoded).expect("encoding settings");
+        assert_eq!(&data[..], &encoded[..]);
+    }
+
+    #[test]
+    fn oversized_dictionary_length_fails_cleanly() {
+        // claims u32::MAX entries but ends immediately
+        let data = hex!("01 00 01 00 52 00 04 00 00 05 00 FF FF FF FF");
+        assert!(Settings::decode(&mut Cursor::new(data)).is_err());
+    }
+
+    #[test]
+    fn body_with_assumed_version() {
+        let data = hex!("05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 00 00 00 00 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00");
+        let version = "1.1.82.4".parse().expect("parsing version");
+        let settings =
+            Settings::from_body_reader(&mut Cursor::new(data), version, &Default::default())
+                .expect("decoding body");
+        assert_eq!(settings.version, version);
+        ModSettings::try_from(&settings).expect("to modsettings");
+    }
+
+    #[test]
+    fn owned_and_borrowed_conversion_match() {
+        let mut reader =
+            BufReader::new(File::open("test_data/complex-settings.dat").expect("opening file"));
+        let settings = Settings::decode(&mut reader).expect("decoding settings");
+        let simple = ModSettings::try_from(&settings).expect("to modsettings");
+        let mut borrowed = Vec::new();
+        Settings::from_simple(&simple)
+            .encode(&mut borrowed)
+            .expect("encoding borrowed");
+        let mut owned = Vec::new();
+        Settings::from(simple)
+            .encode(&mut owned)
+            .expect("encoding owned");
+        assert_eq!(borrowed, owned);
+    }
+
+    #[test]
+    fn raw_tree_serde_round_trip() {
+        let data = std::fs::read("test_data/settings-2.0.dat").expect("reading file");
+        let settings = Settings::decode(&mut Cursor::new(&data)).expect("decoding settings");
+        let json = serde_json::to_string(&settings).expect("serializing json");
+        let toml = toml::to_string(&settings).expect("serializing toml");
+        for deserialized in [
+            serde_json::from_str::<Settings>(&json).expect("deserializing json"),
+            toml::from_str::<Settings>(&toml).expect("deserializing toml"),
+        ] {
+            let mut encoded = Vec::new();
+            deserialized
+                .encode(&mut encoded)
+                .expect("encoding settings");
+            assert_eq!(data, encoded);
+        }
+    }
+
+    #[test]
+    fn raw_tree_serialized_shape() {
+        let property = Property {
+            any_flag: true,
+            value: PropertyValue::Integer(3),
+        };
+        assert_eq!(
+            serde_json::to_string(&property).expect("serializing"),
+            r#"{"any_flag":true,"type":"integer","value":3}"#
+        );
+        let property = Property {
+            any_flag: false,
+            value: PropertyValue::None,
+        };
+        assert_eq!(
+            serde_json::to_string(&property).expect("serializing"),
+            r#"{"type":"none"}"#
+        );
+        let none: Property = serde_json::from_str(r#"{"type":"none"}"#).expect("deserializing");
+        assert!(matches!(none.value, PropertyValue::None));
+        assert!(!none.any_flag);
+    }
+
     #[test]
     fn complex() {
         let mut reader =
@@ -474,6 +1235,58 @@ mod tests {
         decode_encode_parity("test_data/settings-2.0.dat");
     }
 
+    #[test]
+    fn decode_encode_parity_lists() {
+        decode_encode_parity("test_data/list-settings.dat");
+    }
+
+    #[test]
+    fn lists() {
+        let data = std::fs::read("test_data/list-settings.dat").expect("reading file");
+        let settings = Settings::decode(&mut Cursor::new(&data)).expect("decoding settings");
+        let simple = ModSettings::try_from(&settings).expect("to modsettings");
+        let history = simple.startup["list-mod-recent-items"].extra["history"]
+            .value
+            .as_list()
+            .expect("a list");
+        assert_eq!(
+            history
+                .iter()
+                .map(|item| &item.property.value)
+                .collect::<Vec<_>>(),
+            [
+                &PropertyValue::String("iron-plate".to_owned()),
+                &PropertyValue::String("copper-plate".to_owned()),
+                &PropertyValue::Integer(3),
+            ]
+        );
+        // the second item has a key, which is kept
+        let layers = simple.runtime_per_user["list-mod-nested"].extra["layers"]
+            .value
+            .as_list()
+            .expect("a list");
+        assert_eq!(
+            layers
+                .iter()
+                .map(|item| item.key.as_str())
+                .collect::<Vec<_>>(),
+            ["", "top"]
+        );
+
+        let mut encoded = Vec::new();
+        Settings::from(simple)
+            .encode(&mut encoded)
+            .expect("encoding settings");
+        assert_eq!(encoded, data);
+        // scanning for a setting skips over the lists before it
+        assert!(has_setting(
+            &mut Cursor::new(&data),
+            "runtime-per-user",
+            "list-mod-nested"
+        )
+        .expect("scanning"));
+    }
+
     fn decode_encode_parity(file: impl AsRef<Path>) {
         let mut reader = BufReader::new(File::open(file).expect("opening file"));
         let data = {
@@ -501,6 +1314,89 @@ mod tests {
         ModSettings::try_from(&set).expect("to modsettings");
     }
 
+    /// A xorshift generator, so the round trip below sees the same cases every run
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn flag(&mut self) -> bool {
+            self.below(2) == 1
+        }
+
+        fn double(&mut self) -> f64 {
+            let f = f64::from_bits(self.next());
+            if f.is_finite() {
+                f
+            } else {
+                self.below(1000) as f64 / 8.0
+            }
+        }
+    }
+
+    fn random_settings(rng: &mut Rng) -> ModSettings {
+        let mut settings = ModSettings::empty(FactorioVersion::new(2, 0, 28, 0));
+        for (_, section) in settings.sections_mut() {
+            for i in 0..rng.below(5) {
+                let value = match rng.below(6) {
+                    0 => ModSettingsValue::None,
+                    1 => ModSettingsValue::Bool(rng.flag()),
+                    2 => ModSettingsValue::Double(rng.double()),
+                    3 => ModSettingsValue::String(format!("value-{}", rng.next())),
+                    4 => ModSettingsValue::Color {
+                        r: rng.double(),
+                        g: rng.double(),
+                        b: rng.double(),
+                        a: rng.double(),
+                    },
+                    _ => ModSettingsValue::Integer(rng.next() as i64),
+                };
+                let mut setting = ModSetting::from(value);
+                setting.any_flag = AnyFlags {
+                    setting: rng.flag(),
+                    value: rng.flag(),
+                };
+                section.insert(format!("setting-{}", i), setting);
+            }
+        }
+        settings
+    }
+
+    #[test]
+    fn any_flags_round_trip() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let simple = random_settings(&mut rng);
+            let mut encoded = Vec::new();
+            Settings::from_simple(&simple)
+                .encode_to_writer(&mut encoded)
+                .expect("encoding");
+            let decoded = Settings::from_reader(&mut &encoded[..]).expect("decoding");
+            let round_tripped = ModSettings::try_from(&decoded).expect("simplifying");
+            assert_eq!(round_tripped, simple);
+
+            let json = serde_json::to_string(&round_tripped).expect("serializing");
+            let from_json: ModSettings = serde_json::from_str(&json).expect("deserializing");
+            let flags = |settings: &ModSettings| {
+                settings
+                    .sections()
+                    .into_iter()
+                    .flat_map(|(_, map)| map.values().map(|setting| setting.any_flag))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(flags(&from_json), flags(&simple));
+        }
+    }
+
     fn get_map(prop: &Property) -> &IndexMap<String, Property> {
         match &prop.value {
             PropertyValue::Dictionary(map) => map,
@@ -0,0 +1,105 @@
+use crate::simple::ModSettings;
+use crate::types::FactorioVersion;
+
+/// Advisory findings about writing settings for a different Factorio version than the one that
+/// wrote them: a different `major.minor`, whose mods the target won't load, a newer version that
+/// may have settings from mod updates the target lacks, and a target too old for the version
+/// header.
+pub fn check(settings: &ModSettings, target: FactorioVersion) -> Vec<String> {
+    let source = settings.factorio_version;
+    let count = settings
+        .sections()
+        .iter()
+        .map(|(_, map)| map.len())
+        .sum::<usize>();
+    let mut findings = Vec::new();
+    if (source.major, source.minor) != (target.major, target.minor) {
+        findings.push(format!(
+            "the settings were written by Factorio {}, but mods are built for one major.minor version, \
+             so {} to mods that Factorio {} won't load",
+            source,
+            if count == 1 {
+                "the 1 setting belongs".to_owned()
+            } else {
+                format!("all {} settings belong", count)
+            },
+            target
+        ));
+    } else if source > target {
+        findings.push(format!(
+            "the settings were written by Factorio {}, newer than {}; settings added by mod \
+             updates since may not be recognized",
+            source, target
+        ));
+    }
+    if !target.has_header() && source.has_header() {
+        findings.push(format!(
+            "Factorio {} predates the version header, so the file is written without one",
+            target
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+    use indexmap::IndexMap;
+
+    fn settings(version: &str) -> ModSettings {
+        let mut startup = IndexMap::new();
+        startup.insert(
+            "tint".to_owned(),
+            ModSettingsValue::Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }
+            .into(),
+        );
+        ModSettings {
+            factorio_version: version.parse().expect("version"),
+            startup,
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        }
+    }
+
+    fn target(version: &str) -> FactorioVersion {
+        version.parse().expect("version")
+    }
+
+    #[test]
+    fn same_version_is_compatible() {
+        assert!(check(&settings("1.1.82"), target("1.1.82")).is_empty());
+        assert!(check(&settings("1.1.80"), target("1.1.82")).is_empty());
+    }
+
+    #[test]
+    fn newer_source() {
+        let findings = check(&settings("2.0.26"), target("1.1.110"));
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert!(
+            findings[0].contains("so the 1 setting belongs to mods"),
+            "{}",
+            findings[0]
+        );
+        let findings = check(&settings("1.1.110"), target("1.1.82"));
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert!(
+            findings[0].contains("newer than 1.1.82.0"),
+            "{}",
+            findings[0]
+        );
+    }
+
+    #[test]
+    fn old_targets() {
+        let findings = check(&settings("0.17.79"), target("0.16.51"));
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        assert!(findings[1].contains("without one"));
+    }
+}
@@ -0,0 +1,367 @@
+use crate::exit::IoError;
+use crate::simple::{ModSettings, ModSettingsValue, RUNTIME_GLOBAL, RUNTIME_PER_USER, STARTUP};
+use anyhow::Context;
+use indexmap::IndexMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Default values of settings, by setting name. Factorio requires setting names to be unique
+/// across all mods and sections, so the name alone identifies a setting.
+#[derive(Debug, Default)]
+pub struct Defaults {
+    pub values: IndexMap<String, serde_json::Value>,
+    /// Zipped mods that couldn't be read, with the reason
+    pub skipped: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl Defaults {
+    /// Reads the defaults declared in the `settings.lua` of each mod in a mods directory, whether
+    /// unpacked or zipped. Only literal defaults are understood; settings whose default is
+    /// computed are left out, and zipped mods that can't be read are listed in `skipped`.
+    pub fn from_mods_dir(dir: &Path) -> anyhow::Result<Defaults> {
+        let mut entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Listing {}", dir.display()))
+            .context(IoError("Reading mods directory"))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        let mut defaults = Defaults::default();
+        for path in entries {
+            let settings = path.join("settings.lua");
+            if settings.is_file() {
+                let text = std::fs::read(&settings)
+                    .with_context(|| format!("Reading {}", settings.display()))
+                    .context(IoError("Reading mod settings"))?;
+                defaults
+                    .values
+                    .extend(scan_settings_lua(&String::from_utf8_lossy(&text)));
+            } else if path.extension().is_some_and(|ext| ext == "zip") {
+                match read_zipped_settings(&path) {
+                    Ok(Some(text)) => defaults.values.extend(scan_settings_lua(&text)),
+                    Ok(None) => {}
+                    Err(err) => defaults.skipped.push((path, err)),
+                }
+            }
+        }
+        Ok(defaults)
+    }
+
+    /// Takes the values of a settings file as the defaults
+    pub fn from_settings(settings: &ModSettings) -> Defaults {
+        let values = settings
+            .sections()
+            .into_iter()
+            .flat_map(|(_, map)| map)
+            .filter_map(|(key, setting)| Some((key.clone(), value_json(&setting.value)?)))
+            .collect();
+        Defaults {
+            values,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Adds a `default` beside the value of each setting in serialized settings whose default is
+    /// known, returning how many settings have no known default
+    pub fn annotate(&self, settings: &mut serde_json::Value) -> usize {
+        let mut unknown = 0;
+        for name in [STARTUP, RUNTIME_GLOBAL, RUNTIME_PER_USER] {
+            let Some(section) = settings.get_mut(name).and_then(|s| s.as_object_mut()) else {
+                continue;
+            };
+            for (key, setting) in section {
+                match (self.values.get(key), setting.as_object_mut()) {
+                    (Some(default), Some(setting)) => {
+                        setting.insert("default".to_owned(), default.clone());
+                    }
+                    _ => unknown += 1,
+                }
+            }
+        }
+        unknown
+    }
+}
+
+/// The text of a zipped mod's `settings.lua`, or `None` if it has none
+fn read_zipped_settings(path: &Path) -> anyhow::Result<Option<String>> {
+    let file = std::fs::File::open(path).context(IoError("Opening zipped mod"))?;
+    let Some(mut entry) = crate::zip::open_mod_settings(std::io::BufReader::new(file))? else {
+        return Ok(None);
+    };
+    let mut text = Vec::new();
+    entry
+        .read_to_end(&mut text)
+        .context(IoError("Reading zipped mod"))?;
+    Ok(Some(String::from_utf8_lossy(&text).into_owned()))
+}
+
+/// A setting value as it appears under `value` in serialized settings
+fn value_json(value: &ModSettingsValue) -> Option<serde_json::Value> {
+    serde_json::to_value(value)
+        .ok()?
+        .get_mut("value")
+        .map(serde_json::Value::take)
+}
+
+/// A value written literally in Lua
+#[derive(Clone, Debug, PartialEq)]
+enum Literal {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(IndexMap<String, Literal>),
+    /// An expression this scanner doesn't evaluate
+    Computed,
+}
+
+impl Literal {
+    fn to_json(&self) -> Option<serde_json::Value> {
+        match self {
+            Literal::Bool(b) => Some((*b).into()),
+            Literal::Number(n) if n.fract() == 0.0 && n.abs() < 2f64.powi(53) => {
+                Some((*n as i64).into())
+            }
+            Literal::Number(n) => Some((*n).into()),
+            Literal::String(s) => Some(s.as_str().into()),
+            Literal::Table(fields) => {
+                // colors, the only table a setting's default can be
+                let channel = |name: &str, fallback: Option<f64>| match fields.get(name) {
+                    Some(Literal::Number(n)) => Some(*n),
+                    None => fallback,
+                    _ => None,
+                };
+                Some(serde_json::json!({
+                    "r": channel("r", None)?,
+                    "g": channel("g", None)?,
+                    "b": channel("b", None)?,
+                    "a": channel("a", Some(1.0))?,
+                }))
+            }
+            Literal::Computed => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Literal(Literal),
+    Equals,
+    Open,
+    Close,
+    Separator,
+    Other,
+}
+
+/// Splits Lua source into the tokens that matter for finding table fields, skipping comments
+fn tokenize(source: &str) -> Vec<Token> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        let rest = &chars[i..];
+        if rest.starts_with(&['-', '-']) {
+            // a long comment is closed by ]], anything else runs to the end of the line
+            let long = rest.get(2..4) == Some(&['[', '['][..]);
+            while i < chars.len() {
+                if long && chars[i..].starts_with(&[']', ']']) {
+                    i += 2;
+                    break;
+                }
+                if !long && chars[i] == '\n' {
+                    break;
+                }
+                i += 1;
+            }
+            continue;
+        }
+        i += 1;
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            ',' | ';' => tokens.push(Token::Separator),
+            '=' if chars.get(i) == Some(&'=') => {
+                i += 1;
+                tokens.push(Token::Other);
+            }
+            '=' => tokens.push(Token::Equals),
+            '"' | '\'' => {
+                let mut text = String::new();
+                while let Some(&next) = chars.get(i) {
+                    i += 1;
+                    match next {
+                        '\\' => {
+                            let escaped = chars.get(i).copied().unwrap_or('\\');
+                            i += 1;
+                            text.push(match escaped {
+                                'n' => '\n',
+                                't' => '\t',
+                                other => other,
+                            });
+                        }
+                        _ if next == c => break,
+                        _ => text.push(next),
+                    }
+                }
+                tokens.push(Token::Literal(Literal::String(text)));
+            }
+            c if c.is_ascii_digit()
+                || (c == '.' || (c == '-' && tokens.last() == Some(&Token::Equals)))
+                    && chars.get(i).is_some_and(char::is_ascii_digit) =>
+            {
+                let start = i - 1;
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.')
+                {
+                    i += 1;
+                }
+                let text = chars[start..i].iter().collect::<String>();
+                tokens.push(Token::Literal(match text.parse() {
+                    Ok(n) => Literal::Number(n),
+                    Err(_) => Literal::Computed,
+                }));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i - 1;
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    i += 1;
+                }
+                let name = chars[start..i].iter().collect::<String>();
+                tokens.push(match name.as_str() {
+                    "true" => Token::Literal(Literal::Bool(true)),
+                    "false" => Token::Literal(Literal::Bool(false)),
+                    _ => Token::Name(name),
+                });
+            }
+            c if c.is_whitespace() => {}
+            _ => tokens.push(Token::Other),
+        }
+    }
+    tokens
+}
+
+/// Finds the settings declared in a mod's `settings.lua`: tables with a string `name` and a
+/// literal `default_value`, wherever they appear.
+fn scan_settings_lua(source: &str) -> IndexMap<String, serde_json::Value> {
+    struct Table {
+        fields: IndexMap<String, Literal>,
+        /// The field of the enclosing table this table is the value of
+        field: Option<String>,
+    }
+    let mut found = IndexMap::new();
+    let mut stack: Vec<Table> = Vec::new();
+    let tokens = tokenize(source);
+    let mut i = 0;
+    while i < tokens.len() {
+        match (&tokens[i], tokens.get(i + 1), tokens.get(i + 2)) {
+            (Token::Name(field), Some(Token::Equals), Some(Token::Open)) => {
+                stack.push(Table {
+                    fields: IndexMap::new(),
+                    field: Some(field.clone()),
+                });
+                i += 3;
+                continue;
+            }
+            (Token::Name(field), Some(Token::Equals), value) => {
+                // a literal only counts when nothing else follows it, as in `60 * 60`
+                let literal = match (value, tokens.get(i + 3)) {
+                    (
+                        Some(Token::Literal(literal)),
+                        None | Some(Token::Separator | Token::Close),
+                    ) => literal.clone(),
+                    _ => Literal::Computed,
+                };
+                if let Some(table) = stack.last_mut() {
+                    table.fields.insert(field.clone(), literal);
+                }
+                i += 3;
+                continue;
+            }
+            (Token::Open, ..) => stack.push(Table {
+                fields: IndexMap::new(),
+                field: None,
+            }),
+            (Token::Close, ..) => {
+                if let Some(table) = stack.pop() {
+                    let name = table.fields.get("name");
+                    let default = table.fields.get("default_value").and_then(Literal::to_json);
+                    if let (Some(Literal::String(name)), Some(default)) = (name, default) {
+                        found.insert(name.clone(), default);
+                    }
+                    if let (Some(field), Some(parent)) = (table.field, stack.last_mut()) {
+                        parent.fields.insert(field, Literal::Table(table.fields));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan_settings_lua, Defaults};
+    use serde_json::json;
+
+    #[test]
+    fn scans_literal_defaults() {
+        let source = r#"
+            -- a comment with { braces
+            data:extend({
+                {
+                    type = "int-setting",
+                    name = "my-mod-count",
+                    setting_type = "startup",
+                    default_value = 3,
+                    minimum_value = -1,
+                    minimum_value = 1,
+                },
+                {type = "double-setting", name = "my-mod-rate", default_value = -0.5},
+                {type = "string-setting", name = 'my-mod-mode', default_value = "fast", allowed_values = {"fast", "slow"}},
+                {type = "bool-setting", name = "my-mod-on", default_value = false},
+                {type = "color-setting", name = "my-mod-tint", default_value = {r = 1, g = 0.5, b = 0}},
+                --[[ {type = "bool-setting", name = "commented-out", default_value = true}, ]]
+                {type = "int-setting", name = "my-mod-ticks", default_value = 60 * 60},
+                {type = "bool-setting", name = "my-mod-computed", default_value = settings_default},
+            })
+        "#;
+        let found = scan_settings_lua(source);
+        assert_eq!(
+            found.into_iter().collect::<Vec<_>>(),
+            [
+                ("my-mod-count".to_owned(), json!(3)),
+                ("my-mod-rate".to_owned(), json!(-0.5)),
+                ("my-mod-mode".to_owned(), json!("fast")),
+                ("my-mod-on".to_owned(), json!(false)),
+                (
+                    "my-mod-tint".to_owned(),
+                    json!({"r": 1.0, "g": 0.5, "b": 0.0, "a": 1.0})
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn annotates_known_defaults() {
+        let mut defaults = Defaults::default();
+        defaults.values.insert("a".to_owned(), json!(1));
+        let mut settings = json!({
+            "factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0},
+            "startup": {
+                "a": {"type": "Integer", "value": 2},
+                "b": {"type": "Integer", "value": 3},
+            },
+            "runtime-global": {},
+            "runtime-per-user": {},
+        });
+        assert_eq!(defaults.annotate(&mut settings), 1);
+        assert_eq!(settings["startup"]["a"]["default"], 1);
+        assert!(settings["startup"]["b"].get("default").is_none());
+    }
+}
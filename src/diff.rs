@@ -0,0 +1,405 @@
+use crate::setting::SettingPath;
+use crate::simple::{ModSetting, ModSettings, ModSettingsValue};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// How a single setting differs between two settings files
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(ModSetting),
+    Removed(ModSetting),
+    Changed { old: ModSetting, new: ModSetting },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub path: SettingPath,
+    pub change: Change,
+}
+
+/// The settings that differ from `old` to `new`, section by section. Within a section, changed
+/// and removed settings come in the order of `old`, followed by added settings in the order of
+/// `new`. Reordering alone isn't a difference.
+pub fn diff(old: &ModSettings, new: &ModSettings) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    for ((section, old), (_, new)) in old.sections().into_iter().zip(new.sections()) {
+        let path = |key: &String| SettingPath {
+            section,
+            key: key.clone(),
+        };
+        for (key, old_setting) in old {
+            let change = match new.get(key) {
+                None => Change::Removed(old_setting.clone()),
+                Some(new_setting) if new_setting != old_setting => Change::Changed {
+                    old: old_setting.clone(),
+                    new: new_setting.clone(),
+                },
+                Some(_) => continue,
+            };
+            differences.push(Difference {
+                path: path(key),
+                change,
+            });
+        }
+        for (key, new_setting) in new {
+            if !old.contains_key(key) {
+                differences.push(Difference {
+                    path: path(key),
+                    change: Change::Added(new_setting.clone()),
+                });
+            }
+        }
+    }
+    differences
+}
+
+/// One step of a patch that turns one settings file into another
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Adds the setting, or replaces it if present
+    Set {
+        path: String,
+        setting: ModSetting,
+    },
+    Remove {
+        path: String,
+    },
+}
+
+/// The differences as patch operations, which serialize as the `json` diff format
+pub fn patch(differences: &[Difference]) -> Vec<PatchOp> {
+    differences
+        .iter()
+        .map(|difference| {
+            let path = difference.path.to_string();
+            match &difference.change {
+                Change::Added(setting) | Change::Changed { new: setting, .. } => PatchOp::Set {
+                    path,
+                    setting: setting.clone(),
+                },
+                Change::Removed(_) => PatchOp::Remove { path },
+            }
+        })
+        .collect()
+}
+
+/// Writes the differences as a changelog, one sentence per setting
+pub fn write_summary(differences: &[Difference], writer: &mut impl Write) -> std::io::Result<()> {
+    if differences.is_empty() {
+        return writeln!(writer, "No differences");
+    }
+    for difference in differences {
+        match &difference.change {
+            Change::Added(setting) => {
+                writeln!(writer, "Added {} = {}", difference.path, describe(setting))?
+            }
+            Change::Removed(setting) => writeln!(
+                writer,
+                "Removed {} (was {})",
+                difference.path,
+                describe(setting)
+            )?,
+            Change::Changed { old, new } if old.value.type_name() != new.value.type_name() => {
+                writeln!(
+                    writer,
+                    "Changed {} from {} ({}) to {} ({})",
+                    difference.path,
+                    describe(old),
+                    old.value.type_name(),
+                    describe(new),
+                    new.value.type_name()
+                )?
+            }
+            Change::Changed { old, new } => writeln!(
+                writer,
+                "Changed {} from {} to {}",
+                difference.path,
+                describe(old),
+                describe(new)
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// A setting's value written the way it's given on the command line
+pub fn describe(setting: &ModSetting) -> String {
+    let value = match &setting.value {
+        ModSettingsValue::None => "none".to_owned(),
+        ModSettingsValue::Bool(b) => b.to_string(),
+        ModSettingsValue::Double(f) => format!("{:?}", f),
+        ModSettingsValue::String(s) => format!("{:?}", s),
+        ModSettingsValue::Color { r, g, b, a } => format!("color({}, {}, {}, {})", r, g, b, a),
+        ModSettingsValue::Integer(i) => i.to_string(),
+    };
+    if setting.extra.is_empty() {
+        value
+    } else {
+        format!("{} with {} extra properties", value, setting.extra.len())
+    }
+}
+
+/// A line of a textual diff
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Pairs up the lines of `old` and `new` with a shortest edit script, using Myers' algorithm
+fn line_diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    // v[k + max] is the furthest x reached on diagonal k = x - y
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let index = |k: isize| (k + max + 1) as usize;
+    // the state of v after each step d, for diagonals -d..=d
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                trace.push(v[index(-d)..=index(d)].to_vec());
+                break 'search;
+            }
+        }
+        trace.push(v[index(-d)..=index(d)].to_vec());
+    }
+
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize).rev() {
+        let k = x - y;
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let previous = &trace[d as usize - 1];
+            let at = |k: isize| previous[(k + d - 1) as usize];
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            (at(prev_k), at(prev_k) - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            lines.push(Line::Same(old[x as usize - 1]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                lines.push(Line::Added(new[y as usize - 1]));
+            } else {
+                lines.push(Line::Removed(old[x as usize - 1]));
+            }
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    lines.reverse();
+    lines
+}
+
+/// Writes a unified diff of two texts, like `diff -u` or `git diff`
+pub fn write_unified(
+    old: &str,
+    new: &str,
+    labels: (&str, &str),
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    const CONTEXT: usize = 3;
+    let old_lines = old.lines().collect::<Vec<_>>();
+    let new_lines = new.lines().collect::<Vec<_>>();
+    let lines = line_diff(&old_lines, &new_lines);
+    let changes = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return Ok(());
+    }
+    writeln!(writer, "--- {}", labels.0)?;
+    writeln!(writer, "+++ {}", labels.1)?;
+
+    // the line numbers in each text at which each diff line starts
+    let mut positions = Vec::with_capacity(lines.len() + 1);
+    let (mut old_line, mut new_line) = (0, 0);
+    for line in &lines {
+        positions.push((old_line, new_line));
+        match line {
+            Line::Same(_) => (old_line, new_line) = (old_line + 1, new_line + 1),
+            Line::Removed(_) => old_line += 1,
+            Line::Added(_) => new_line += 1,
+        }
+    }
+    positions.push((old_line, new_line));
+
+    let mut changes = changes.into_iter().peekable();
+    while let Some(first) = changes.next() {
+        let mut last = first;
+        // join changes whose context would overlap into one hunk
+        while let Some(&next) = changes.peek() {
+            if next - last > 2 * CONTEXT {
+                break;
+            }
+            last = next;
+            changes.next();
+        }
+        let start = first.saturating_sub(CONTEXT);
+        let end = (last + CONTEXT + 1).min(lines.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let range = |start: usize, len: usize| {
+            // an empty range is numbered by the line before it
+            if len == 0 {
+                format!("{},0", start)
+            } else {
+                format!("{},{}", start + 1, len)
+            }
+        };
+        writeln!(
+            writer,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        )?;
+        for line in &lines[start..end] {
+            match line {
+                Line::Same(text) => writeln!(writer, " {}", text)?,
+                Line::Removed(text) => writeln!(writer, "-{}", text)?,
+                Line::Added(text) => writeln!(writer, "+{}", text)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, line_diff, patch, write_summary, write_unified, Line, PatchOp};
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+
+    fn settings(startup: &[(&str, ModSettingsValue)]) -> ModSettings {
+        ModSettings {
+            factorio_version: FactorioVersion::FIRST_WITH_HEADER,
+            startup: startup
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone().into()))
+                .collect(),
+            runtime_global: Default::default(),
+            runtime_per_user: Default::default(),
+        }
+    }
+
+    #[test]
+    fn differences() {
+        let old = settings(&[
+            ("same", ModSettingsValue::Bool(true)),
+            ("changed", ModSettingsValue::Integer(1)),
+            ("removed", ModSettingsValue::String("x".to_owned())),
+            ("retyped", ModSettingsValue::Bool(true)),
+            ("widened", ModSettingsValue::Integer(1)),
+        ]);
+        let new = settings(&[
+            ("added", ModSettingsValue::Double(0.5)),
+            ("changed", ModSettingsValue::Integer(2)),
+            ("same", ModSettingsValue::Bool(true)),
+            ("retyped", ModSettingsValue::Integer(1)),
+            ("widened", ModSettingsValue::Double(1.0)),
+        ]);
+        let differences = diff(&old, &new);
+        let mut summary = Vec::new();
+        write_summary(&differences, &mut summary).expect("writing");
+        assert_eq!(
+            String::from_utf8(summary).unwrap(),
+            "Changed startup/changed from 1 to 2\n\
+             Removed startup/removed (was \"x\")\n\
+             Changed startup/retyped from true (bool) to 1 (integer)\n\
+             Changed startup/widened from 1 (integer) to 1.0 (double)\n\
+             Added startup/added = 0.5\n"
+        );
+        assert!(diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn patch_round_trips_through_json() {
+        let old = settings(&[("a", ModSettingsValue::Bool(true))]);
+        let new = settings(&[("b", ModSettingsValue::Bool(false))]);
+        let ops = patch(&diff(&old, &new));
+        let json = serde_json::to_string(&ops).expect("serializing");
+        assert_eq!(
+            json,
+            r#"[{"op":"remove","path":"startup/a"},{"op":"set","path":"startup/b","setting":{"type":"Bool","value":false}}]"#
+        );
+        let parsed: Vec<PatchOp> = serde_json::from_str(&json).expect("deserializing");
+        assert_eq!(parsed, ops);
+    }
+
+    #[test]
+    fn shortest_line_diff() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let lines = line_diff(&old, &new);
+        let edits = lines
+            .iter()
+            .filter(|line| !matches!(line, Line::Same(_)))
+            .count();
+        assert_eq!(edits, 5);
+        let rebuilt_old = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Same(s) | Line::Removed(s) => Some(*s),
+                Line::Added(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let rebuilt_new = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Same(s) | Line::Added(s) => Some(*s),
+                Line::Removed(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rebuilt_old, old);
+        assert_eq!(rebuilt_new, new);
+        assert!(line_diff(&[], &[]).is_empty());
+        assert_eq!(line_diff(&[], &["a"]), [Line::Added("a")]);
+    }
+
+    #[test]
+    fn unified_hunks() {
+        let old = (1..=20).map(|i| format!("{}\n", i)).collect::<String>();
+        let new = (1..=20)
+            .filter(|i| *i != 18)
+            .map(|i| match i {
+                2 => "two\n".to_owned(),
+                i => format!("{}\n", i),
+            })
+            .collect::<String>();
+        let mut output = Vec::new();
+        write_unified(&old, &new, ("a", "b"), &mut output).expect("writing");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "--- a\n+++ b\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -15,6 +15,5 @@\n 15\n 16\n 17\n-18\n 19\n 20\n"
+        );
+        let mut output = Vec::new();
+        write_unified(&old, &old, ("a", "b"), &mut output).expect("writing");
+        assert!(output.is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::codec::{Property, PropertyValue};
+use std::fmt::Write;
+
+/// Renders a property tree as a GraphViz DOT graph, with a node per property labeled with its key
+/// and type, and an edge from each dictionary or list to the properties it holds. List items are
+/// keyed by their index.
+pub fn render(root: &Property) -> String {
+    let mut dot = String::from("digraph settings {\n");
+    dot.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+    let mut next_id = 0;
+    render_property(&mut dot, &mut next_id, None, "root", root);
+    dot.push_str("}\n");
+    dot
+}
+
+fn render_property(
+    dot: &mut String,
+    next_id: &mut usize,
+    parent: Option<usize>,
+    key: &str,
+    property: &Property,
+) {
+    let id = *next_id;
+    *next_id += 1;
+    let _ = writeln!(
+        dot,
+        "  n{} [label=\"{}: {}\"];",
+        id,
+        escape(key),
+        property.value.type_name()
+    );
+    if let Some(parent) = parent {
+        let _ = writeln!(dot, "  n{} -> n{};", parent, id);
+    }
+    match &property.value {
+        PropertyValue::Dictionary(dict) => {
+            for (key, child) in dict {
+                render_property(dot, next_id, Some(id), key, child);
+            }
+        }
+        PropertyValue::List(list) => {
+            for (index, item) in list.iter().enumerate() {
+                render_property(
+                    dot,
+                    next_id,
+                    Some(id),
+                    &format!("[{}]", index),
+                    &item.property,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Escapes text for a double-quoted DOT string. Backslashes start DOT's own label escapes, so
+/// they're doubled, and other control characters are written out so a label stays on its line.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\\\u{{{:x}}}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, render};
+    use crate::codec::{Property, PropertyValue};
+    use indexmap::IndexMap;
+
+    #[test]
+    fn nodes_and_edges() {
+        let property = |value| Property {
+            any_flag: false,
+            value,
+        };
+        let mut setting = IndexMap::new();
+        setting.insert("value".to_owned(), property(PropertyValue::Double(0.5)));
+        let mut section = IndexMap::new();
+        section.insert(
+            "my-setting".to_owned(),
+            property(PropertyValue::Dictionary(setting)),
+        );
+        let mut root = IndexMap::new();
+        root.insert(
+            "startup".to_owned(),
+            property(PropertyValue::Dictionary(section)),
+        );
+        root.insert(
+            "list".to_owned(),
+            property(PropertyValue::List(vec![
+                property(PropertyValue::None).into()
+            ])),
+        );
+        assert_eq!(
+            render(&property(PropertyValue::Dictionary(root))),
+            "digraph settings {\n\
+             \x20 node [shape=box, fontname=\"monospace\"];\n\
+             \x20 n0 [label=\"root: dictionary\"];\n\
+             \x20 n1 [label=\"startup: dictionary\"];\n\
+             \x20 n0 -> n1;\n\
+             \x20 n2 [label=\"my-setting: dictionary\"];\n\
+             \x20 n1 -> n2;\n\
+             \x20 n3 [label=\"value: double\"];\n\
+             \x20 n2 -> n3;\n\
+             \x20 n4 [label=\"list: list\"];\n\
+             \x20 n0 -> n4;\n\
+             \x20 n5 [label=\"[0]: none\"];\n\
+             \x20 n4 -> n5;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(escape(r#"a "b" \c"#), r#"a \"b\" \\c"#);
+        assert_eq!(escape("line\nbreak\r\t"), r"line\nbreak\\u{d}\\u{9}");
+        assert_eq!(escape("caf\u{e9}"), "caf\u{e9}");
+    }
+}
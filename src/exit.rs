@@ -0,0 +1,77 @@
+use std::fmt::{Display, Formatter};
+use std::process::ExitCode;
+
+/// The process exit codes. These are part of the command line contract and are asserted by the
+/// integration tests, so scripts can rely on them.
+///
+/// | Code | Meaning                                                      |
+/// |------|--------------------------------------------------------------|
+/// | 0    | Success                                                      |
+/// | 1    | Conversion error (malformed input, unsupported data, etc.)   |
+/// | 2    | Usage error (bad or ambiguous arguments)                     |
+/// | 3    | Differences found                                            |
+/// | 4    | I/O error (input unreadable, output unwritable)              |
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Status {
+    Success = 0,
+    ConversionError = 1,
+    UsageError = 2,
+    DiffFound = 3,
+    IoError = 4,
+}
+
+impl Status {
+    /// Classifies an error returned from the application into its exit status. Data found to be
+    /// malformed while reading it, such as damaged compressed input, is a conversion error even
+    /// though it surfaced as a read failing.
+    pub fn of(error: &anyhow::Error) -> Status {
+        let invalid_data = error.chain().any(|cause| {
+            cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::InvalidData)
+        });
+        if error.downcast_ref::<UsageError>().is_some() {
+            Status::UsageError
+        } else if invalid_data {
+            Status::ConversionError
+        } else if error.downcast_ref::<IoError>().is_some() {
+            Status::IoError
+        } else {
+            Status::ConversionError
+        }
+    }
+}
+
+impl From<Status> for ExitCode {
+    fn from(value: Status) -> Self {
+        ExitCode::from(value as u8)
+    }
+}
+
+/// An error caused by the arguments given to the application, rather than the data.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl Display for UsageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// Context marking an error as an I/O failure on the input or output, rather than the data.
+#[derive(Debug)]
+pub struct IoError(pub &'static str);
+
+impl Display for IoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Shorthand for creating a [`UsageError`] as an [`anyhow::Error`]
+pub fn usage(message: impl Into<String>) -> anyhow::Error {
+    UsageError(message.into()).into()
+}
@@ -0,0 +1,164 @@
+use crate::codec::{Codec, Property, PropertyValue, Settings};
+use std::io::Write;
+
+/// A run of encoded bytes and what they represent
+pub struct Annotation {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub description: String,
+    pub depth: usize,
+}
+
+/// Splits the encoding of settings into annotated pieces, in file order
+pub fn explain_settings(settings: &Settings) -> anyhow::Result<Vec<Annotation>> {
+    let mut explainer = Explainer::default();
+    if settings.version.has_header() {
+        let version = settings.version;
+        explainer.push_encoded(&version, format!("version {}", version))?;
+        explainer.push(vec![0], "header flag (false)".to_owned());
+    }
+    explainer.visit(&settings.properties)?;
+    Ok(explainer.annotations)
+}
+
+/// Splits the encoding of one property into annotated pieces, with offsets from its start
+pub fn explain_property(property: &Property) -> anyhow::Result<Vec<Annotation>> {
+    let mut explainer = Explainer::default();
+    explainer.visit(property)?;
+    Ok(explainer.annotations)
+}
+
+/// Writes annotations as offset, bytes in hex, and description, indented by nesting depth
+pub fn write_annotations(
+    annotations: &[Annotation],
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    const BYTES_PER_LINE: usize = 8;
+    for annotation in annotations {
+        let mut chunks = annotation.bytes.chunks(BYTES_PER_LINE);
+        let first = chunks.next().unwrap_or_default();
+        writeln!(
+            writer,
+            "{:06x}  {:<width$}  {:indent$}{}",
+            annotation.offset,
+            hex(first),
+            "",
+            annotation.description,
+            width = BYTES_PER_LINE * 3 - 1,
+            indent = annotation.depth * 2
+        )?;
+        for (index, chunk) in chunks.enumerate() {
+            writeln!(
+                writer,
+                "{:06x}  {}",
+                annotation.offset + (index + 1) * BYTES_PER_LINE,
+                hex(chunk)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes as space separated hex pairs
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Default)]
+struct Explainer {
+    annotations: Vec<Annotation>,
+    offset: usize,
+    depth: usize,
+}
+
+impl Explainer {
+    fn push(&mut self, bytes: Vec<u8>, description: String) {
+        let len = bytes.len();
+        self.annotations.push(Annotation {
+            offset: self.offset,
+            bytes,
+            description,
+            depth: self.depth,
+        });
+        self.offset += len;
+    }
+
+    fn push_encoded(&mut self, value: &impl Codec, description: String) -> anyhow::Result<()> {
+        let mut bytes = Vec::new();
+        value.encode(&mut bytes)?;
+        self.push(bytes, description);
+        Ok(())
+    }
+
+    fn visit(&mut self, property: &Property) -> anyhow::Result<()> {
+        self.push(
+            vec![property.value.type_byte(), property.any_flag as u8],
+            format!(
+                "type {} (any flag {})",
+                property.value.type_name(),
+                property.any_flag
+            ),
+        );
+        match &property.value {
+            PropertyValue::None => {}
+            PropertyValue::Bool(b) => self.push_encoded(b, format!("bool {}", b))?,
+            PropertyValue::Double(f) => self.push_encoded(f, format!("double {}", f))?,
+            PropertyValue::String(s) => self.push_encoded(s, format!("string {:?}", s))?,
+            PropertyValue::Integer(i) => self.push_encoded(i, format!("integer {}", i))?,
+            PropertyValue::List(list) => {
+                self.push(
+                    (list.len() as u32).to_le_bytes().to_vec(),
+                    format!("list of {} items", list.len()),
+                );
+                self.depth += 1;
+                for item in list {
+                    self.push_encoded(&item.key, format!("key {:?}", item.key))?;
+                    self.visit(&item.property)?;
+                }
+                self.depth -= 1;
+            }
+            PropertyValue::Dictionary(dict) => {
+                self.push(
+                    (dict.len() as u32).to_le_bytes().to_vec(),
+                    format!("dictionary of {} entries", dict.len()),
+                );
+                self.depth += 1;
+                for (key, value) in dict {
+                    self.push_encoded(key, format!("key {:?}", key))?;
+                    self.visit(value)?;
+                }
+                self.depth -= 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain_settings;
+    use crate::codec::Settings;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    #[test]
+    fn annotations_cover_encoding() {
+        let data = std::fs::read("test_data/settings-2.0.dat").expect("reading file");
+        let mut reader =
+            BufReader::new(File::open("test_data/settings-2.0.dat").expect("opening file"));
+        let settings = Settings::from_reader(&mut reader).expect("decoding settings");
+        let annotations = explain_settings(&settings).expect("explaining");
+        let mut offset = 0;
+        let mut joined = Vec::new();
+        for annotation in annotations {
+            assert_eq!(annotation.offset, offset);
+            offset += annotation.bytes.len();
+            joined.extend(annotation.bytes);
+        }
+        assert_eq!(data, joined);
+    }
+}
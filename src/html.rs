@@ -0,0 +1,213 @@
+use crate::codec::Property;
+use crate::simple::{ModSetting, ModSettings, ModSettingsValue};
+use indexmap::IndexMap;
+use std::fmt::Write;
+
+/// Renders the settings as a self-contained HTML page, with a table per section. Colors are shown
+/// as swatches beside their channels. Styles are inline so the page can be pasted anywhere.
+pub fn render(settings: &ModSettings) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(
+        html,
+        "<title>Mod settings for Factorio {}</title>\n</head>",
+        settings.factorio_version
+    );
+    html.push_str("<body style=\"font-family: sans-serif; margin: 2em;\">\n");
+    let _ = writeln!(
+        html,
+        "<h1>Mod settings for Factorio {}</h1>",
+        settings.factorio_version
+    );
+    for (name, section) in settings.sections() {
+        render_section(&mut html, name, section);
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+const CELL: &str = "border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left;";
+
+fn render_section(html: &mut String, name: &str, section: &IndexMap<String, ModSetting>) {
+    let _ = writeln!(html, "<h2>{}</h2>", escape(name));
+    if section.is_empty() {
+        html.push_str("<p><em>No settings</em></p>\n");
+        return;
+    }
+    html.push_str("<table style=\"border-collapse: collapse; margin-bottom: 2em;\">\n");
+    let _ = writeln!(
+        html,
+        "<tr><th style=\"{0}\">Setting</th><th style=\"{0}\">Type</th><th style=\"{0}\">Value</th></tr>",
+        CELL
+    );
+    for (key, setting) in section {
+        let (kind, mut value) = describe(&setting.value);
+        value.push_str(&describe_extra(&setting.extra));
+        let _ = writeln!(
+            html,
+            "<tr><td style=\"{0}\"><code>{1}</code></td><td style=\"{0}\">{2}</td><td style=\"{0}\">{3}</td></tr>",
+            CELL,
+            escape(key),
+            kind,
+            value
+        );
+    }
+    html.push_str("</table>\n");
+}
+
+/// Properties stored beside a setting's value, such as allowed values, each on a line of its own
+fn describe_extra(extra: &IndexMap<String, Property>) -> String {
+    let mut html = String::new();
+    for (key, property) in extra {
+        let value = match ModSettingsValue::try_from(property) {
+            Ok(value) => describe(&value).1,
+            Err(_) => format!(
+                "<code>{}</code>",
+                escape(&serde_json::to_string(property).unwrap_or_default())
+            ),
+        };
+        let _ = write!(
+            html,
+            "<br><small><code>{}</code>: {}</small>",
+            escape(key),
+            value
+        );
+    }
+    html
+}
+
+/// The type name and the value as HTML
+fn describe(value: &ModSettingsValue) -> (&'static str, String) {
+    match value {
+        ModSettingsValue::None => ("none", "<em>none</em>".to_owned()),
+        ModSettingsValue::Bool(b) => ("bool", b.to_string()),
+        ModSettingsValue::Double(f) => ("double", f.to_string()),
+        ModSettingsValue::String(s) => ("string", format!("<code>{}</code>", escape(s))),
+        ModSettingsValue::Integer(i) => ("integer", i.to_string()),
+        ModSettingsValue::Color { r, g, b, a } => {
+            let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            (
+                "color",
+                format!(
+                    "<span style=\"display: inline-block; width: 1em; height: 1em; vertical-align: middle; \
+                     border: 1px solid #000; background: rgba({}, {}, {}, {:.3});\"></span> r {} g {} b {} a {}",
+                    channel(*r),
+                    channel(*g),
+                    channel(*b),
+                    a.clamp(0.0, 1.0),
+                    r,
+                    g,
+                    b,
+                    a
+                ),
+            )
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{describe, describe_extra, escape, render};
+    use crate::codec::{Property, PropertyValue};
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">&'"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;"
+        );
+        let (kind, value) = describe(&ModSettingsValue::String("<b>".to_owned()));
+        assert_eq!((kind, value.as_str()), ("string", "<code>&lt;b&gt;</code>"));
+    }
+
+    #[test]
+    fn colors_have_swatches() {
+        let (kind, value) = describe(&ModSettingsValue::Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.0,
+            a: 1.0,
+        });
+        assert_eq!(kind, "color");
+        assert!(
+            value.contains("background: rgba(255, 128, 0, 1.000)"),
+            "{}",
+            value
+        );
+    }
+
+    #[test]
+    fn shows_extra_properties() {
+        let mut extra = IndexMap::new();
+        extra.insert(
+            "hint".to_owned(),
+            Property {
+                any_flag: false,
+                value: PropertyValue::String("<slow>".to_owned()),
+            },
+        );
+        extra.insert(
+            "allowed_values".to_owned(),
+            Property {
+                any_flag: false,
+                value: PropertyValue::List(vec![Property {
+                    any_flag: false,
+                    value: PropertyValue::String("a".to_owned()),
+                }
+                .into()]),
+            },
+        );
+        let html = describe_extra(&extra);
+        assert!(
+            html.contains("<code>hint</code>: <code>&lt;slow&gt;</code>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<code>allowed_values</code>: <code>"),
+            "{}",
+            html
+        );
+    }
+
+    #[test]
+    fn renders_each_section() {
+        let mut startup = IndexMap::new();
+        startup.insert("k".to_owned(), ModSettingsValue::Integer(3).into());
+        let html = render(&ModSettings {
+            factorio_version: FactorioVersion::new(1, 1, 82, 0),
+            startup,
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        });
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Factorio 1.1.82.0"));
+        for section in ["startup", "runtime-global", "runtime-per-user"] {
+            assert!(
+                html.contains(&format!("<h2>{}</h2>", section)),
+                "{}",
+                section
+            );
+        }
+        assert_eq!(html.matches("<table").count(), 1);
+        assert_eq!(html.matches("No settings").count(), 2);
+    }
+}
@@ -0,0 +1,693 @@
+use crate::args::Format;
+use crate::codec;
+use crate::exit::IoError;
+use crate::zip::{self, ZipEntry};
+use anyhow::Context;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{Chain, Cursor, ErrorKind, Read, Seek, SeekFrom, StdinLock};
+use std::path::Path;
+
+/// How many bytes are read from the start of the input to recognize its format
+pub const SNIFF_LEN: usize = 4096;
+
+/// The source of input to convert
+pub enum Input {
+    Stdin(StdinLock<'static>),
+    File(File),
+    /// Input that was decoded or otherwise prepared in memory before conversion
+    Memory(Cursor<Vec<u8>>),
+    /// The first bytes of a stream that can't seek, read ahead by [`Input::head`], followed by
+    /// the rest of it
+    Chained(Chain<Cursor<Vec<u8>>, Box<Input>>),
+    /// Gzip-compressed input, decompressed as it's read
+    Gzip(Box<MultiGzDecoder<Input>>),
+    /// The settings file inside a zip archive
+    Zip(Box<ZipEntry<File>>),
+}
+
+impl Input {
+    /// Opens the input path, with "-" meaning stdin. A `.zip` path opens the `mod-settings.dat`
+    /// inside it.
+    pub fn open(path: &Path) -> anyhow::Result<Input> {
+        if matches!(path.to_str(), Some("-")) {
+            Ok(Input::Stdin(std::io::stdin().lock()))
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+        {
+            let file = File::open(path).context(IoError("Opening input file"))?;
+            let entry = zip::open_settings(file)
+                .with_context(|| format!("Finding {} in {}", zip::SETTINGS_NAME, path.display()))?;
+            Ok(Input::Zip(Box::new(entry)))
+        } else {
+            Ok(Input::File(
+                File::open(path).context(IoError("Opening input file"))?,
+            ))
+        }
+    }
+
+    /// The input's length, if known before reading it
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Input::Stdin(_) | Input::Chained(_) | Input::Gzip(_) | Input::Zip(_) => None,
+            Input::File(file) => file.metadata().ok().map(|meta| meta.len()),
+            Input::Memory(cursor) => Some(cursor.get_ref().len() as u64),
+        }
+    }
+
+    /// Reads up to `len` bytes from the input without consuming them. Files and memory seek back
+    /// afterwards; stdin can't, so the bytes are kept and read again ahead of the rest of it.
+    pub fn head(&mut self, len: usize) -> std::io::Result<Vec<u8>> {
+        let mut head = Vec::new();
+        match self {
+            Input::File(file) => {
+                rewind_after(file, |file| file.take(len as u64).read_to_end(&mut head))?;
+            }
+            Input::Memory(cursor) => {
+                rewind_after(cursor, |cursor| {
+                    cursor.take(len as u64).read_to_end(&mut head)
+                })?;
+            }
+            Input::Stdin(_) | Input::Chained(_) | Input::Gzip(_) | Input::Zip(_) => {
+                self.take(len as u64).read_to_end(&mut head)?;
+                let rest = std::mem::replace(self, Input::Memory(Cursor::default()));
+                *self = Input::Chained(Cursor::new(head.clone()).chain(Box::new(rest)));
+            }
+        }
+        Ok(head)
+    }
+
+    /// The input decompressed, if it starts like a gzip stream. As much is read ahead as for
+    /// sniffing, so input that isn't compressed still starts with its whole head in one read.
+    pub fn decompressed(mut self) -> std::io::Result<Input> {
+        let head = self.head(SNIFF_LEN)?;
+        Ok(match Compression::sniff(&head) {
+            Some(Compression::Gzip) => Input::Gzip(Box::new(MultiGzDecoder::new(self))),
+            _ => self,
+        })
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, Input::Gzip(_))
+    }
+
+    /// Reads hex text from the argument itself, or stdin when the argument is "-". At most
+    /// `max_len` bytes of text are read.
+    pub fn hex(argument: &Path, max_len: u64) -> anyhow::Result<Input> {
+        let text = argument_or_stdin(argument, max_len)?;
+        Ok(Input::Memory(Cursor::new(
+            parse_hex(&text).context("Parsing hex input")?,
+        )))
+    }
+
+    /// Reads a hexdump from the file, or stdin when the path is "-". At most `max_len` bytes of
+    /// text are read.
+    pub fn hexdump(path: &Path, max_len: u64) -> anyhow::Result<Input> {
+        let text = if matches!(path.to_str(), Some("-")) {
+            argument_or_stdin(path, max_len)?
+        } else {
+            let file = File::open(path).context(IoError("Opening input file"))?;
+            let mut text = String::new();
+            SizeLimit::new(file, max_len)
+                .read_to_string(&mut text)
+                .context(IoError("Reading input file"))?;
+            text
+        };
+        Ok(Input::Memory(Cursor::new(
+            parse_hexdump(&text, max_len).context("Parsing hexdump input")?,
+        )))
+    }
+
+    /// Reads base64 text from the argument itself, or stdin when the argument is "-". At most
+    /// `max_len` bytes of text are read.
+    pub fn base64(argument: &Path, max_len: u64) -> anyhow::Result<Input> {
+        let text = argument_or_stdin(argument, max_len)?;
+        Ok(Input::Memory(Cursor::new(
+            parse_base64(&text).context("Parsing base64 input")?,
+        )))
+    }
+
+    /// Reads the system clipboard, unwrapping settings pasted as hex or base64
+    pub fn clipboard() -> anyhow::Result<Input> {
+        Ok(Input::Memory(Cursor::new(unwrap_pasted(read_clipboard()?))))
+    }
+}
+
+/// The commands that print the clipboard, tried in order until one succeeds
+const CLIPBOARD_COMMANDS: &[&[&str]] = if cfg!(target_os = "macos") {
+    &[&["pbpaste"]]
+} else if cfg!(windows) {
+    &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]]
+} else {
+    &[
+        &["wl-paste", "--no-newline"],
+        &["xclip", "-selection", "clipboard", "-out"],
+        &["xsel", "--clipboard", "--output"],
+    ]
+};
+
+fn read_clipboard() -> anyhow::Result<Vec<u8>> {
+    for command in CLIPBOARD_COMMANDS {
+        let output = std::process::Command::new(command[0])
+            .args(&command[1..])
+            .stderr(std::process::Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => return Ok(output.stdout),
+            _ => continue,
+        }
+    }
+    let tried = CLIPBOARD_COMMANDS
+        .iter()
+        .map(|command| command[0])
+        .collect::<Vec<_>>();
+    Err(anyhow::anyhow!(
+        "Couldn't read the clipboard with any of {}",
+        tried.join(", ")
+    ))
+    .context(IoError("Reading the clipboard"))
+}
+
+/// Recognizes what was pasted: binary settings written as hex or base64 are decoded to their
+/// bytes, and anything else, whether settings text or raw bytes, is left as it is
+pub fn unwrap_pasted(data: Vec<u8>) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(&data) else {
+        return data;
+    };
+    [parse_hex(text).ok(), parse_base64(text).ok()]
+        .into_iter()
+        .flatten()
+        .find(|bytes| codec::starts_like_settings(bytes))
+        .unwrap_or(data)
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Input::Stdin(stdin) => stdin.read(buf),
+            Input::File(file) => file.read(buf),
+            Input::Memory(cursor) => cursor.read(buf),
+            Input::Chained(chain) => chain.read(buf),
+            Input::Gzip(gzip) => gzip.read(buf).map_err(invalid_compressed),
+            Input::Zip(entry) => entry.read(buf),
+        }
+    }
+}
+
+/// Reports compressed data that can't be decompressed as invalid data, like any other damage to
+/// the input, rather than as the decompressor's error for it. Other errors, from reading the
+/// compressed data, are kept as they are.
+pub fn invalid_compressed(err: std::io::Error) -> std::io::Error {
+    match err.kind() {
+        ErrorKind::InvalidInput | ErrorKind::InvalidData | ErrorKind::UnexpectedEof => {
+            std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid compressed data: {}", err),
+            )
+        }
+        _ => err,
+    }
+}
+
+/// Runs a read and then seeks back to where it started
+fn rewind_after<S: Seek>(
+    stream: &mut S,
+    read: impl FnOnce(&mut S) -> std::io::Result<usize>,
+) -> std::io::Result<usize> {
+    let start = stream.stream_position()?;
+    let count = read(stream)?;
+    stream.seek(SeekFrom::Start(start))?;
+    Ok(count)
+}
+
+/// Recognizes the format of input from its first bytes: `None` for binary settings, otherwise the
+/// text format. Text starting with `{` is JSON, or NDJSON when its first line is a whole JSON
+/// record followed by another; anything else is taken to be TOML.
+pub fn sniff_format(head: &[u8]) -> Option<Format> {
+    if codec::looks_encoded(head) || Compression::sniff(head).is_some() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    if !text.starts_with('{') {
+        return Some(Format::Toml);
+    }
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let first = lines.next().unwrap_or_default();
+    let record = serde_json::from_str::<serde_json::Value>(first).is_ok();
+    if record
+        && lines
+            .next()
+            .is_some_and(|line| line.trim_start().starts_with('{'))
+    {
+        Some(Format::Ndjson)
+    } else {
+        Some(Format::Json)
+    }
+}
+
+/// Fails reading once more than a set number of bytes has been read, rather than silently
+/// truncating the input
+pub struct SizeLimit<R> {
+    inner: R,
+    limit: u64,
+    read: u64,
+}
+
+impl<R: Read> SizeLimit<R> {
+    pub fn new(inner: R, limit: u64) -> SizeLimit<R> {
+        SizeLimit {
+            inner,
+            limit,
+            read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for SizeLimit<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.read += count as u64;
+        if self.read > self.limit {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Input is larger than the maximum of {} bytes", self.limit),
+            ));
+        }
+        Ok(count)
+    }
+}
+
+/// Compression formats recognized by their magic bytes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Detects compressed data from its first bytes
+    pub fn sniff(data: &[u8]) -> Option<Compression> {
+        if data.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Fails with guidance if the data is compressed in a way that wasn't undone when the input
+    /// was opened. Gzip is decompressed then, but this build has no zstd decompressor.
+    pub fn reject(data: &[u8]) -> anyhow::Result<()> {
+        match Self::sniff(data) {
+            Some(Compression::Gzip) => Err(anyhow::anyhow!(
+                "Input is gzip-compressed twice. Decompress it once first, e.g. with `gzip -dc`"
+            )),
+            Some(Compression::Zstd) => Err(anyhow::anyhow!(
+                "Input is zstd-compressed, which this build can't decompress. Decompress it first, e.g. with `zstd -dc`"
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The argument as text, or all of stdin when the argument is "-", failing if that's more than
+/// `max_len` bytes
+fn argument_or_stdin(argument: &Path, max_len: u64) -> anyhow::Result<String> {
+    if matches!(argument.to_str(), Some("-")) {
+        let mut text = String::new();
+        SizeLimit::new(std::io::stdin(), max_len)
+            .read_to_string(&mut text)
+            .context(IoError("Reading stdin"))?;
+        Ok(text)
+    } else {
+        argument
+            .to_str()
+            .map(str::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("Input argument is not valid UTF-8"))
+    }
+}
+
+/// Parses hex digits into bytes, ignoring whitespace and `0x` prefixes on any group of digits
+pub fn parse_hex(text: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = text
+        .split_whitespace()
+        .map(|group| {
+            group
+                .strip_prefix("0x")
+                .or_else(|| group.strip_prefix("0X"))
+                .unwrap_or(group)
+        })
+        .collect::<String>();
+    if digits.len() % 2 != 0 {
+        anyhow::bail!("Hex input has an odd number of digits ({})", digits.len());
+    }
+    digits
+        .as_bytes()
+        .chunks(2)
+        .enumerate()
+        .map(|(index, pair)| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Invalid hex byte {:?} at byte {}",
+                        String::from_utf8_lossy(pair),
+                        index
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Parses a hexdump in the layout of `xxd` or `hexdump -C`: each line is an offset, the bytes in
+/// hex and an ASCII gutter, which is ignored. A `*` line, written by `hexdump` in place of lines
+/// repeating the one before, is expanded up to the offset of the line after it, which may not be
+/// past `max_len`.
+pub fn parse_hexdump(text: &str, max_len: u64) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut start = None;
+    let mut previous = Vec::new();
+    let mut repeating = false;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "*" {
+            repeating = true;
+            continue;
+        }
+        let (offset, rest) = line
+            .split_once(|c: char| c == ':' || c.is_whitespace())
+            .unwrap_or((line, ""));
+        let xxd = line[offset.len()..].starts_with(':');
+        let offset = usize::from_str_radix(offset, 16)
+            .map_err(|_| anyhow::anyhow!("Hexdump line {} doesn't start with an offset", number))?;
+        // a dump of part of a file starts past 0
+        let offset = offset
+            .checked_sub(*start.get_or_insert(offset))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Hexdump offsets go backwards at line {}, before the first line's",
+                    number
+                )
+            })?;
+        if repeating {
+            if previous.is_empty() || offset < bytes.len() {
+                anyhow::bail!(
+                    "Hexdump line {} doesn't follow on from the * before it",
+                    number
+                );
+            }
+            if offset as u64 > max_len {
+                anyhow::bail!(
+                    "Hexdump line {} repeats up to offset {:#x}, past the maximum of {} bytes",
+                    number,
+                    offset,
+                    max_len
+                );
+            }
+            bytes.try_reserve(offset - bytes.len()).map_err(|_| {
+                anyhow::anyhow!(
+                    "Hexdump line {} repeats up to offset {:#x}, more than fits in memory",
+                    number,
+                    offset
+                )
+            })?;
+            while bytes.len() < offset {
+                bytes.extend_from_slice(&previous);
+            }
+            repeating = false;
+        }
+        if offset != bytes.len() {
+            anyhow::bail!(
+                "Hexdump line {} is at offset {:#x}, but {:#x} bytes came before it",
+                number,
+                offset,
+                bytes.len()
+            );
+        }
+        // the layout is told by what follows the offset, since the gutter can hold any character.
+        // The gutter follows two spaces in xxd, after a colon, and a | in hexdump -C, after
+        // whitespace. Other layouts, like hexdump's default of 16-bit words, would be read with
+        // their bytes swapped.
+        let hex = if xxd {
+            rest.trim_start().split("  ").next().unwrap_or_default()
+        } else if let Some(gutter) = rest.find('|') {
+            &rest[..gutter]
+        } else if rest.is_empty() {
+            ""
+        } else {
+            anyhow::bail!(
+                "Hexdump line {} isn't laid out like xxd or hexdump -C output",
+                number
+            );
+        };
+        let line_bytes =
+            parse_hex(hex).with_context(|| format!("Reading hexdump line {}", number))?;
+        bytes.extend_from_slice(&line_bytes);
+        previous = line_bytes;
+    }
+    if repeating {
+        anyhow::bail!("Hexdump ends with * rather than the offset of its end");
+    }
+    Ok(bytes)
+}
+
+/// Parses base64 in either the standard or the URL-safe alphabet, ignoring whitespace. Padding is
+/// optional.
+pub fn parse_base64(text: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect::<Vec<_>>();
+    let padding = digits.iter().rev().take_while(|&&b| b == b'=').count();
+    let digits = &digits[..digits.len() - padding];
+    if padding > 2 || digits.len() % 4 == 1 {
+        anyhow::bail!(
+            "Base64 input has an invalid length ({} characters)",
+            digits.len() + padding
+        );
+    }
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for (index, &digit) in digits.iter().enumerate() {
+        let value = match digit {
+            b'A'..=b'Z' => digit - b'A',
+            b'a'..=b'z' => digit - b'a' + 26,
+            b'0'..=b'9' => digit - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => anyhow::bail!(
+                "Invalid base64 character {:?} at character {}",
+                digit as char,
+                index
+            ),
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_base64, parse_hex, parse_hexdump, sniff_format, unwrap_pasted, Compression, Input,
+        SizeLimit,
+    };
+    use crate::args::Format;
+    use hex_literal::hex;
+    use std::io::{Cursor, Read};
+    use std::path::Path;
+
+    #[test]
+    fn size_limit() {
+        let mut data = Vec::new();
+        SizeLimit::new(&[1u8, 2, 3][..], 3)
+            .read_to_end(&mut data)
+            .expect("within limit");
+        assert_eq!(data, [1, 2, 3]);
+        let err = SizeLimit::new(&[1u8, 2, 3, 4][..], 3)
+            .read_to_end(&mut Vec::new())
+            .expect_err("over limit");
+        assert!(err.to_string().contains("maximum of 3 bytes"));
+    }
+
+    #[test]
+    fn head_leaves_input_unread() {
+        let file = Input::open(Path::new("test_data/golden.dat")).expect("opening");
+        let memory = Input::Memory(Cursor::new(b"0123456789".to_vec()));
+        let stream = Input::Chained(
+            Cursor::new(b"0123".to_vec())
+                .chain(Box::new(Input::Memory(Cursor::new(b"456789".to_vec())))),
+        );
+        for mut input in [file, memory, stream] {
+            let head = input.head(6).expect("reading head");
+            assert_eq!(head.len(), 6);
+            let mut all = Vec::new();
+            input.read_to_end(&mut all).expect("reading");
+            assert!(all.starts_with(&head));
+            assert!(all.len() >= 10);
+        }
+    }
+
+    #[test]
+    fn sniff_formats() {
+        let dat = std::fs::read("test_data/golden.dat").expect("reading fixture");
+        assert_eq!(sniff_format(&dat), None);
+        assert_eq!(sniff_format(&[0x1f, 0x8b, 0x08]), None);
+        assert_eq!(
+            sniff_format(b"\n  {\n  \"startup\": {}"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            sniff_format(b"{\"factorio_version\": {}}\n{\"section\": \"startup\""),
+            Some(Format::Ndjson)
+        );
+        assert_eq!(
+            sniff_format(b"[factorio_version]\nmajor = 1"),
+            Some(Format::Toml)
+        );
+        assert_eq!(
+            sniff_format("# caf\u{e9}".as_bytes().split_last().unwrap().1),
+            Some(Format::Toml),
+            "a character cut off by the sniff length is still text"
+        );
+    }
+
+    #[test]
+    fn sniff_compression() {
+        assert_eq!(
+            Compression::sniff(&[0x1f, 0x8b, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::sniff(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::sniff(b"{}"), None);
+        assert_eq!(Compression::sniff(&[0x1f]), None);
+    }
+
+    #[test]
+    fn pasted_input() {
+        let settings = hex!("01 00 01 00 52 00 04 00 00 05 00 00 00 00 00").to_vec();
+        for pasted in [
+            "01 00 01 00 52 00 04 00 00 05 00 00 00 00 00\n",
+            "AQABAFIABAAABQAAAAAA",
+        ] {
+            assert_eq!(unwrap_pasted(pasted.into()), settings, "{}", pasted);
+        }
+        assert_eq!(unwrap_pasted(settings.clone()), settings);
+        // text that happens to be valid hex or base64, but not of settings, is kept
+        for text in ["{\"startup\": {}}", "cafe", "[startup]\n"] {
+            assert_eq!(unwrap_pasted(text.into()), text.as_bytes(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn hex_with_whitespace_and_prefixes() {
+        assert_eq!(
+            parse_hex("01 00\n0x52 0X0400\t ff").expect("parsing"),
+            vec![0x01, 0x00, 0x52, 0x04, 0x00, 0xff]
+        );
+        assert_eq!(parse_hex("").expect("parsing"), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn hexdump_layouts() {
+        let expected = hex!("01 00 01 00 52 00 04 00 00 03 00 00 03 61 62 63 05 00");
+        let xxd = "00000000: 0100 0100 5200 0400 0003 0000 0361 6263  ....R........abc\n\
+                   00000010: 0500                                     ..\n";
+        assert_eq!(parse_hexdump(xxd, u64::MAX).expect("parsing"), expected);
+        let xxd_bytes =
+            "00000000: 01 00 01 00 52 00 04 00 00 03 00 00 03 61 62 63  ....R..  ....abc\n\
+                         00000010: 05 00                                            ..\n";
+        assert_eq!(
+            parse_hexdump(xxd_bytes, u64::MAX).expect("parsing"),
+            expected
+        );
+        let xxd_pipes = "00000000: 7c7c 2061 7c62  || a|b\n";
+        assert_eq!(
+            parse_hexdump(xxd_pipes, u64::MAX).expect("parsing"),
+            b"|| a|b"
+        );
+        let canonical =
+            "00000000  01 00 01 00 52 00 04 00  00 03 00 00 03 61 62 63  |....R........abc|\n\
+                         00000010  05 00                                             |..|\n\
+                         00000012\n";
+        assert_eq!(
+            parse_hexdump(canonical, u64::MAX).expect("parsing"),
+            expected
+        );
+
+        let repeated =
+            "00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+                        *\n\
+                        00000030  ff                                                |.|\n\
+                        00000031\n";
+        let mut expected = vec![0; 0x30];
+        expected.push(0xff);
+        assert_eq!(
+            parse_hexdump(repeated, u64::MAX).expect("parsing"),
+            expected
+        );
+    }
+
+    #[test]
+    fn hexdump_errors() {
+        // hexdump's default layout of 16-bit words
+        assert!(parse_hexdump("0000000 0001 0001 0052 0004\n", u64::MAX).is_err());
+        assert!(parse_hexdump("00000000: 0100\n00000004: 0100\n", u64::MAX).is_err());
+        assert!(parse_hexdump("00000000  00 00  |..|\n*\n", u64::MAX).is_err());
+        assert!(parse_hexdump("bytes: 0100\n", u64::MAX).is_err());
+        let err =
+            parse_hexdump("00000010: 0100\n00000000: 0100\n", u64::MAX).expect_err("backwards");
+        assert!(err.to_string().contains("go backwards"), "{}", err);
+
+        // a repeat is refused before it's expanded past the maximum size
+        let huge = "00000000: 05  .\n*\nffffffffff: 00  .\n";
+        let err = parse_hexdump(huge, 1000).expect_err("too large");
+        assert!(err.to_string().contains("past the maximum"), "{}", err);
+        let repeated = "00000000: 05  .\n*\n00000010: 00  .\n";
+        assert_eq!(parse_hexdump(repeated, 17).expect("parsing").len(), 17);
+        assert!(parse_hexdump(repeated, 15).is_err());
+    }
+
+    #[test]
+    fn base64_alphabets_and_padding() {
+        assert_eq!(parse_base64("Zm9vYmFy").expect("parsing"), b"foobar");
+        assert_eq!(parse_base64("Zm9v\nYg==").expect("parsing"), b"foob");
+        assert_eq!(parse_base64("Zm9vYg").expect("parsing"), b"foob");
+        assert_eq!(parse_base64("+/8=").expect("parsing"), [0xfb, 0xff]);
+        assert_eq!(parse_base64("-_8").expect("parsing"), [0xfb, 0xff]);
+        assert_eq!(parse_base64("").expect("parsing"), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn base64_errors() {
+        assert!(parse_base64("Zm9vY").is_err());
+        assert!(parse_base64("Zm9v!A==").is_err());
+        assert!(parse_base64("Zg===").is_err());
+        assert!(parse_base64("Zm=9v").is_err());
+    }
+
+    #[test]
+    fn hex_errors() {
+        assert!(parse_hex("012").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert!(parse_hex("+1").is_err());
+    }
+}
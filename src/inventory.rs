@@ -0,0 +1,242 @@
+use crate::cache::Cache;
+use crate::codec::{DecodeOptions, Settings, StringEncoding};
+use crate::exit::IoError;
+use crate::simple::{RUNTIME_GLOBAL, RUNTIME_PER_USER, STARTUP};
+use crate::transform::glob_match;
+use anyhow::Context;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// One file's line in an inventory of settings files
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub file: String,
+    /// Hash of the file's content, so identical files are easy to spot
+    pub hash: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Outcome {
+    Decoded {
+        version: String,
+        startup: usize,
+        #[serde(rename = "runtime-global")]
+        runtime_global: usize,
+        #[serde(rename = "runtime-per-user")]
+        runtime_per_user: usize,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+impl Entry {
+    /// Why the file couldn't be summarized, if it couldn't
+    pub fn error(&self) -> Option<&str> {
+        match &self.outcome {
+            Outcome::Failed { error } => Some(error),
+            Outcome::Decoded { .. } => None,
+        }
+    }
+
+    fn of(path: &Path, strings: StringEncoding) -> Entry {
+        let data = std::fs::read(path);
+        let hash = data
+            .as_ref()
+            .map(|data| format!("{:016x}", Cache::hash(data)))
+            .unwrap_or_default();
+        let outcome = data
+            .context(IoError("Reading settings file"))
+            .and_then(|data| {
+                let options = DecodeOptions {
+                    strings,
+                    ..Default::default()
+                };
+                Settings::from_reader_with(&mut data.as_slice(), &options)
+            })
+            .map(|settings| {
+                let count = |section: &str| {
+                    settings
+                        .properties
+                        .value
+                        .as_dictionary()
+                        .and_then(|root| root.get(section))
+                        .and_then(|section| section.value.as_dictionary())
+                        .map_or(0, |section| section.len())
+                };
+                Outcome::Decoded {
+                    version: settings.version.to_string(),
+                    startup: count(STARTUP),
+                    runtime_global: count(RUNTIME_GLOBAL),
+                    runtime_per_user: count(RUNTIME_PER_USER),
+                }
+            })
+            .unwrap_or_else(|err| Outcome::Failed {
+                error: format!("{:#}", err),
+            });
+        Entry {
+            file: path.display().to_string(),
+            hash,
+            outcome,
+        }
+    }
+}
+
+/// Summarizes every `.dat` file in a directory, or every file matching a `*`/`?` pattern in its
+/// last component. Files are read in parallel, and the entries are sorted by path.
+///
+/// A file that can't be decoded gets an entry with its error, unless failing fast, which reads
+/// the files in order and stops with an error at the first that fails.
+pub fn collect(
+    input: &Path,
+    strings: StringEncoding,
+    fail_fast: bool,
+) -> anyhow::Result<Vec<Entry>> {
+    let files = files(input)?;
+    if fail_fast {
+        return files
+            .iter()
+            .map(|path| {
+                let entry = Entry::of(path, strings);
+                match entry.error() {
+                    Some(error) => Err(anyhow::anyhow!("{}: {}", entry.file, error)),
+                    None => Ok(entry),
+                }
+            })
+            .collect();
+    }
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = files.len().div_ceil(threads).max(1);
+    Ok(std::thread::scope(|scope| {
+        let handles = files
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|path| Entry::of(path, strings))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("inventory thread panicked"))
+            .collect()
+    }))
+}
+
+/// The files an inventory or batch covers, sorted
+pub fn files(input: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let name = input.file_name().and_then(|name| name.to_str());
+    let (dir, pattern) = match name {
+        Some(name) if name.contains(['*', '?']) => {
+            let parent = input
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty());
+            (parent.unwrap_or(Path::new(".")), name)
+        }
+        _ if input.is_dir() => (input, "*.dat"),
+        _ => return Ok(vec![input.to_owned()]),
+    };
+    let mut files = std::fs::read_dir(dir)
+        .with_context(|| format!("Listing {}", dir.display()))
+        .context(IoError("Reading inventory"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| glob_match(pattern, name))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Writes the entries as a table of file, version, setting count per section and content hash
+pub fn write_table(entries: &[Entry], writer: &mut impl Write) -> std::io::Result<()> {
+    let width = entries
+        .iter()
+        .map(|entry| entry.file.len())
+        .chain([4])
+        .max()
+        .unwrap_or_default();
+    writeln!(
+        writer,
+        "{:<width$}  {:<12} {:>8} {:>14} {:>16}  hash",
+        "file", "version", STARTUP, RUNTIME_GLOBAL, RUNTIME_PER_USER
+    )?;
+    for entry in entries {
+        match &entry.outcome {
+            Outcome::Decoded {
+                version,
+                startup,
+                runtime_global,
+                runtime_per_user,
+            } => writeln!(
+                writer,
+                "{:<width$}  {:<12} {:>8} {:>14} {:>16}  {}",
+                entry.file, version, startup, runtime_global, runtime_per_user, entry.hash
+            )?,
+            Outcome::Failed { error } => {
+                writeln!(writer, "{:<width$}  error: {}", entry.file, error)?
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect, files, write_table, Outcome};
+    use crate::codec::StringEncoding;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn lists_directories_and_patterns() {
+        let all = [
+            PathBuf::from("test_data/complex-settings.dat"),
+            PathBuf::from("test_data/golden.dat"),
+            PathBuf::from("test_data/list-settings.dat"),
+            PathBuf::from("test_data/settings-2.0.dat"),
+        ];
+        assert_eq!(files(Path::new("test_data")).expect("listing"), all);
+        assert_eq!(
+            files(Path::new("test_data/settings-*")).expect("listing"),
+            all[3..]
+        );
+        assert_eq!(
+            files(&all[0]).expect("single file"),
+            all[..1],
+            "a plain file is its own inventory"
+        );
+    }
+
+    #[test]
+    fn summarizes_each_file() {
+        let entries =
+            collect(Path::new("test_data"), StringEncoding::Utf8, false).expect("collecting");
+        assert_eq!(entries.len(), 4);
+        for entry in &entries {
+            assert_eq!(entry.hash.len(), 16);
+            let Outcome::Decoded {
+                startup, version, ..
+            } = &entry.outcome
+            else {
+                panic!("{} failed to decode: {:?}", entry.file, entry.outcome);
+            };
+            assert!(*startup > 0);
+            assert!(version.starts_with(['1', '2']));
+        }
+        let mut table = Vec::new();
+        write_table(&entries, &mut table).expect("writing");
+        assert_eq!(String::from_utf8(table).expect("utf-8").lines().count(), 5);
+    }
+}
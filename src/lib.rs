@@ -0,0 +1,48 @@
+//! Reads and writes Factorio's `mod-settings.dat`. [`decode_reader`] and [`encode_writer`] convert
+//! between the binary file and [`ModSettings`], which serializes to the same JSON and TOML as the
+//! command line tool writes. The property tree underneath is available through [`Settings`].
+
+use anyhow::Context;
+use std::io::{Read, Write};
+
+pub mod codec;
+pub mod simple;
+pub mod types;
+
+pub use codec::Settings;
+pub use simple::{ModSetting, ModSettings, ModSettingsValue};
+pub use types::FactorioVersion;
+
+/// Decodes a `mod-settings.dat` into its settings
+pub fn decode_reader(reader: &mut impl Read) -> anyhow::Result<ModSettings> {
+    let settings = Settings::from_reader(reader).context("Decoding settings")?;
+    ModSettings::try_from(&settings).context("Converting format")
+}
+
+/// Encodes settings as a `mod-settings.dat`
+pub fn encode_writer(settings: &ModSettings, writer: &mut impl Write) -> anyhow::Result<()> {
+    Settings::from_simple(settings)
+        .encode_to_writer(writer)
+        .context("Encoding settings")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_reader, encode_writer, ModSettingsValue};
+
+    #[test]
+    fn decodes_and_encodes() {
+        let data = std::fs::read("test_data/golden.dat").expect("reading file");
+        let settings = decode_reader(&mut data.as_slice()).expect("decoding");
+        assert_eq!(
+            settings.startup["golden-integer"].value,
+            ModSettingsValue::Integer(-9_007_199_254_740_993)
+        );
+        let mut encoded = Vec::new();
+        encode_writer(&settings, &mut encoded).expect("encoding");
+        assert_eq!(encoded, data);
+
+        let err = decode_reader(&mut &data[..20]).expect_err("truncated");
+        assert_eq!(err.to_string(), "Decoding settings");
+    }
+}
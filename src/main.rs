@@ -1,28 +1,87 @@
-use crate::args::{Args, Format, Mode};
-use crate::simple::ModSettings;
+use crate::args::{
+    Args, ColorFormat, DiffFormat, Format, Kind, Mode, SideFormat, TomlColors, WarningsFormat,
+};
+use crate::cache::Cache;
+use crate::codec::{Codec, StringEncoding};
+use crate::exit::{IoError, Status};
+use crate::input::{Compression, Input, SizeLimit};
+use crate::output::Output;
+use crate::simple::{ModSettings, RUNTIME_GLOBAL, RUNTIME_PER_USER, STARTUP};
+use crate::source::SourceMap;
+use crate::types::FactorioVersion;
 use anyhow::Context;
-use either::Either;
+use factorio_settings::{codec, simple, types};
+use indexmap::IndexMap;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 mod args;
-mod codec;
-mod simple;
-mod types;
+mod batch;
+mod cache;
+mod compat;
+mod defaults;
+mod diff;
+mod dot;
+mod exit;
+mod explain;
+mod html;
+mod input;
+mod inventory;
+mod modlist;
+mod output;
+mod profile;
+mod selftest;
+mod setting;
+mod sha256;
+mod source;
+mod transform;
+mod zip;
 
+/// Whether the path has the extension, looking past a `.gz` that marks it compressed
 fn extension_is(path: &Path, s: &str) -> bool {
+    let path = match path.file_stem() {
+        Some(stem) if is_gzip_path(path) => Path::new(stem),
+        _ => path,
+    };
     path.extension()
         .map(|a| a.eq_ignore_ascii_case(s))
         .unwrap_or(false)
 }
 
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// Whether the output is compressed, by --gzip or a `.gz` output path
+fn gzip_output(arg: &Args) -> bool {
+    arg.gzip || arg.output.as_deref().is_some_and(is_gzip_path)
+}
+
+/// The mode implied by the arguments: text on both sides converts, text in encodes and text out
+/// decodes
 fn infer_args_mode(arg: &Args) -> Option<Mode> {
-    if let Some(path) = arg.output.as_ref() {
-        let json = extension_is(path, "json");
-        let toml = extension_is(path, "toml");
+    let text_input = match arg.input_format {
+        Some(format) => format.text().is_some(),
+        None => format_of(arg.input()).is_some(),
+    };
+    if arg.input_format == Some(SideFormat::Dat) || arg.inline_binary() {
+        // the input is binary
+        Some(Mode::Decode)
+    } else if arg.output_format == Some(SideFormat::Dat) {
+        Some(Mode::Encode)
+    } else if let Some(path) = arg.output.as_ref() {
         let dat = extension_is(path, "dat");
-        if json || toml {
+        if format_of(path).is_some() && text_input {
+            Some(Mode::Convert)
+        } else if format_of(path).is_some() {
             Some(Mode::Decode)
         } else if dat {
             Some(Mode::Encode)
@@ -30,11 +89,15 @@ fn infer_args_mode(arg: &Args) -> Option<Mode> {
             None
         }
     } else {
-        let path = arg.input.as_path();
-        let json = extension_is(path, "json");
-        let toml = extension_is(path, "toml");
+        let path = arg.input();
         let dat = extension_is(path, "dat");
-        if json || toml {
+        if text_input
+            && arg
+                .output_format
+                .is_some_and(|format| format.text().is_some())
+        {
+            Some(Mode::Convert)
+        } else if format_of(path).is_some() {
             Some(Mode::Encode)
         } else if dat {
             Some(Mode::Decode)
@@ -44,90 +107,2183 @@ fn infer_args_mode(arg: &Args) -> Option<Mode> {
     }
 }
 
-fn infer_args_format(arg: &Args, mode: &Mode) -> Option<Format> {
-    match mode {
-        Mode::Encode => {
-            let path = arg.input.as_path();
-            let json = extension_is(path, "json");
-            let toml = extension_is(path, "toml");
-            if json {
-                Some(Format::Json)
-            } else if toml {
-                Some(Format::Toml)
-            } else {
-                None
-            }
+/// The text format indicated by a path's extension
+fn format_of(path: &Path) -> Option<Format> {
+    if extension_is(path, "json") {
+        Some(Format::Json)
+    } else if extension_is(path, "toml") {
+        Some(Format::Toml)
+    } else if extension_is(path, "ndjson") || extension_is(path, "jsonl") {
+        Some(Format::Ndjson)
+    } else if extension_is(path, "html") || extension_is(path, "htm") {
+        Some(Format::Html)
+    } else {
+        None
+    }
+}
+
+/// The format of text input: --input-format, then --format, then the input's extension
+fn input_text_format(arg: &Args) -> Option<Format> {
+    match arg.input_format {
+        Some(format) => format.text(),
+        None => arg.format.or_else(|| format_of(arg.input())),
+    }
+}
+
+/// The format of text output: --output-format, then --format, then the output's extension
+fn output_text_format(arg: &Args) -> Option<Format> {
+    match arg.output_format {
+        Some(format) => format.text(),
+        None => arg
+            .format
+            .or_else(|| arg.output.as_deref().and_then(format_of)),
+    }
+}
+
+const NDJSON_INPUT_ONLY: &str = "NDJSON is only supported as input, when encoding mod settings";
+const TOML_UNREPRESENTABLE: &str =
+    "Serializing to TOML. These settings can't be represented in TOML; use --format json instead";
+const HTML_OUTPUT_ONLY: &str = "HTML is only supported as output, when decoding mod settings";
+
+/// Warnings printed so far, reported by --stats
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether warnings are written as JSON objects, from --warnings-format
+static JSON_WARNINGS: AtomicBool = AtomicBool::new(false);
+
+/// Prints a warning to stderr
+fn warn(message: std::fmt::Arguments) {
+    warn_setting(None, message);
+}
+
+/// Prints a warning to stderr, naming the setting it's about when written as JSON
+fn warn_setting(path: Option<&str>, message: std::fmt::Arguments) {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+    report("warn", path, message);
+}
+
+/// Prints a message that isn't a warning to stderr, such as what an option changed
+fn notice(path: Option<&str>, message: std::fmt::Arguments) {
+    report("info", path, message);
+}
+
+/// Messages printed while decoding for the cache, which are stored with the output and printed
+/// again when it's used
+static RECORDED: Mutex<Option<Vec<Recorded>>> = Mutex::new(None);
+
+/// A message printed by [`report`], as stored in the cache
+#[derive(Serialize, Deserialize)]
+struct Recorded {
+    level: String,
+    key: Option<String>,
+    message: String,
+}
+
+/// Prints a message to stderr, as a JSON object with its level and setting with
+/// --warnings-format json
+fn report(level: &'static str, path: Option<&str>, message: std::fmt::Arguments) {
+    if let Some(recorded) = RECORDED.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        recorded.push(Recorded {
+            level: level.to_owned(),
+            key: path.map(str::to_owned),
+            message: message.to_string(),
+        });
+    }
+    if JSON_WARNINGS.load(Ordering::Relaxed) {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            level: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            key: Option<&'a str>,
+            message: String,
+        }
+        let report = Report {
+            level,
+            key: path,
+            message: message.to_string(),
+        };
+        eprintln!("{}", serde_json::to_string(&report).unwrap_or_default());
+    } else {
+        match level {
+            "warn" => eprintln!("Warning: {}", message),
+            "error" => eprintln!("Error: {}", message),
+            _ => eprintln!("{}", message),
+        }
+    }
+}
+
+/// Prints a report of several values to stderr, such as --stats: as text, or with
+/// --warnings-format json as one JSON object of the values and the level
+fn report_values(level: &'static str, values: impl Serialize, text: impl std::fmt::Display) {
+    if !JSON_WARNINGS.load(Ordering::Relaxed) {
+        eprintln!("{}", text);
+        return;
+    }
+    let mut object = serde_json::Map::new();
+    object.insert("level".to_owned(), level.into());
+    match serde_json::to_value(values) {
+        Ok(serde_json::Value::Object(values)) => object.extend(values),
+        Ok(value) => {
+            object.insert("value".to_owned(), value);
         }
-        Mode::Decode => arg.output.as_deref().and_then(|path| {
-            let json = extension_is(path, "json");
-            let toml = extension_is(path, "toml");
-            if json {
-                Some(Format::Json)
-            } else if toml {
-                Some(Format::Toml)
+        Err(_) => {}
+    }
+    eprintln!("{}", serde_json::Value::Object(object));
+}
+
+/// Lists the files of a batch that failed, and their errors
+fn report_failures(failed: &[(String, String)], total: usize) {
+    if JSON_WARNINGS.load(Ordering::Relaxed) {
+        for (file, error) in failed {
+            eprintln!(
+                "{}",
+                serde_json::json!({"level": "error", "file": file, "message": error})
+            );
+        }
+        return;
+    }
+    eprintln!("{} of {} files failed:", failed.len(), total);
+    for (file, error) in failed {
+        eprintln!("  {}: {}", file, error);
+    }
+}
+
+fn main() -> ExitCode {
+    let arg = args::parse_args();
+    let json_warnings = arg.warnings_format == WarningsFormat::Json;
+    JSON_WARNINGS.store(json_warnings, Ordering::Relaxed);
+    let status = match run(arg) {
+        Ok(status) => status.into(),
+        Err(err) => {
+            if json_warnings {
+                report("error", None, format_args!("{:#}", err));
             } else {
-                None
+                eprintln!("Error: {:?}", err);
             }
-        }),
+            Status::of(&err).into()
+        }
+    };
+    if json_warnings {
+        eprintln!(
+            "{}",
+            serde_json::json!({"level": "summary", "warnings": WARNINGS.load(Ordering::Relaxed)})
+        );
     }
+    status
 }
 
-fn main() -> anyhow::Result<()> {
-    let arg = args::parse_args();
-    let mode = match arg.mode {
+/// Runs the command, then writes the output's checksum if it was asked for
+fn run(arg: Args) -> anyhow::Result<Status> {
+    if arg.emit_checksum && (arg.output_template.is_some() || arg.check || arg.has.is_some()) {
+        return Err(exit::usage(
+            "--emit-checksum needs a single output file, which --output-template, --check and --has don't write",
+        ));
+    }
+    let status = dispatch(&arg)?;
+    if arg.emit_checksum {
+        write_checksum(&arg)?;
+    }
+    Ok(status)
+}
+
+fn dispatch(arg: &Args) -> anyhow::Result<Status> {
+    if cfg!(not(unix)) && arg.file_mode.is_some() {
+        warn(format_args!("--file-mode has no effect on this platform"));
+    }
+    if arg.self_test {
+        let mut output_writer = BufWriter::new(Output::create(None, None)?);
+        let passed = selftest::run(&mut output_writer).context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(if passed {
+            Status::Success
+        } else {
+            Status::ConversionError
+        });
+    }
+    if arg.dump_types_table {
+        let mut output_writer = BufWriter::new(Output::create(None, None)?);
+        let passed =
+            selftest::types_table(&mut output_writer).context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(if passed {
+            Status::Success
+        } else {
+            Status::ConversionError
+        });
+    }
+    if let Some(assignment) = &arg.encode_single {
+        let mut output_writer = BufWriter::new(Output::create(None, None)?);
+        encode_single(arg, assignment, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if arg.encode_version_only {
+        let version = arg
+            .factorio_version
+            .ok_or_else(|| exit::usage("--factorio-version is required"))?;
+        let mut output_writer = BufWriter::new(Output::create(None, None)?);
+        factorio_settings::encode_writer(&ModSettings::empty(version), &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if !arg.kv.is_empty() {
+        let version = arg
+            .factorio_version
+            .ok_or_else(|| exit::usage("--factorio-version is required"))?;
+        let settings = setting::build_settings(version, &arg.kv)
+            .map_err(|err| exit::usage(format!("{:#}", err)))?;
+        let mut output_writer = BufWriter::new(Output::create(None, None)?);
+        factorio_settings::encode_writer(&settings, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if arg.count_bytes {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        count_bytes(arg, &mut input_reader, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if arg.inventory {
+        return inventory(arg);
+    }
+    if let Some(template) = &arg.output_template {
+        return decode_batch(arg, template);
+    }
+    if arg.list_mods {
+        return list_mods(arg);
+    }
+    if arg.schema.is_some() && !arg.schema_validate && !arg.lenient_bools {
+        return Err(exit::usage(
+            "--schema is only read by --schema-validate and --lenient-bools",
+        ));
+    }
+    if arg.passthrough {
+        let mut input_reader = limit_input(arg, open_input(arg)?)?;
+        if arg.check {
+            // the output isn't opened at all, so it's left untouched even if it's the input
+            return check_passthrough(arg, &mut input_reader);
+        }
+        let encoded = passthrough(arg, &mut read_all(&mut input_reader)?.as_slice())?;
+        let mut output_writer = create_output(arg)?;
+        output_writer
+            .write_all(&encoded)
+            .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if let Some(path) = &arg.has {
+        // nothing is written, so the output isn't opened
+        let mut input_reader = limit_input(arg, open_input(arg)?)?;
+        return Ok(if has_setting(arg, path, &mut input_reader)? {
+            Status::Success
+        } else {
+            Status::ConversionError
+        });
+    }
+    if let Some(path) = &arg.get {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let settings = read_input_settings(arg, &mut input_reader)?;
+        let setting = simple::section(&settings, path.section)
+            .get(&path.key)
+            .ok_or_else(|| anyhow::anyhow!("The input doesn't contain {}", path))?;
+        writeln!(
+            output_writer,
+            "{}",
+            setting::raw_value(&setting.value, arg.color_format)
+        )
+        .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if let Some(assignment) = &arg.set {
+        set_value(arg, assignment)?;
+        return Ok(Status::Success);
+    }
+    if let Some(path) = &arg.extract_bytes {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        extract_bytes(arg, path, &mut input_reader, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if let (true, Some(target)) = (arg.compat_check, arg.target_version) {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let settings = read_input_settings(arg, &mut input_reader)?;
+        let findings = compat::check(&settings, target);
+        for finding in &findings {
+            writeln!(output_writer, "{}", finding).context(IoError("Writing output"))?;
+        }
+        finish_output(output_writer)?;
+        return Ok(if findings.is_empty() {
+            Status::Success
+        } else {
+            Status::DiffFound
+        });
+    }
+    if let Some(query) = &arg.find_value {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let settings = read_input_settings(arg, &mut input_reader)?;
+        let found = setting::find_value(&settings, query);
+        match arg.format {
+            Some(Format::Json) => {
+                let paths = found.iter().map(ToString::to_string).collect::<Vec<_>>();
+                writeln!(output_writer, "{}", serialize(Format::Json, &paths)?)
+            }
+            Some(Format::Toml | Format::Ndjson | Format::Html) => {
+                return Err(exit::usage("--find-value can only be formatted as JSON"))
+            }
+            None => found
+                .iter()
+                .try_for_each(|path| writeln!(output_writer, "{}", path)),
+        }
+        .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if arg.find_duplicates {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let settings = read_input_settings(arg, &mut input_reader)?;
+        let found = setting::find_duplicates(&settings);
+        match arg.format {
+            Some(Format::Json) => writeln!(output_writer, "{}", serialize(Format::Json, &found)?),
+            Some(Format::Toml | Format::Ndjson | Format::Html) => {
+                return Err(exit::usage(
+                    "--find-duplicates can only be formatted as JSON",
+                ))
+            }
+            None => found.iter().try_for_each(|group| {
+                writeln!(
+                    output_writer,
+                    "{}, {}:",
+                    group.section,
+                    diff::describe(group.setting)
+                )?;
+                group
+                    .keys
+                    .iter()
+                    .try_for_each(|key| writeln!(output_writer, "  {}", key))
+            }),
+        }
+        .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if let Some(other) = &arg.diff {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let status = diff_settings(arg, other, &mut input_reader, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(status);
+    }
+    if arg.dot {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let decoded = read_settings(arg, &mut input_reader)?;
+        output_writer
+            .write_all(dot::render(&decoded.properties).as_bytes())
+            .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if arg.length_stats {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let options = decode_options(arg);
+        read_settings_with(arg, &mut input_reader, &options)?;
+        let lengths = &options.lengths;
+        writeln!(
+            output_writer,
+            "1-byte lengths: {}\n5-byte lengths: {}\n5-byte lengths below 255: {}",
+            lengths.short.get(),
+            lengths.long.get(),
+            lengths.long_but_short.get()
+        )
+        .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if let (true, Some(path)) = (arg.schema_validate, &arg.schema) {
+        let expected = read_schema(path)?;
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let settings = read_input_settings(arg, &mut input_reader)?;
+        let actual = transform::schema(&codec::Settings::from(settings).properties);
+        let violations = transform::schema_violations(&expected, &actual);
+        match arg.format {
+            Some(Format::Json) => {
+                writeln!(output_writer, "{}", serialize(Format::Json, &violations)?)
+            }
+            Some(Format::Toml | Format::Ndjson | Format::Html) => {
+                return Err(exit::usage(
+                    "--schema-validate can only be formatted as JSON",
+                ))
+            }
+            None => violations
+                .iter()
+                .try_for_each(|violation| writeln!(output_writer, "{}", violation)),
+        }
+        .context(IoError("Writing output"))?;
+        finish_output(output_writer)?;
+        return Ok(if violations.is_empty() {
+            Status::Success
+        } else {
+            Status::ConversionError
+        });
+    }
+    if arg.lint {
+        let (mut input_reader, mut output_writer) = open_streams(arg)?;
+        let (settings, sources) = if arg.source_lines {
+            let data = read_all(&mut input_reader)?;
+            let sources = source_map(input_text_format(arg), &data);
+            (read_input_settings(arg, &mut data.as_slice())?, sources)
+        } else {
+            (read_input_settings(arg, &mut input_reader)?, None)
+        };
+        let status = match settings.validate() {
+            Ok(()) => Status::Success,
+            Err(errors) => {
+                for error in errors {
+                    let cited = cite(sources.as_ref(), error.section, &error.key);
+                    writeln!(output_writer, "{}{}", error, cited)
+                        .context(IoError("Writing output"))?;
+                }
+                Status::ConversionError
+            }
+        };
+        finish_output(output_writer)?;
+        return Ok(status);
+    }
+    if arg.input().is_dir() {
+        if !matches!(arg.mode, None | Some(Mode::Encode)) || arg.kind == Kind::MapSettings {
+            return Err(exit::usage(
+                "A directory of section files can only be encoded, as mod settings",
+            ));
+        }
+        let settings = read_section_dir(arg, arg.input())?;
+        let mut output_writer =
+            BufWriter::new(Output::create(arg.output.as_deref(), arg.file_mode)?);
+        encode_settings(arg, settings, None, &mut output_writer)?;
+        finish_output(output_writer)?;
+        return Ok(Status::Success);
+    }
+    if !arg.overlay.is_empty() && arg.mode.is_none() && binary_input(arg) && binary_output(arg) {
+        merge_binary(arg)?;
+        return Ok(Status::Success);
+    }
+    // When the arguments don't say what the input is, its first bytes are sniffed. The input is
+    // opened for that, and kept for the conversion.
+    let mut input = None;
+    let mut sniffed = None;
+    let mut sniff = |arg: &Args| -> anyhow::Result<Option<Format>> {
+        if let Some(format) = sniffed {
+            return Ok(format);
+        }
+        let head = input
+            .insert(open_input(arg)?)
+            .head(input::SNIFF_LEN)
+            .context(IoError("Reading input"))?;
+        if head.is_empty() {
+            return Err(exit::usage(
+                "Unable to infer the format of empty input from arguments",
+            ));
+        }
+        Ok(*sniffed.insert(input::sniff_format(&head)))
+    };
+    let mode = match arg.mode.or_else(|| infer_args_mode(arg)) {
         Some(mode) => mode,
+        None if arg.input.is_some() || arg.clipboard => match sniff(arg)? {
+            Some(_) => Mode::Encode,
+            None => Mode::Decode,
+        },
+        None => return Err(exit::usage("Unable to infer mode from arguments")),
+    };
+    if arg.input_format == Some(SideFormat::Dat) && !matches!(mode, Mode::Decode) {
+        return Err(exit::usage("--input-format dat only applies when decoding"));
+    }
+    if arg.output_format == Some(SideFormat::Dat) && !matches!(mode, Mode::Encode) {
+        return Err(exit::usage(
+            "--output-format dat only applies when encoding",
+        ));
+    }
+    let infer_failed = || exit::usage("Unable to infer format from arguments");
+    let mut input_format = |arg: &Args| -> anyhow::Result<Format> {
+        match input_text_format(arg) {
+            Some(format) => Ok(format),
+            None if arg.inline_binary() => Err(infer_failed()),
+            None => sniff(arg)?.ok_or_else(|| {
+                exit::usage("The input looks like binary settings, not text; did you mean to decode? (--mode decode)")
+            }),
+        }
+    };
+    let (input_format, output_format) = match mode {
+        Mode::Encode => (Some(input_format(arg)?), None),
+        Mode::Decode => (
+            None,
+            Some(output_text_format(arg).ok_or_else(infer_failed)?),
+        ),
+        Mode::Convert => {
+            if arg.format.is_some() {
+                return Err(exit::usage(
+                    "--format is ambiguous when converting between text formats; use --input-format and --output-format",
+                ));
+            }
+            (
+                Some(input_format(arg)?),
+                Some(output_text_format(arg).ok_or_else(infer_failed)?),
+            )
+        }
+    };
+    if output_format == Some(Format::Ndjson) {
+        return Err(exit::usage(NDJSON_INPUT_ONLY));
+    }
+    if input_format == Some(Format::Html) {
+        return Err(exit::usage(HTML_OUTPUT_ONLY));
+    }
+    if arg.reproducible && (input_format.is_some() || output_format != Some(Format::Json)) {
+        return Err(exit::usage(
+            "--reproducible only applies when decoding to JSON",
+        ));
+    }
+    if arg.vcs
+        && (input_format.is_some() || !matches!(output_format, Some(Format::Json | Format::Toml)))
+    {
+        return Err(exit::usage(
+            "--vcs only applies when decoding to JSON or TOML",
+        ));
+    }
+    if arg.toml_section_counts && output_format != Some(Format::Toml) {
+        return Err(exit::usage(
+            "--toml-section-counts only applies to TOML output",
+        ));
+    }
+    if arg.omit_none && output_format.is_none() {
+        return Err(exit::usage(
+            "--omit-none only applies to text output; encoding always keeps None values",
+        ));
+    }
+    if arg.size_report && (input_format.is_some() || output_format.is_none()) {
+        return Err(exit::usage("--size-report only applies when decoding"));
+    }
+    if arg.trim_strings && output_format.is_some() {
+        return Err(exit::usage("--trim-strings only applies when encoding"));
+    }
+    let input = match input {
+        Some(input) => input,
+        None => open_input(arg)?,
+    };
+    let (mut input_reader, mut output_writer) = streams(arg, input)?;
+
+    match (input_format, output_format) {
+        (Some(input_format), Some(output_format)) => convert(
+            arg,
+            input_format,
+            output_format,
+            &mut input_reader,
+            &mut output_writer,
+        )?,
+        (Some(format), None) => encode(arg, format, &mut input_reader, &mut output_writer)?,
+        (None, Some(format)) => decode(arg, format, &mut input_reader, &mut output_writer)?,
+        (None, None) => unreachable!("every mode has a text side"),
+    }
+    finish_output(output_writer)?;
+
+    Ok(Status::Success)
+}
+
+/// Writes the SHA-256 of the written output to OUTPUT.sha256, in the format `sha256sum -c` reads
+fn write_checksum(arg: &Args) -> anyhow::Result<()> {
+    let output = match arg.set {
+        Some(_) => set_output(arg),
+        None => arg.output.as_deref(),
+    };
+    let Some(path) = output.filter(|path| path.is_file()) else {
+        warn(format_args!(
+            "--emit-checksum only applies when writing to a file, so no checksum was written"
+        ));
+        return Ok(());
+    };
+    let data = std::fs::read(path)
+        .with_context(|| format!("Reading {}", path.display()))
+        .context(IoError("Reading output for its checksum"))?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Output path has no file name"))?;
+    let mut checksum_path = path.as_os_str().to_owned();
+    checksum_path.push(".sha256");
+    let mut output_writer = BufWriter::new(Output::create(
+        Some(Path::new(&checksum_path)),
+        arg.file_mode,
+    )?);
+    writeln!(
+        output_writer,
+        "{}  {}",
+        sha256::hex_digest(&data),
+        name.to_string_lossy()
+    )
+    .context(IoError("Writing checksum"))?;
+    finish_output(output_writer)
+}
+
+type InputReader = BufReader<SizeLimit<Input>>;
+type OutputWriter = BufWriter<Output>;
+
+fn open_streams(arg: &Args) -> anyhow::Result<(InputReader, OutputWriter)> {
+    streams(arg, open_input(arg)?)
+}
+
+/// Opens the input, decompressing it if it's gzip-compressed
+fn open_input(arg: &Args) -> anyhow::Result<Input> {
+    let max_size = arg.max_input_size.unwrap_or(u64::MAX);
+    let input = if arg.clipboard {
+        Input::clipboard()
+    } else if arg.hex {
+        Input::hex(arg.input(), max_size)
+    } else if arg.hexdump {
+        Input::hexdump(arg.input(), max_size)
+    } else if arg.base64 {
+        Input::base64(arg.input(), max_size)
+    } else {
+        Input::open(arg.input())
+    }?;
+    input.decompressed().context(IoError("Reading input"))
+}
+
+/// Limits the input to --max-input-size and creates the output
+fn streams(arg: &Args, input: Input) -> anyhow::Result<(InputReader, OutputWriter)> {
+    Ok((limit_input(arg, input)?, create_output(arg)?))
+}
+
+/// Creates the output, compressed if --gzip or its path asks for that
+fn create_output(arg: &Args) -> anyhow::Result<OutputWriter> {
+    let mut output = Output::create(arg.output.as_deref(), arg.file_mode)?;
+    if gzip_output(arg) {
+        output = output.compressed();
+    }
+    Ok(BufWriter::new(output))
+}
+
+/// Limits the input to --max-input-size
+fn limit_input(arg: &Args, input: Input) -> anyhow::Result<InputReader> {
+    let max_size = arg.max_input_size.unwrap_or(u64::MAX);
+    if let Some(len) = input.len().filter(|len| *len > max_size) {
+        anyhow::bail!(
+            "Input is {} bytes, larger than the maximum of {} bytes",
+            len,
+            max_size
+        );
+    }
+    Ok(BufReader::new(SizeLimit::new(input, max_size)))
+}
+
+fn finish_output(writer: OutputWriter) -> anyhow::Result<()> {
+    writer
+        .into_inner()
+        .map_err(|err| err.into_error())
+        .context(IoError("Writing output"))?
+        .commit()
+}
+
+/// Decodes the binary settings, honoring the recovery options
+fn read_settings(arg: &Args, reader: &mut impl Read) -> anyhow::Result<codec::Settings> {
+    read_settings_with(arg, reader, &decode_options(arg))
+}
+
+fn decode_options(arg: &Args) -> codec::DecodeOptions {
+    codec::DecodeOptions {
+        strings: arg.string_encoding.into(),
+        // with --assume-version, decoding begins at the body offset
+        position: arg.assume_version.map_or(0, |_| arg.body_offset).into(),
+        deadline: arg
+            .timeout
+            .map(|ms| Instant::now() + Duration::from_millis(ms)),
+        ..Default::default()
+    }
+}
+
+fn read_settings_with(
+    arg: &Args,
+    reader: &mut impl Read,
+    options: &codec::DecodeOptions,
+) -> anyhow::Result<codec::Settings> {
+    let settings = match arg.assume_version {
+        Some(version) => {
+            let skipped = std::io::copy(&mut reader.take(arg.body_offset), &mut std::io::sink())
+                .context(IoError("Reading input"))?;
+            if skipped != arg.body_offset {
+                anyhow::bail!("Input ended before offset {}", arg.body_offset);
+            }
+            warn(format_args!(
+                "output is reconstructed, assuming version {} and skipping {} bytes",
+                version, arg.body_offset
+            ));
+            codec::Settings::from_body_reader(reader, version, options)
+        }
+        None => codec::Settings::from_reader_with(reader, options),
+    }
+    .context("Decoding settings")?;
+    if arg.max_depth_report {
+        let depth = options.max_depth.get();
+        report_values("max-depth", serde_json::json!({ "depth": depth }), depth);
+    }
+    if options.transcoded.get() > 0 {
+        warn(format_args!(
+            "{} strings weren't valid UTF-8 and were read {}; encoding them again won't reproduce the original bytes",
+            options.transcoded.get(),
+            match options.strings {
+                StringEncoding::Latin1 => "as Latin-1",
+                _ => "with replacement characters",
+            }
+        ));
+    }
+    Ok(settings)
+}
+
+fn encode_single(
+    arg: &Args,
+    assignment: &setting::Assignment,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let version = arg
+        .factorio_version
+        .ok_or_else(|| exit::usage("--factorio-version is required"))?;
+    let mut simple = ModSettings::empty(version);
+    simple::section_mut(&mut simple, assignment.path.section)
+        .insert(assignment.path.key.clone(), assignment.value.clone().into());
+    let settings = codec::Settings::from_simple(&simple);
+
+    let mut encoded = Vec::new();
+    settings
+        .encode_to_writer(&mut encoded)
+        .context("Encoding settings")?;
+    writeln!(writer, "{}", explain::hex(&encoded)).context(IoError("Writing output"))?;
+    if arg.explain {
+        writeln!(writer).context(IoError("Writing output"))?;
+        explain::write_annotations(&explain::explain_settings(&settings)?, writer)
+            .context(IoError("Writing output"))?;
+    }
+    Ok(())
+}
+
+/// Where --set writes: the output if there is one, or else back to the input file
+fn set_output(arg: &Args) -> Option<&Path> {
+    match &arg.output {
+        Some(path) => Some(path.as_path()),
+        None if arg.input() == Path::new("-") || arg.inline_binary() => None,
+        None => Some(arg.input()),
+    }
+}
+
+/// Changes one setting of the binary input and encodes the settings again, to OUTPUT or in place
+fn set_value(arg: &Args, assignment: &setting::Assignment) -> anyhow::Result<()> {
+    let input = open_input(arg)?;
+    // a compressed file is written back compressed
+    let compress = gzip_output(arg) || input.is_compressed();
+    let decoded = read_settings(arg, &mut limit_input(arg, input)?)?;
+    let mut settings = ModSettings::try_from(&decoded).context("Converting format")?;
+    drop(decoded);
+
+    let path = &assignment.path;
+    let section = simple::section_mut(&mut settings, path.section);
+    match section.get_mut(&path.key) {
+        Some(setting) => setting.value = assignment.value_like(&setting.value),
+        None if arg.create => {
+            section.insert(path.key.clone(), assignment.value.clone().into());
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "The input doesn't contain {}; add --create to add it",
+                path
+            ))
+        }
+    }
+
+    // the output replaces the input only when it's committed, after it has been read
+    let mut output = Output::create(set_output(arg), arg.file_mode)?;
+    if compress {
+        output = output.compressed();
+    }
+    let mut output_writer = BufWriter::new(output);
+    factorio_settings::encode_writer(&settings, &mut output_writer)?;
+    finish_output(output_writer)
+}
+
+/// Whether the arguments say the input is binary settings
+fn binary_input(arg: &Args) -> bool {
+    match arg.input_format {
+        Some(format) => format == SideFormat::Dat,
+        None => arg.inline_binary() || extension_is(arg.input(), "dat"),
+    }
+}
+
+/// Whether the arguments say the output is binary settings
+fn binary_output(arg: &Args) -> bool {
+    match arg.output_format {
+        Some(format) => format == SideFormat::Dat,
+        None => arg
+            .output
+            .as_deref()
+            .is_some_and(|path| extension_is(path, "dat")),
+    }
+}
+
+/// Applies the --overlay files to binary settings, writing binary settings with the result
+fn merge_binary(arg: &Args) -> anyhow::Result<()> {
+    let (mut input_reader, mut output_writer) = open_streams(arg)?;
+    let decoded = read_settings(arg, &mut input_reader)?;
+    let settings = ModSettings::try_from(&decoded).context("Converting format")?;
+    drop(decoded);
+    encode_settings(arg, settings, None, &mut output_writer)?;
+    finish_output(output_writer)
+}
+
+/// Writes the encoding of one setting's property subtree from the binary input: as hex with any
+/// annotations on stdout, or raw to an output file
+fn extract_bytes(
+    arg: &Args,
+    path: &setting::SettingPath,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let decoded = read_settings(arg, reader)?;
+    let property = decoded
+        .properties
+        .value
+        .as_dictionary()
+        .and_then(|root| root.get(path.section))
+        .and_then(|section| section.value.as_dictionary())
+        .and_then(|section| section.get(&path.key))
+        .ok_or_else(|| anyhow::anyhow!("The input has no setting {}", path))?;
+    let mut encoded = Vec::new();
+    property.encode(&mut encoded).context("Encoding setting")?;
+    if arg.output.is_some() {
+        return writer
+            .write_all(&encoded)
+            .context(IoError("Writing output"));
+    }
+    writeln!(writer, "{}", explain::hex(&encoded)).context(IoError("Writing output"))?;
+    if arg.explain {
+        writeln!(writer).context(IoError("Writing output"))?;
+        explain::write_annotations(&explain::explain_property(property)?, writer)
+            .context(IoError("Writing output"))?;
+    }
+    Ok(())
+}
+
+/// Compares the input against another settings file, returning whether they differ
+fn diff_settings(
+    arg: &Args,
+    other: &Path,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> anyhow::Result<Status> {
+    let old = read_input_settings(arg, reader)?;
+    let new = read_settings_file(other).context("Reading the settings to compare against")?;
+    let differences = diff::diff(&old, &new);
+    match arg.diff_format {
+        DiffFormat::Summary => diff::write_summary(&differences, writer),
+        DiffFormat::Json => writeln!(
+            writer,
+            "{}",
+            serialize(Format::Json, &diff::patch(&differences))?
+        ),
+        DiffFormat::Unified => {
+            let format = arg.format.unwrap_or(Format::Toml);
+            diff::write_unified(
+                &serialize(format, &old)?,
+                &serialize(format, &new)?,
+                (
+                    &arg.input().display().to_string(),
+                    &other.display().to_string(),
+                ),
+                writer,
+            )
+        }
+    }
+    .context(IoError("Writing output"))?;
+    Ok(if differences.is_empty() {
+        Status::Success
+    } else {
+        Status::DiffFound
+    })
+}
+
+/// Re-encodes the raw property tree, which reproduces any file the codec can read
+fn passthrough(arg: &Args, reader: &mut impl Read) -> anyhow::Result<Vec<u8>> {
+    let decoded = read_settings(arg, reader)?;
+    let mut encoded = Vec::new();
+    decoded
+        .encode_to_writer(&mut encoded)
+        .context("Encoding settings")?;
+    Ok(encoded)
+}
+
+/// Reports whether re-encoding with --passthrough would change the input, without writing anything
+fn check_passthrough(arg: &Args, reader: &mut impl Read) -> anyhow::Result<Status> {
+    let data = read_all(reader)?;
+    Ok(if passthrough(arg, &mut data.as_slice())? == data {
+        Status::Success
+    } else {
+        notice(None, format_args!("Encoding changes the file"));
+        Status::DiffFound
+    })
+}
+
+/// Writes a summary of every file the input names. Files that can't be decoded are listed with
+/// their error, and make the status a conversion error.
+fn inventory(arg: &Args) -> anyhow::Result<Status> {
+    let entries = inventory::collect(arg.input(), arg.string_encoding.into(), arg.fail_fast)?;
+    let mut output_writer = BufWriter::new(Output::create(arg.output.as_deref(), arg.file_mode)?);
+    match arg.format {
+        None => inventory::write_table(&entries, &mut output_writer)
+            .context(IoError("Writing output"))?,
+        Some(Format::Json) => {
+            write_document(arg, &serialize(Format::Json, &entries)?, &mut output_writer)?
+        }
+        Some(_) => return Err(exit::usage("--inventory can only be formatted as JSON")),
+    }
+    finish_output(output_writer)?;
+    let failed = entries
+        .iter()
+        .filter_map(|entry| Some((entry.file.to_string(), entry.error()?.to_string())))
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        return Ok(Status::Success);
+    }
+    report_failures(&failed, entries.len());
+    Ok(Status::ConversionError)
+}
+
+/// Decodes each file matched by the input to the path --output-template gives it
+fn decode_batch(arg: &Args, template: &batch::OutputTemplate) -> anyhow::Result<Status> {
+    let format = match arg.output_format {
+        Some(format) => format.text(),
+        None => arg.format,
+    };
+    let format = format.ok_or_else(|| {
+        exit::usage("--output-template needs --format to choose what each file is decoded to")
+    })?;
+    let files = inventory::files(arg.input())?;
+    let date = batch::today();
+    let mut written = std::collections::HashMap::new();
+    let mut failed = Vec::new();
+    for file in &files {
+        match decode_batch_file(arg, template, format, &date, file, &mut written) {
+            Ok(path) if arg.verbose => notice(
+                None,
+                format_args!("{} -> {}", file.display(), path.display()),
+            ),
+            Ok(_) => {}
+            Err(err) if arg.fail_fast => {
+                return Err(err.context(format!("Decoding {}", file.display())))
+            }
+            Err(err) => failed.push((file.display().to_string(), format!("{:#}", err))),
+        }
+    }
+    if failed.is_empty() {
+        return Ok(Status::Success);
+    }
+    report_failures(&failed, files.len());
+    Ok(Status::ConversionError)
+}
+
+/// Decodes one file of a batch, returning the path it was written to. A path already written for
+/// an earlier file of the batch is an error rather than being overwritten. Files are opened as
+/// the main input is, so compressed and zipped settings are read too.
+fn decode_batch_file<'a>(
+    arg: &Args,
+    template: &batch::OutputTemplate,
+    format: Format,
+    date: &str,
+    file: &'a Path,
+    written: &mut std::collections::HashMap<std::path::PathBuf, &'a Path>,
+) -> anyhow::Result<std::path::PathBuf> {
+    let input = Input::open(file)?
+        .decompressed()
+        .context(IoError("Reading input file"))?;
+    let data = read_all(&mut limit_input(arg, input)?)?;
+    let version = match arg.assume_version {
+        _ if !template.uses_version() => None,
+        Some(version) => Some(version),
         None => {
-            infer_args_mode(&arg).ok_or(anyhow::anyhow!("Unable to infer mode from arguments"))?
+            let decoded =
+                codec::Settings::from_reader(&mut data.as_slice()).context("Decoding settings")?;
+            Some(decoded.version)
         }
     };
-    let format = match arg.format {
-        Some(format) => format,
-        None => infer_args_format(&arg, &mode)
-            .ok_or(anyhow::anyhow!("Unable to infer format from arguments"))?,
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let path = arg
+        .output
+        .as_deref()
+        .unwrap_or(Path::new(""))
+        .join(template.expand(&batch::Fields {
+            stem: &stem,
+            version,
+            format: format.extension(),
+            date,
+        }));
+    if let Some(earlier) = written.get(&path) {
+        anyhow::bail!(
+            "{} was already written for {}; add {{stem}} to the template",
+            path.display(),
+            earlier.display()
+        );
+    }
+    let serialized = decode_settings(arg, format, &mut data.as_slice())?;
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {}", parent.display()))
+            .context(IoError("Writing output"))?;
+    }
+    let mut output_writer = BufWriter::new(Output::create(Some(&path), arg.file_mode)?);
+    write_document(arg, &serialized, &mut output_writer)?;
+    finish_output(output_writer)?;
+    // only a file that was written claims its path, so a failure doesn't fail later files too
+    written.insert(path.clone(), file);
+    Ok(path)
+}
+
+fn count_bytes(arg: &Args, reader: &mut impl Read, writer: &mut impl Write) -> anyhow::Result<()> {
+    let options = profile::ByteProfile::counting(decode_options(arg));
+    read_settings_with(arg, reader, &options)?;
+    profile::ByteProfile::of(options)
+        .write_table(writer)
+        .context(IoError("Writing output"))
+}
+
+fn decode(
+    arg: &Args,
+    format: Format,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let serialized = match &arg.cache_dir {
+        // the statistics, depth report and spellcheck warnings describe the decoding itself, and
+        // defaults come from a whole mods directory, so these bypass the cache
+        Some(dir)
+            if !arg.stats
+                && !arg.size_report
+                && !arg.max_depth_report
+                && arg.spellcheck.is_none()
+                && arg.resolve_defaults.is_none() =>
+        {
+            decode_cached(arg, format, &Cache::new(dir), reader)?
+        }
+        _ => decode_settings(arg, format, reader)?,
+    };
+    write_document(arg, &serialized, writer)
+}
+
+/// Decoded output as the cache stores it, with the messages printed while decoding
+#[derive(Serialize, Deserialize)]
+struct CachedDecode {
+    messages: Vec<Recorded>,
+    output: String,
+}
+
+/// Decodes through the cache, which holds output for the options that affect it
+fn decode_cached(
+    arg: &Args,
+    format: Format,
+    cache: &Cache,
+    reader: &mut impl Read,
+) -> anyhow::Result<String> {
+    let data = read_all(reader)?;
+    let options = format!(
+        "{} {:?} {:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        format,
+        overlay_contents(arg)?,
+        cache_options(arg)
+    );
+    let key = Cache::key(&data, &options);
+    let cached = cache
+        .get(&key)
+        .and_then(|cached| serde_json::from_slice::<CachedDecode>(&cached).ok());
+    if let Some(cached) = cached {
+        for Recorded {
+            level,
+            key,
+            message,
+        } in cached.messages
+        {
+            match level.as_str() {
+                "warn" => warn_setting(key.as_deref(), format_args!("{}", message)),
+                _ => notice(key.as_deref(), format_args!("{}", message)),
+            }
+        }
+        return Ok(cached.output);
+    }
+
+    *RECORDED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+    let serialized = decode_settings(arg, format, &mut data.as_slice());
+    let messages = RECORDED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default();
+    let entry = CachedDecode {
+        messages,
+        output: serialized?,
     };
-    let mut input_reader = if matches!(arg.input.to_str(), Some("-")) {
-        BufReader::new(Either::Left(std::io::stdin().lock()))
+    let stored = serde_json::to_vec(&entry)
+        .map_err(std::io::Error::from)
+        .and_then(|value| cache.put(&key, &value));
+    if let Err(err) = stored {
+        warn(format_args!(
+            "couldn't store the decoded output in the cache: {}",
+            err
+        ));
+    }
+    Ok(entry.output)
+}
+
+/// The options as they affect decoded output, for keying the cache. Every option is kept except
+/// those saying where the input and output are and how they're written, so an option added later
+/// can't be left out of the key by mistake.
+fn cache_options(arg: &Args) -> Args {
+    Args {
+        input: None,
+        output: None,
+        cache_dir: None,
+        file_mode: None,
+        gzip: false,
+        no_trailing_newline: false,
+        emit_checksum: false,
+        warnings_format: WarningsFormat::Text,
+        ..arg.clone()
+    }
+}
+
+fn decode_settings(arg: &Args, format: Format, reader: &mut impl Read) -> anyhow::Result<String> {
+    let options = decode_options(arg);
+    let decoded = read_settings_with(arg, reader, &options)?;
+    if arg.schema_only {
+        return serialize_output(arg, format, &transform::schema(&decoded.properties));
+    }
+    if arg.kind == Kind::MapSettings {
+        return serialize_output(arg, format, &decoded);
+    }
+    let mut settings = simple::ModSettings::try_from(&decoded).context("Converting format")?;
+    if arg.size_report {
+        let sizes = size_report(arg, options.position.get(), &settings);
+        let by_format = sizes.iter().copied().collect::<IndexMap<_, _>>();
+        report_values(
+            "size-report",
+            serde_json::json!({ "bytes": by_format }),
+            size_table(&sizes).trim_end(),
+        );
+    }
+    apply_overlays(arg, &mut settings)?;
+    spellcheck(arg, &settings, None)?;
+    if arg.stats {
+        let stats = simple::ConversionStats::of(&settings, WARNINGS.load(Ordering::Relaxed));
+        report_values("stats", &stats, &stats);
+    }
+    if arg.omit_none {
+        transform::omit_none(&mut settings);
+    }
+    warn_lossy_doubles(format, &settings);
+    if let Some(path) = &arg.resolve_defaults {
+        if format == Format::Html {
+            return Err(exit::usage("--resolve-defaults can't be written as HTML"));
+        }
+        let defaults = if path.is_dir() {
+            defaults::Defaults::from_mods_dir(path)?
+        } else {
+            defaults::Defaults::from_settings(
+                &read_settings_file(path).context("Reading default settings")?,
+            )
+        };
+        for (zip, err) in &defaults.skipped {
+            warn(format_args!(
+                "Couldn't read zipped mod {}, so its defaults are unknown: {:#}",
+                zip.display(),
+                err
+            ));
+        }
+        let mut annotated = serde_json::to_value(&settings).context("Serializing settings")?;
+        let unknown = defaults.annotate(&mut annotated);
+        if unknown > 0 {
+            notice(
+                None,
+                format_args!("{} settings have no known default", unknown),
+            );
+        }
+        return serialize_output(arg, format, &annotated);
+    }
+    if arg.group_by_mod {
+        serialize_output(arg, format, &transform::group_by_mod(&settings))
+    } else if format == Format::Html {
+        Ok(html::render(&settings))
     } else {
-        BufReader::new(Either::Right(
-            File::open(arg.input).context("Opening input file")?,
-        ))
+        serialize_output(arg, format, &settings)
+    }
+}
+
+/// The size of the settings as read and in each text format, the binary size first. A format that
+/// can't hold the settings has no size. The text is written as it would be by default, with
+/// --toml-colors, since options that restyle it would make the formats harder to compare.
+fn size_report(
+    arg: &Args,
+    binary: u64,
+    settings: &ModSettings,
+) -> Vec<(&'static str, Option<usize>)> {
+    let default_style = Args {
+        color_format: ColorFormat::Object,
+        vcs: false,
+        reproducible: false,
+        toml_section_counts: false,
+        ..arg.clone()
+    };
+    // measured as they're written, with their ending
+    let written = |text: String| {
+        let mut written = Vec::new();
+        write_document(&default_style, &text, &mut written).ok()?;
+        Some(written.len())
+    };
+    let size = |format| {
+        serialize_output(&default_style, format, settings)
+            .ok()
+            .and_then(written)
+    };
+    vec![
+        ("binary", Some(binary as usize)),
+        ("json", size(Format::Json)),
+        (
+            "json compact",
+            serde_json::to_string(settings).ok().and_then(written),
+        ),
+        ("toml", size(Format::Toml)),
+    ]
+}
+
+/// A table of the sizes from [`size_report`], and how each compares to the encoding
+fn size_table(sizes: &[(&str, Option<usize>)]) -> String {
+    let binary = sizes.first().and_then(|(_, size)| *size).unwrap_or(0);
+    let mut table = format!("{:<14}{:>10}{:>12}\n", "format", "bytes", "vs binary");
+    for &(name, size) in sizes {
+        table.push_str(&match size {
+            Some(size) => format!(
+                "{:<14}{:>10}{:>11.2}x\n",
+                name,
+                size,
+                size as f64 / binary as f64
+            ),
+            // TOML can't hold every settings file
+            None => format!("{:<14}{:>10}{:>12}\n", name, "-", "-"),
+        });
+    }
+    table
+}
+
+/// Serializes settings for output, with colors in the form of --color-format, as compact JSON
+/// with sorted keys when --reproducible is given, with sorted keys when --vcs is, and with colors
+/// styled by --toml-colors in TOML
+fn serialize_output(arg: &Args, format: Format, value: &impl Serialize) -> anyhow::Result<String> {
+    let restyled = arg.color_format != ColorFormat::Object || arg.vcs || arg.reproducible;
+    let serialized = match format {
+        // TOML is restyled through its own value rather than JSON's, which can't hold doubles
+        // such as NaN
+        Format::Toml if restyled => {
+            let mut value = toml::Value::try_from(value).context(TOML_UNREPRESENTABLE)?;
+            warn_inexact_colors(transform::format_toml_colors(&mut value, arg.color_format));
+            if arg.vcs {
+                value = transform::sort_toml_keys(value);
+            }
+            serialize(format, &value)?
+        }
+        _ if restyled => {
+            let mut value = serde_json::to_value(value).context("Serializing settings")?;
+            warn_inexact_colors(transform::format_colors(&mut value, arg.color_format));
+            if arg.reproducible {
+                return Ok(transform::sort_keys(value).to_string());
+            }
+            if arg.vcs {
+                value = transform::sort_keys(value);
+            }
+            serialize(format, &value)?
+        }
+        _ => serialize(format, value)?,
     };
-    let mut output_writer = if let Some(output) = arg.output {
-        BufWriter::new(Either::Left(File::create(output)?))
+    let restyle_toml = arg.color_format == ColorFormat::Array
+        || arg.toml_colors == TomlColors::Inline
+        || arg.toml_section_counts;
+    if format != Format::Toml || !restyle_toml {
+        return Ok(serialized);
+    }
+    let mut document = serialized
+        .parse::<toml_edit::Document>()
+        .context("Restyling TOML")?;
+    match (arg.toml_colors, arg.color_format) {
+        (_, ColorFormat::Array) => inline_toml_arrays(document.as_table_mut()),
+        (TomlColors::Inline, _) => inline_toml_colors(document.as_table_mut()),
+        _ => {}
+    }
+    if arg.toml_section_counts {
+        count_toml_sections(&mut document);
+    }
+    Ok(document.to_string())
+}
+
+fn warn_inexact_colors(paths: Vec<String>) {
+    for path in paths {
+        warn_setting(
+            Some(&path),
+            format_args!(
+                "Color {} can't be written as hex without losing precision, so it's kept as an object",
+                path
+            ),
+        );
+    }
+}
+
+/// Puts a comment such as `# startup: 42 settings` above the first header of each section
+fn count_toml_sections(document: &mut toml_edit::Document) {
+    for section in [STARTUP, RUNTIME_GLOBAL, RUNTIME_PER_USER] {
+        let Some(settings) = document
+            .get_mut(section)
+            .and_then(toml_edit::Item::as_table_mut)
+        else {
+            continue;
+        };
+        let plural = if settings.len() == 1 { "" } else { "s" };
+        let comment = format!("# {}: {} setting{}\n", section, settings.len(), plural);
+        if let Some(header) = first_header(settings) {
+            let decor = header.decor_mut();
+            let prefix = decor
+                .prefix()
+                .and_then(|prefix| prefix.as_str())
+                .unwrap_or("");
+            decor.set_prefix(format!("{}{}", prefix, comment));
+        }
+    }
+}
+
+/// The table whose header comes first among this table and those inside it. A table holding
+/// only other tables has no header of its own, so it's usually one of those.
+fn first_header(table: &mut toml_edit::Table) -> Option<&mut toml_edit::Table> {
+    fn first_position(table: &toml_edit::Table) -> Option<usize> {
+        let own = table.position().filter(|_| !table.is_implicit());
+        let nested = table.iter().flat_map(|(_, item)| match item {
+            toml_edit::Item::Table(table) => vec![first_position(table)],
+            toml_edit::Item::ArrayOfTables(array) => array.iter().map(first_position).collect(),
+            _ => Vec::new(),
+        });
+        own.into_iter().chain(nested.flatten()).min()
+    }
+    fn find(table: &mut toml_edit::Table, position: usize) -> Option<&mut toml_edit::Table> {
+        if !table.is_implicit() && table.position() == Some(position) {
+            return Some(table);
+        }
+        for (_, item) in table.iter_mut() {
+            let found = match item {
+                toml_edit::Item::Table(table) => find(table, position),
+                toml_edit::Item::ArrayOfTables(array) => {
+                    array.iter_mut().find_map(|table| find(table, position))
+                }
+                _ => None,
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+    let position = first_position(table)?;
+    find(table, position)
+}
+
+/// Puts each array of numbers, which can only be a color in the array form, on one line instead
+/// of one line per channel
+fn inline_toml_arrays(table: &mut toml_edit::Table) {
+    for (_, item) in table.iter_mut() {
+        match item {
+            toml_edit::Item::Value(toml_edit::Value::Array(array))
+                if array
+                    .iter()
+                    .all(|item| item.is_float() || item.is_integer()) =>
+            {
+                array.fmt()
+            }
+            toml_edit::Item::Table(table) => inline_toml_arrays(table),
+            toml_edit::Item::ArrayOfTables(array) => array.iter_mut().for_each(inline_toml_arrays),
+            _ => {}
+        }
+    }
+}
+
+/// Moves each color's `value` table onto its setting as an inline table
+fn inline_toml_colors(table: &mut toml_edit::Table) {
+    let is_color = table.get("type").and_then(toml_edit::Item::as_str) == Some("Color");
+    for (mut key, item) in table.iter_mut() {
+        match item {
+            toml_edit::Item::Table(_) if is_color && key.get() == "value" => {
+                if let toml_edit::Item::Table(value) = std::mem::take(item) {
+                    let mut channels = value.into_inline_table();
+                    channels.fmt();
+                    // the key was part of a header, so it has no spacing for `key = value`
+                    key.fmt();
+                    *item = toml_edit::value(channels);
+                }
+            }
+            toml_edit::Item::Table(table) => inline_toml_colors(table),
+            toml_edit::Item::ArrayOfTables(array) => array.iter_mut().for_each(inline_toml_colors),
+            _ => {}
+        }
+    }
+}
+
+/// Writes serialized text output, ending in exactly one newline unless --no-trailing-newline is
+/// given. The serializers differ in whether they add one.
+fn write_document(arg: &Args, serialized: &str, writer: &mut impl Write) -> anyhow::Result<()> {
+    let body = serialized.trim_end_matches('\n');
+    let ending = if arg.no_trailing_newline { "" } else { "\n" };
+    writer
+        .write_all(body.as_bytes())
+        .and_then(|()| writer.write_all(ending.as_bytes()))
+        .context(IoError("Writing output"))
+}
+
+fn serialize(format: Format, value: &impl Serialize) -> anyhow::Result<String> {
+    Ok(match format {
+        // the TOML serializer already places tables after plain values, so this only fails for
+        // data TOML can't express at all
+        Format::Toml => toml::to_string_pretty(value).context(TOML_UNREPRESENTABLE)?,
+        Format::Json => serde_json::to_string_pretty(value).context("Serializing to JSON")?,
+        Format::Ndjson => return Err(exit::usage(NDJSON_INPUT_ONLY)),
+        Format::Html => return Err(exit::usage(HTML_OUTPUT_ONLY)),
+    })
+}
+
+/// Whether the input contains the setting. Binary input is scanned rather than decoded in full.
+fn has_setting(
+    arg: &Args,
+    path: &setting::SettingPath,
+    reader: &mut impl Read,
+) -> anyhow::Result<bool> {
+    let binary = matches!(arg.mode, Some(Mode::Decode))
+        || (arg.mode.is_none() && (arg.inline_binary() || extension_is(arg.input(), "dat")));
+    if binary && arg.assume_version.is_none() {
+        codec::has_setting(reader, path.section, &path.key).context("Scanning settings")
     } else {
-        BufWriter::new(Either::Right(std::io::stdout().lock()))
+        let settings = read_input_settings(arg, reader)?;
+        Ok(simple::section(&settings, path.section).contains_key(&path.key))
+    }
+}
+
+/// Lists the mods enabled in the mod-list.json beside the input, one per line or as a JSON array
+fn list_mods(arg: &Args) -> anyhow::Result<Status> {
+    if arg.input() == Path::new("-") || arg.inline_binary() {
+        return Err(exit::usage(
+            "--list-mods reads the mod-list.json beside the settings file, so INPUT must be its path",
+        ));
+    }
+    let mods = modlist::enabled_mods(&modlist::beside(arg.input()))?;
+    let mut output_writer = BufWriter::new(Output::create(arg.output.as_deref(), arg.file_mode)?);
+    match arg.format {
+        Some(Format::Json) => writeln!(output_writer, "{}", serialize(Format::Json, &mods)?),
+        Some(Format::Toml | Format::Ndjson | Format::Html) => {
+            return Err(exit::usage("--list-mods can only be formatted as JSON"))
+        }
+        None => mods
+            .iter()
+            .try_for_each(|name| writeln!(output_writer, "{}", name)),
+    }
+    .context(IoError("Writing output"))?;
+    finish_output(output_writer)?;
+    Ok(Status::Success)
+}
+
+/// Applies each --overlay file in order. With --verbose, reports which overlay last set each
+/// setting.
+fn apply_overlays(arg: &Args, settings: &mut ModSettings) -> anyhow::Result<()> {
+    let mut provenance = indexmap::IndexMap::new();
+    for path in &arg.overlay {
+        let overlay = read_settings_file(path).context("Reading overlay")?;
+        if arg.take_version_from_overlay {
+            settings.factorio_version = overlay.factorio_version;
+        }
+        for setting in transform::overlay(settings, overlay) {
+            provenance.insert(setting, path);
+        }
+    }
+    if arg.verbose {
+        for (setting, path) in provenance {
+            notice(
+                Some(&setting.to_string()),
+                format_args!("{}: set by {}", setting, path.display()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The overlays' contents, which affect decoded output just as the input does
+fn overlay_contents(arg: &Args) -> anyhow::Result<Vec<u64>> {
+    arg.overlay
+        .iter()
+        .map(|path| {
+            let data = std::fs::read(path).context(IoError("Reading overlay"))?;
+            Ok(cache::Cache::hash(&data))
+        })
+        .collect()
+}
+
+/// Warns about doubles that wouldn't read back identically from the text format
+fn warn_lossy_doubles(format: Format, settings: &ModSettings) {
+    let (name, alternative) = match format {
+        Format::Toml => ("TOML", "json"),
+        Format::Json | Format::Ndjson => ("JSON", "toml"),
+        // a page isn't read back
+        Format::Html => return,
     };
+    for (path, channel, value) in lossy_doubles(format, settings) {
+        let setting = path.to_string();
+        warn_setting(
+            Some(&setting),
+            format_args!(
+                "{}{} = {:?} won't read back exactly from {}; consider --format {}",
+                setting,
+                channel.map(|c| format!(".{}", c)).unwrap_or_default(),
+                value,
+                name,
+                alternative
+            ),
+        );
+    }
+}
 
+/// The doubles, including color channels, that don't survive serializing and deserializing,
+/// with the setting they belong to and the channel if they're part of a color
+fn lossy_doubles(
+    format: Format,
+    settings: &ModSettings,
+) -> Vec<(setting::SettingPath, Option<&'static str>, f64)> {
+    let mut lossy = Vec::new();
+    for (section, map) in settings.sections() {
+        for (key, setting) in map {
+            let mut check = |channel: Option<&'static str>, value: f64| {
+                if !double_round_trips(format, value) {
+                    let path = setting::SettingPath {
+                        section,
+                        key: key.clone(),
+                    };
+                    lossy.push((path, channel, value));
+                }
+            };
+            match setting.value {
+                simple::ModSettingsValue::Double(f) => check(None, f),
+                simple::ModSettingsValue::Color { r, g, b, a } => {
+                    for (channel, value) in [("r", r), ("g", g), ("b", b), ("a", a)] {
+                        check(Some(channel), value);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    lossy
+}
+
+fn double_round_trips(format: Format, value: f64) -> bool {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct Probe {
+        value: f64,
+    }
+    let read_back = serialize(format, &Probe { value })
+        .ok()
+        .and_then(|text| match format {
+            Format::Toml => toml::from_str::<Probe>(&text).ok(),
+            Format::Json | Format::Ndjson | Format::Html => {
+                serde_json::from_str::<Probe>(&text).ok()
+            }
+        });
+    read_back.is_some_and(|probe| {
+        probe.value.to_bits() == value.to_bits() || (probe.value.is_nan() && value.is_nan())
+    })
+}
+
+/// Reads the input as settings for modes that inspect it rather than convert it. Whether the
+/// input is binary or text is taken from the flags, or inferred from the input's extension.
+fn read_input_settings(arg: &Args, reader: &mut impl Read) -> anyhow::Result<ModSettings> {
+    let path = arg.input();
+    let text_format = input_text_format(arg);
+    let mode = arg
+        .mode
+        .or_else(|| (arg.inline_binary() || extension_is(path, "dat")).then_some(Mode::Decode))
+        .or_else(|| text_format.map(|_| Mode::Encode))
+        .ok_or_else(|| exit::usage("Unable to infer whether the input is binary or text"))?;
     match mode {
-        Mode::Encode => encode(format, &mut input_reader, &mut output_writer)?,
-        Mode::Decode => decode(format, &mut input_reader, &mut output_writer)?,
+        Mode::Decode => {
+            let decoded = read_settings(arg, reader)?;
+            ModSettings::try_from(&decoded).context("Converting format")
+        }
+        Mode::Encode | Mode::Convert => {
+            let format =
+                text_format.ok_or_else(|| exit::usage("Unable to infer format from arguments"))?;
+            deserialize_settings(format, reader)
+        }
     }
+}
+
+fn read_all(reader: &mut impl Read) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader
+        .read_to_end(&mut data)
+        .context(IoError("Reading input"))?;
+    Ok(data)
+}
+
+/// Maps where each setting is written in the input for --source-lines, which only TOML supports
+fn source_map(format: Option<Format>, data: &[u8]) -> Option<SourceMap> {
+    if format != Some(Format::Toml) {
+        warn(format_args!("--source-lines only applies to TOML input"));
+        return None;
+    }
+    std::str::from_utf8(data).ok().map(SourceMap::of_toml)
+}
 
+/// Warns about settings whose keys are a likely typo of a key in the --spellcheck reference
+fn spellcheck(
+    arg: &Args,
+    settings: &ModSettings,
+    sources: Option<&SourceMap>,
+) -> anyhow::Result<()> {
+    let Some(reference) = &arg.spellcheck else {
+        return Ok(());
+    };
+    let known = known_keys(reference)?;
+    for (path, suggestion) in setting::misspellings(settings, &known) {
+        warn_setting(
+            Some(&path.to_string()),
+            format_args!(
+                "{} isn't a known setting; did you mean {}?{}",
+                path,
+                suggestion,
+                cite(sources, path.section, &path.key)
+            ),
+        );
+    }
     Ok(())
 }
 
-fn decode(format: Format, reader: &mut impl Read, writer: &mut impl Write) -> anyhow::Result<()> {
-    let decoded = codec::Settings::from_reader(reader).context("Decoding settings")?;
-    let settings = simple::ModSettings::try_from(&decoded).context("Converting format")?;
-    let serialized = match format {
-        Format::Toml => toml::to_string_pretty(&settings).context("Serializing to TOML")?,
-        Format::Json => serde_json::to_string_pretty(&settings).context("Serializing to JSON")?,
+/// The setting keys in a mods directory, a settings file, or a text file listing one per line
+fn known_keys(path: &Path) -> anyhow::Result<indexmap::IndexSet<String>> {
+    if path.is_dir() {
+        let defaults = defaults::Defaults::from_mods_dir(path)?;
+        for (zip, err) in &defaults.skipped {
+            warn(format_args!(
+                "Couldn't read zipped mod {}, so it isn't spellchecked against: {:#}",
+                zip.display(),
+                err
+            ));
+        }
+        return Ok(defaults.values.into_keys().collect());
+    }
+    if extension_is(path, "dat") || format_of(path).is_some() {
+        let settings = read_settings_file(path).context("Reading spellcheck reference")?;
+        return Ok(settings
+            .sections()
+            .into_iter()
+            .flat_map(|(_, map)| map.keys().cloned())
+            .collect());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading {}", path.display()))
+        .context(IoError("Reading spellcheck reference"))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Where a setting is written in the input, for appending to a message about it
+fn cite(sources: Option<&SourceMap>, section: &'static str, key: &str) -> String {
+    sources
+        .and_then(|sources| sources.locate(section, key))
+        .map_or_else(String::new, |location| format!(" (at {})", location))
+}
+
+/// Assembles settings from a directory with a file per section, such as `startup.json` or
+/// `runtime-global.toml`, each holding that section's settings by key. A missing file is an empty
+/// section.
+fn read_section_dir(arg: &Args, dir: &Path) -> anyhow::Result<ModSettings> {
+    let version = arg.factorio_version.ok_or_else(|| {
+        exit::usage("--factorio-version is required to encode a directory of section files")
+    })?;
+    let mut settings = ModSettings::empty(version);
+    let formats = match input_text_format(arg) {
+        Some(format) => vec![format],
+        None => vec![Format::Json, Format::Toml],
     };
+    for (section, map) in settings.sections_mut() {
+        let files = formats
+            .iter()
+            .map(|format| {
+                (
+                    dir.join(format!("{}.{}", section, format.extension())),
+                    *format,
+                )
+            })
+            .filter(|(path, _)| path.is_file())
+            .collect::<Vec<_>>();
+        let (path, format) = match files.as_slice() {
+            [] => continue,
+            [file] => file,
+            _ => {
+                return Err(exit::usage(format!(
+                    "{} has more than one file for {}; choose one with --input-format",
+                    dir.display(),
+                    section
+                )))
+            }
+        };
+        let mut reader = BufReader::new(File::open(path).context(IoError("Opening section file"))?);
+        let entries: IndexMap<String, serde_json::Value> = deserialize(*format, &mut reader)
+            .with_context(|| format!("Reading {}", path.display()))?;
+        for (key, value) in entries {
+            if simple::section_name(&key).is_ok() {
+                anyhow::bail!(
+                    "{} contains the key {:?}, which names a section. Each file holds only its own section's settings",
+                    path.display(),
+                    key
+                );
+            }
+            let setting = serde_json::from_value(value)
+                .with_context(|| format!("Reading {}/{} from {}", section, key, path.display()))?;
+            map.insert(key, setting);
+        }
+    }
+    Ok(settings)
+}
 
-    writer
-        .write_all(serialized.as_bytes())
-        .context("Writing output")
+/// Reads a schema written by --schema-only as JSON or TOML
+fn read_schema(path: &Path) -> anyhow::Result<serde_json::Value> {
+    let format = format_of(path)
+        .filter(|format| matches!(format, Format::Json | Format::Toml))
+        .ok_or_else(|| {
+            exit::usage(format!(
+                "Unable to infer the format of schema {} from its extension",
+                path.display()
+            ))
+        })?;
+    let mut reader = BufReader::new(File::open(path).context(IoError("Opening schema file"))?);
+    deserialize(format, &mut reader).with_context(|| format!("Reading {}", path.display()))
+}
+
+/// Reads settings from a file in any supported format, chosen by its extension
+fn read_settings_file(path: &Path) -> anyhow::Result<ModSettings> {
+    let mut reader = BufReader::new(File::open(path).context(IoError("Opening settings file"))?);
+    if extension_is(path, "dat") {
+        let decoded = codec::Settings::from_reader(&mut reader)
+            .with_context(|| format!("Decoding {}", path.display()))?;
+        ModSettings::try_from(&decoded).context("Converting format")
+    } else {
+        let format = format_of(path).ok_or_else(|| {
+            exit::usage(format!(
+                "Unable to infer the format of {} from its extension",
+                path.display()
+            ))
+        })?;
+        deserialize_settings(format, &mut reader)
+            .with_context(|| format!("Reading {}", path.display()))
+    }
 }
 
-fn encode(format: Format, reader: &mut impl Read, writer: &mut impl Write) -> anyhow::Result<()> {
-    let mut data = String::new();
-    reader.read_to_string(&mut data).context("Reading stream")?;
-    let deserialized: ModSettings = match format {
-        Format::Toml => toml::from_str(&data).context("Deserializing TOML")?,
-        Format::Json => serde_json::from_str(&data).context("Deserializing JSON")?,
+/// Reads a document in a text format. JSON is parsed as it's read, so a large input isn't held in
+/// memory twice; TOML has no streaming parser and is read whole first. Only the first bytes are
+/// checked for binary input, and a UTF-8 byte order mark is skipped.
+fn deserialize<T: DeserializeOwned>(format: Format, reader: &mut impl Read) -> anyhow::Result<T> {
+    let mut reader = BufReader::new(reader);
+    let head = reader.fill_buf().context("Reading stream")?;
+    Compression::reject(head)?;
+    if codec::looks_encoded(head) {
+        return Err(exit::usage(
+            "This looks like an already-encoded .dat; did you mean to decode? (--mode decode)",
+        ));
+    }
+    if head.starts_with("\u{feff}".as_bytes()) {
+        reader.consume("\u{feff}".len());
+    }
+    Ok(match format {
+        Format::Toml => {
+            let mut data = String::new();
+            reader.read_to_string(&mut data).context("Reading stream")?;
+            toml::from_str(&data).context("Deserializing TOML")?
+        }
+        Format::Json => serde_json::from_reader(reader).context("Deserializing JSON")?,
+        Format::Ndjson => return Err(exit::usage(NDJSON_INPUT_ONLY)),
+        Format::Html => return Err(exit::usage(HTML_OUTPUT_ONLY)),
+    })
+}
+
+/// Reads mod settings in a text format. NDJSON is read a line at a time.
+fn deserialize_settings(format: Format, reader: &mut impl Read) -> anyhow::Result<ModSettings> {
+    match format {
+        Format::Ndjson => {
+            ModSettings::from_ndjson(BufReader::new(reader)).context("Deserializing NDJSON")
+        }
+        _ => deserialize(format, reader),
+    }
+}
+
+fn convert(
+    arg: &Args,
+    input_format: Format,
+    output_format: Format,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    let serialized = match arg.kind {
+        Kind::ModSettings => {
+            let mut settings = deserialize_settings(input_format, reader)?;
+            if arg.omit_none {
+                transform::omit_none(&mut settings);
+            }
+            serialize_output(arg, output_format, &settings)?
+        }
+        Kind::MapSettings => serialize_output(
+            arg,
+            output_format,
+            &deserialize::<codec::Settings>(input_format, reader)?,
+        )?,
     };
+    write_document(arg, &serialized, writer)
+}
 
-    codec::Settings::from_simple(&deserialized)
-        .encode_to_writer(writer)
-        .context("Encoding settings")
+fn encode(
+    arg: &Args,
+    format: Format,
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    if arg.kind == Kind::MapSettings {
+        return deserialize::<codec::Settings>(format, reader)?
+            .encode_to_writer(writer)
+            .context("Encoding settings");
+    }
+    let (deserialized, sources) = if arg.source_lines {
+        let data = read_all(reader)?;
+        let sources = source_map(Some(format), &data);
+        (deserialize_settings(format, &mut data.as_slice())?, sources)
+    } else if arg.lenient_bools {
+        (deserialize_lenient_bools(arg, format, reader)?, None)
+    } else {
+        (deserialize_settings(format, reader)?, None)
+    };
+    encode_settings(arg, deserialized, sources, writer)
+}
+
+/// Reads JSON settings, taking 0 and 1 as false and true for the settings --schema says are bools
+fn deserialize_lenient_bools(
+    arg: &Args,
+    format: Format,
+    reader: &mut impl Read,
+) -> anyhow::Result<ModSettings> {
+    if format != Format::Json {
+        return Err(exit::usage("--lenient-bools only applies to JSON input"));
+    }
+    let schema = read_schema(arg.schema.as_deref().expect("required by the parser"))?;
+    let mut value: serde_json::Value = deserialize(format, reader)?;
+    for path in transform::coerce_bools(&mut value, &schema) {
+        warn_setting(
+            Some(&path),
+            format_args!("Read the number at {} as a bool", path),
+        );
+    }
+    serde_json::from_value(value).context("Deserializing JSON")
+}
+
+/// Applies the options that transform settings before encoding, then encodes them
+fn encode_settings(
+    arg: &Args,
+    mut deserialized: ModSettings,
+    sources: Option<SourceMap>,
+    writer: &mut impl Write,
+) -> anyhow::Result<()> {
+    apply_overlays(arg, &mut deserialized)?;
+    if let Some(template) = &arg.order_template {
+        let template = read_settings_file(template).context("Reading order template")?;
+        transform::order_like(&mut deserialized, &template);
+    }
+    if let Some(path) = &arg.rename {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading {}", path.display()))
+            .context(IoError("Reading rename map"))?;
+        let map = transform::RenameMap::parse(&text)
+            .with_context(|| format!("Parsing rename map {}", path.display()))?;
+        let renamed = map.apply(&mut deserialized);
+        for (path, new) in renamed.collisions {
+            warn_setting(
+                Some(&path.to_string()),
+                format_args!(
+                    "not renaming {} to {}, which another setting has after renaming{}",
+                    path,
+                    new,
+                    cite(sources.as_ref(), path.section, &path.key)
+                ),
+            );
+        }
+        notice(None, format_args!("Renamed {} settings", renamed.count));
+    }
+    if arg.allow_keys.is_some() || arg.deny_keys.is_some() {
+        let read_patterns = |path: &Option<std::path::PathBuf>| {
+            path.as_deref()
+                .map(|path| {
+                    std::fs::read_to_string(path)
+                        .with_context(|| format!("Reading {}", path.display()))
+                        .context(IoError("Reading key patterns"))
+                })
+                .transpose()
+        };
+        let allow = read_patterns(&arg.allow_keys)?;
+        let deny = read_patterns(&arg.deny_keys)?;
+        let dropped =
+            transform::KeyFilter::new(allow.as_deref(), deny.as_deref()).apply(&mut deserialized);
+        notice(
+            None,
+            format_args!("Dropped {} settings excluded by the key filters", dropped),
+        );
+    }
+    if !arg.where_.is_empty() {
+        let mut dropped = 0;
+        for (section, map) in deserialized.sections_mut() {
+            let before = map.len();
+            map.retain(|key, setting| {
+                arg.where_
+                    .iter()
+                    .any(|predicate| predicate.matches(section, key, &setting.value))
+            });
+            dropped += before - map.len();
+        }
+        notice(
+            None,
+            format_args!("Dropped {} settings not matching --where", dropped),
+        );
+    }
+
+    if arg.trim_strings {
+        for path in transform::trim_strings(&mut deserialized) {
+            warn_setting(
+                Some(&path.to_string()),
+                format_args!(
+                    "trimmed whitespace from the value of {}{}",
+                    path,
+                    cite(sources.as_ref(), path.section, &path.key)
+                ),
+            );
+        }
+    }
+    spellcheck(arg, &deserialized, sources.as_ref())?;
+    if let Some(target) = arg.target_version {
+        for finding in compat::check(&deserialized, target) {
+            warn(format_args!("{}", finding));
+        }
+        // a file without a header is all any version before it can be written as
+        deserialized.factorio_version = if target.has_header() {
+            target
+        } else {
+            FactorioVersion::LEGACY
+        };
+    }
+
+    if arg.verify {
+        let mut encoded = Vec::new();
+        factorio_settings::encode_writer(&deserialized, &mut encoded)?;
+        verify_encoding(&encoded, &deserialized, sources.as_ref())
+            .context("Verifying the encoded settings")?;
+        return writer
+            .write_all(&encoded)
+            .context(IoError("Writing output"));
+    }
+    factorio_settings::encode_writer(&deserialized, writer)
+}
+
+/// Checks that encoded settings decode back to the settings they were encoded from. Doubles that
+/// aren't equal to themselves, such as NaN, fail the check.
+fn verify_encoding(
+    encoded: &[u8],
+    source: &ModSettings,
+    sources: Option<&SourceMap>,
+) -> anyhow::Result<()> {
+    let decoded = factorio_settings::decode_reader(&mut &encoded[..])?;
+    if decoded.factorio_version != source.factorio_version {
+        anyhow::bail!("The decoded version differs from the input");
+    }
+    for ((section, decoded), (_, source)) in decoded.sections().into_iter().zip(source.sections()) {
+        if decoded.len() != source.len() {
+            anyhow::bail!(
+                "Decoded {} has {} settings rather than {}",
+                section,
+                decoded.len(),
+                source.len()
+            );
+        }
+        for ((decoded_key, decoded), (key, source)) in decoded.iter().zip(source) {
+            if decoded_key != key || decoded != source {
+                anyhow::bail!(
+                    "Decoded {}/{} differs from the input{}",
+                    section,
+                    key,
+                    cite(sources, section, key)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        count_toml_sections, inline_toml_arrays, inline_toml_colors, lossy_doubles, serialize,
+    };
+    use crate::args::Format;
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn edge_case_doubles() {
+        let mut settings = ModSettings {
+            factorio_version: FactorioVersion::FIRST_WITH_HEADER,
+            startup: IndexMap::new(),
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        };
+        let doubles = [
+            ("subnormal", f64::from_bits(1)),
+            ("smallest-normal", f64::MIN_POSITIVE),
+            ("max", f64::MAX),
+            ("min", f64::MIN),
+            ("negative-zero", -0.0),
+            ("nan", f64::NAN),
+            ("infinity", f64::INFINITY),
+        ];
+        for (key, value) in doubles {
+            settings
+                .startup
+                .insert(key.to_owned(), ModSettingsValue::Double(value).into());
+        }
+        settings.startup.insert(
+            "color".to_owned(),
+            ModSettingsValue::Color {
+                r: f64::from_bits(1),
+                g: 0.0,
+                b: 0.0,
+                a: f64::NAN,
+            }
+            .into(),
+        );
+        // finite doubles survive both formats exactly
+        assert!(lossy_doubles(Format::Toml, &settings).is_empty());
+        let lossy = lossy_doubles(Format::Json, &settings)
+            .into_iter()
+            .map(|(path, channel, _)| (path.to_string(), channel))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lossy,
+            [
+                ("startup/nan".to_owned(), None),
+                ("startup/infinity".to_owned(), None),
+                ("startup/color".to_owned(), Some("a"))
+            ]
+        );
+    }
+
+    #[test]
+    fn toml_failure_suggests_json() {
+        let err = serialize(Format::Toml, &vec![1, 2]).expect_err("arrays aren't documents");
+        assert!(format!("{:#}", err).contains("--format json"), "{:#}", err);
+        serialize(Format::Json, &vec![1, 2]).expect("serializing JSON");
+    }
+
+    #[test]
+    fn toml_colors_inline() {
+        let mut settings = ModSettings::empty(FactorioVersion::new(1, 1, 82, 4));
+        settings.startup.insert(
+            "tint".to_owned(),
+            ModSettingsValue::Color {
+                r: 1.0,
+                g: 0.5,
+                b: 0.0,
+                a: 1.0,
+            }
+            .into(),
+        );
+        settings.startup.insert(
+            "note".to_owned(),
+            ModSettingsValue::String("line\n[startup.tint.value]\nr = 2".to_owned()).into(),
+        );
+        let tables = serialize(Format::Toml, &settings).expect("serializing TOML");
+        assert!(tables.contains("[startup.tint.value]\n"), "{}", tables);
+        let mut document = tables.parse::<toml_edit::Document>().expect("parsing");
+        inline_toml_colors(document.as_table_mut());
+        let inline = document.to_string();
+        assert!(
+            inline.contains("[startup.tint]\ntype = \"Color\"\nvalue = { r = 1.0, g = 0.5, b = 0.0, a = 1.0 }\n"),
+            "{}",
+            inline
+        );
+        for text in [tables, inline] {
+            assert_eq!(
+                toml::from_str::<ModSettings>(&text).expect("reading back"),
+                settings
+            );
+        }
+    }
+
+    #[test]
+    fn toml_arrays_inline() {
+        let text = "[a]\ncolor = [\n    1.0,\n    0.5,\n]\nnames = [\n    \"x\",\n]\n";
+        let mut document = text.parse::<toml_edit::Document>().expect("parsing");
+        inline_toml_arrays(document.as_table_mut());
+        assert_eq!(
+            document.to_string(),
+            "[a]\ncolor = [1.0, 0.5]\nnames = [\n    \"x\",\n]\n"
+        );
+    }
+
+    #[test]
+    fn toml_section_counts() {
+        let mut settings = ModSettings::empty(FactorioVersion::new(1, 1, 82, 4));
+        for key in ["a", "b"] {
+            settings
+                .startup
+                .insert(key.to_owned(), ModSettingsValue::Bool(true).into());
+        }
+        settings.runtime_per_user.insert(
+            "note".to_owned(),
+            ModSettingsValue::String("[startup.a]".to_owned()).into(),
+        );
+        let mut document = serialize(Format::Toml, &settings)
+            .expect("serializing TOML")
+            .parse::<toml_edit::Document>()
+            .expect("parsing");
+        count_toml_sections(&mut document);
+        let counted = document.to_string();
+        assert!(
+            counted.contains("\n# startup: 2 settings\n[startup.a]\n"),
+            "{}",
+            counted
+        );
+        assert!(counted.contains("\n# runtime-global: 0 settings\n[runtime-global]\n"));
+        assert!(counted.contains("\n# runtime-per-user: 1 setting\n[runtime-per-user.note]\n"));
+        assert_eq!(counted.matches("# startup").count(), 1);
+        assert_eq!(
+            toml::from_str::<ModSettings>(&counted).expect("reading back"),
+            settings
+        );
+    }
+
+    #[test]
+    fn toml_places_tables_after_values() {
+        let mut setting = IndexMap::new();
+        setting.insert("table", serde_json::json!({ "r": 1.0 }));
+        setting.insert("value", serde_json::json!(1));
+        let mut settings = IndexMap::new();
+        settings.insert("a", setting);
+        let text = serialize(Format::Toml, &settings).expect("serializing TOML");
+        assert_eq!(text, "[a]\nvalue = 1\n\n[a.table]\nr = 1.0\n");
+    }
 }
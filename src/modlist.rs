@@ -0,0 +1,73 @@
+use crate::exit::IoError;
+use anyhow::Context;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The `mod-list.json` Factorio keeps in the mods directory, beside `mod-settings.dat`. The
+/// settings file itself doesn't record which mods were enabled when it was written.
+#[derive(Debug, Deserialize)]
+struct ModList {
+    mods: Vec<ModEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModEntry {
+    name: String,
+    enabled: bool,
+}
+
+/// The `mod-list.json` in the same directory as a settings file
+pub fn beside(settings: &Path) -> PathBuf {
+    settings
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .join("mod-list.json")
+}
+
+/// The names of the enabled mods in a `mod-list.json`, in the order it lists them
+pub fn enabled_mods(path: &Path) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read(path)
+        .with_context(|| format!("Reading {}", path.display()))
+        .context(IoError("Reading mod list"))?;
+    parse(&text).with_context(|| format!("Reading {}", path.display()))
+}
+
+fn parse(text: &[u8]) -> anyhow::Result<Vec<String>> {
+    let list: ModList = serde_json::from_slice(text).context("Parsing mod list")?;
+    Ok(list
+        .mods
+        .into_iter()
+        .filter(|entry| entry.enabled)
+        .map(|entry| entry.name)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{beside, parse};
+    use std::path::Path;
+
+    #[test]
+    fn enabled_mods() {
+        let text = br#"{"mods": [
+            {"name": "base", "enabled": true},
+            {"name": "elevated-rails", "enabled": false},
+            {"name": "bnl", "enabled": true, "version": "1.0.0"}
+        ]}"#;
+        assert_eq!(parse(text).expect("parsing"), ["base", "bnl"]);
+        assert!(parse(br#"{"mods": [{"name": "base"}]}"#).is_err());
+    }
+
+    #[test]
+    fn mod_list_path() {
+        assert_eq!(
+            beside(Path::new("mods/mod-settings.dat")),
+            Path::new("mods/mod-list.json")
+        );
+        assert_eq!(
+            beside(Path::new("mod-settings.dat")),
+            Path::new("./mod-list.json")
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use crate::exit::IoError;
+use anyhow::Context;
+use flate2::write::GzEncoder;
+use std::fs::{File, OpenOptions};
+use std::io::{StdoutLock, Write};
+use std::path::{Path, PathBuf};
+
+/// The destination for converted output.
+///
+/// Regular files are written to a temporary file beside the destination and renamed over it by
+/// [`Output::commit`], so a failed conversion never leaves a truncated file behind. This also
+/// makes it safe for the output to be the input file: it isn't touched until the input has been
+/// read in full. Pipes, FIFOs and other special files can't be renamed over, so they are written
+/// directly.
+///
+/// A replaced file keeps its permissions unless a Unix file mode is given, which is applied to the
+/// temporary file before it's moved into place. A symlinked output path is followed, so the file
+/// it points to is replaced and the link is kept.
+pub enum Output {
+    Stdout(StdoutLock<'static>),
+    Direct(File),
+    Atomic(AtomicFile),
+    /// Output compressed with gzip on its way to another output
+    Gzip(Box<GzEncoder<Output>>),
+}
+
+impl Output {
+    pub fn create(path: Option<&Path>, file_mode: Option<u32>) -> anyhow::Result<Output> {
+        let Some(path) = path else {
+            return Ok(Output::Stdout(std::io::stdout().lock()));
+        };
+        if is_special_file(path) {
+            let file = OpenOptions::new()
+                .write(true)
+                .open(path)
+                .context(IoError("Opening output file"))?;
+            Ok(Output::Direct(file))
+        } else {
+            Ok(Output::Atomic(AtomicFile::create(path, file_mode)?))
+        }
+    }
+
+    /// This output, compressed with gzip
+    pub fn compressed(self) -> Output {
+        Output::Gzip(Box::new(GzEncoder::new(
+            self,
+            flate2::Compression::default(),
+        )))
+    }
+
+    /// Finishes writing, moving the output into place if it was written to a temporary file
+    pub fn commit(self) -> anyhow::Result<()> {
+        match self {
+            Output::Gzip(gzip) => gzip.finish().context(IoError("Writing output"))?.commit(),
+            Output::Stdout(mut stdout) => stdout.flush().context(IoError("Writing output")),
+            Output::Direct(mut file) => file.flush().context(IoError("Writing output")),
+            Output::Atomic(atomic) => atomic.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::Direct(file) => file.write(buf),
+            Output::Atomic(atomic) => atomic.file.write(buf),
+            Output::Gzip(gzip) => gzip.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::Direct(file) => file.flush(),
+            Output::Atomic(atomic) => atomic.file.flush(),
+            Output::Gzip(gzip) => gzip.flush(),
+        }
+    }
+}
+
+/// Whether the path exists and is something other than a regular file, such as a FIFO or device
+fn is_special_file(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|meta| !meta.is_file())
+        .unwrap_or(false)
+}
+
+/// A temporary file that replaces its destination when committed, and is removed if dropped
+/// without being committed.
+pub struct AtomicFile {
+    file: File,
+    temp: PathBuf,
+    destination: PathBuf,
+    committed: bool,
+}
+
+/// Symlinks followed before giving up, as Linux does
+const MAX_LINKS: usize = 40;
+
+/// The path a chain of symlinks ends at, which is the path itself if it isn't a link or doesn't
+/// exist yet. A link to a missing file resolves to where that file would be.
+fn resolve_links(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut resolved = path.to_owned();
+    for _ in 0..MAX_LINKS {
+        let Ok(target) = std::fs::read_link(&resolved) else {
+            return Ok(resolved);
+        };
+        // a relative target is relative to the link's directory; an absolute one replaces it
+        resolved = resolved.parent().unwrap_or(Path::new("")).join(target);
+    }
+    Err(anyhow::anyhow!(
+        "Too many levels of symbolic links at {}",
+        path.display()
+    ))
+    .context(IoError("Creating output file"))
+}
+
+impl AtomicFile {
+    fn create(destination: &Path, file_mode: Option<u32>) -> anyhow::Result<AtomicFile> {
+        let destination = &resolve_links(destination)?;
+        let name = destination
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Output path has no file name"))
+            .context(IoError("Creating output file"))?;
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp = destination.with_file_name(temp_name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .context(IoError("Creating output file"))?;
+        let atomic = AtomicFile {
+            file,
+            temp,
+            destination: destination.to_owned(),
+            committed: false,
+        };
+        let permissions = match file_mode {
+            Some(mode) => mode_permissions(mode),
+            None => std::fs::metadata(destination)
+                .ok()
+                .map(|meta| meta.permissions()),
+        };
+        if let Some(permissions) = permissions {
+            atomic
+                .file
+                .set_permissions(permissions)
+                .context(IoError("Setting output file permissions"))?;
+        }
+        Ok(atomic)
+    }
+
+    fn commit(mut self) -> anyhow::Result<()> {
+        self.file.sync_all().context(IoError("Writing output"))?;
+        std::fs::rename(&self.temp, &self.destination)
+            .context(IoError("Moving output file into place"))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// The permissions for a Unix file mode. Elsewhere there's no equivalent, so the platform default
+/// is kept.
+#[cfg(unix)]
+fn mode_permissions(mode: u32) -> Option<std::fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn mode_permissions(_mode: u32) -> Option<std::fs::Permissions> {
+    None
+}
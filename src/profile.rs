@@ -0,0 +1,102 @@
+use crate::codec::DecodeOptions;
+use indexmap::IndexMap;
+use std::io::Write;
+
+/// How many bytes of the encoded file each kind of data contributes, as counted while decoding it
+#[derive(Debug, Default)]
+pub struct ByteProfile {
+    counts: IndexMap<&'static str, u64>,
+}
+
+impl ByteProfile {
+    /// Options that count the bytes of each kind of data as they're decoded
+    pub fn counting(options: DecodeOptions) -> DecodeOptions {
+        DecodeOptions {
+            bytes: Some(Default::default()),
+            ..options
+        }
+    }
+
+    /// The counts from decoding with options made by [`ByteProfile::counting`]
+    pub fn of(options: DecodeOptions) -> ByteProfile {
+        ByteProfile {
+            counts: options.bytes.unwrap_or_default().into_inner(),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// The tallies, largest first
+    pub fn sorted(&self) -> Vec<(&'static str, u64)> {
+        let mut entries = self
+            .counts
+            .iter()
+            .map(|(kind, count)| (*kind, *count))
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        entries
+    }
+
+    /// Writes the tallies as a table of type, byte count and percentage of the whole file
+    pub fn write_table(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let total = self.total();
+        writeln!(writer, "{:<20} {:>10} {:>8}", "type", "bytes", "percent")?;
+        for (kind, count) in self.sorted() {
+            let percent = if total == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / total as f64
+            };
+            writeln!(writer, "{:<20} {:>10} {:>7.2}%", kind, count, percent)?;
+        }
+        writeln!(writer, "{:<20} {:>10} {:>7.2}%", "total", total, 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ByteProfile;
+    use crate::codec::{DecodeOptions, Settings};
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn profile(file: &str) -> ByteProfile {
+        let mut reader = BufReader::new(File::open(file).expect("opening file"));
+        let options = ByteProfile::counting(DecodeOptions::default());
+        Settings::from_reader_with(&mut reader, &options).expect("decoding settings");
+        ByteProfile::of(options)
+    }
+
+    #[test]
+    fn profile_total_matches_file_size() {
+        for file in [
+            "test_data/complex-settings.dat",
+            "test_data/settings-2.0.dat",
+        ] {
+            let length = std::fs::metadata(file).expect("file metadata").len();
+            assert_eq!(profile(file).total(), length, "{}", file);
+        }
+    }
+
+    #[test]
+    fn list_keys_are_counted() {
+        // a headerless root dictionary holding one list of two bools, each after an empty key
+        let data = hex_literal::hex!(
+            "05 00 01 00 00 00 00 01 61 04 00 02 00 00 00 00 00 01 00 01 00 00 01 00 00"
+        );
+        let options = ByteProfile::counting(DecodeOptions::default());
+        Settings::from_reader_with(&mut &data[..], &options).expect("decoding settings");
+        let profile = ByteProfile::of(options);
+        assert_eq!(profile.total(), data.len() as u64);
+        assert_eq!(profile.counts["list key"], 4);
+        assert_eq!(profile.counts["bool"], 2);
+    }
+
+    #[test]
+    fn profile_table_sorted() {
+        let sorted = profile("test_data/settings-2.0.dat").sorted();
+        assert!(sorted.windows(2).all(|w| w[0].1 >= w[1].1));
+    }
+}
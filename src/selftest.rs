@@ -0,0 +1,126 @@
+use crate::codec::{Codec, Property, Settings, TYPE_BYTES};
+use crate::simple::ModSettings;
+use anyhow::{anyhow, Context};
+use std::io::{Cursor, Write};
+
+/// Fixtures bundled into the binary so a build can be checked without the test data on disk
+const FIXTURES: &[(&str, &[u8])] = &[
+    (
+        "complex-settings.dat",
+        include_bytes!("../test_data/complex-settings.dat"),
+    ),
+    (
+        "settings-2.0.dat",
+        include_bytes!("../test_data/settings-2.0.dat"),
+    ),
+];
+
+/// Checks that a fixture decodes and re-encodes to identical bytes, both directly and through
+/// the simplified model
+fn check(data: &[u8]) -> anyhow::Result<()> {
+    let settings = Settings::from_reader(&mut Cursor::new(data)).context("Decoding")?;
+    let mut encoded = Vec::with_capacity(data.len());
+    settings
+        .encode_to_writer(&mut encoded)
+        .context("Encoding")?;
+    if encoded != data {
+        return Err(anyhow!("Re-encoded bytes differ from the original"));
+    }
+
+    let simple = ModSettings::try_from(&settings).context("Simplifying")?;
+    let json = serde_json::to_string(&simple).context("Serializing to JSON")?;
+    let simple: ModSettings = serde_json::from_str(&json).context("Deserializing JSON")?;
+    let mut encoded = Vec::with_capacity(data.len());
+    Settings::from_simple(&simple)
+        .encode_to_writer(&mut encoded)
+        .context("Encoding simplified settings")?;
+    if encoded != data {
+        return Err(anyhow!(
+            "Bytes encoded from the JSON representation differ from the original"
+        ));
+    }
+    Ok(())
+}
+
+/// Runs every check over the bundled fixtures, reporting each result. Returns whether all passed.
+pub fn run(writer: &mut impl Write) -> std::io::Result<bool> {
+    let mut all_passed = true;
+    for (name, data) in FIXTURES {
+        match check(data) {
+            Ok(()) => writeln!(writer, "pass  {}", name)?,
+            Err(err) => {
+                all_passed = false;
+                writeln!(writer, "FAIL  {}: {:#}", name, err)?
+            }
+        }
+    }
+    Ok(all_passed)
+}
+
+/// Checks that a type byte followed by zeros decodes to a property of that type, the smallest
+/// value it can hold, which encodes back to the same bytes. Returns the type's name.
+fn check_type(byte: u8) -> anyhow::Result<&'static str> {
+    // a type byte and any-flag, then enough zeros for the largest fixed-size value
+    let mut encoded = vec![byte, 0];
+    encoded.extend_from_slice(&[0; 8]);
+    let property = Property::decode(&mut Cursor::new(&encoded)).context("Decoding")?;
+    if property.value.type_byte() != byte {
+        return Err(anyhow!("Decoded as {}", property.value.type_name()));
+    }
+    let mut reencoded = Vec::new();
+    property.encode(&mut reencoded).context("Encoding")?;
+    if !encoded.starts_with(&reencoded) {
+        return Err(anyhow!("Encoded back as {:02x?}", reencoded));
+    }
+    Ok(property.value.type_name())
+}
+
+/// Writes each property type byte with its name and whether it round trips, followed by the
+/// first byte past them, which must be rejected. Returns whether every row is as expected.
+pub fn types_table(writer: &mut impl Write) -> std::io::Result<bool> {
+    let mut all_passed = true;
+    writeln!(writer, "{:<6}{:<12}handling", "byte", "type")?;
+    for byte in TYPE_BYTES {
+        let (name, handling) = match check_type(byte) {
+            Ok(name) => (name, "encode and decode".to_owned()),
+            Err(err) => {
+                all_passed = false;
+                ("?", format!("FAIL: {:#}", err))
+            }
+        };
+        writeln!(writer, "{:<6}{:<12}{}", byte, name, handling)?;
+    }
+    let unknown = TYPE_BYTES.iter().max().map_or(0, |max| max + 1);
+    let handling = match Property::decode(&mut Cursor::new([unknown, 0])) {
+        Err(_) => "rejected",
+        Ok(_) => {
+            all_passed = false;
+            "FAIL: decoded"
+        }
+    };
+    writeln!(writer, "{:<6}{:<12}{}", unknown, "(unknown)", handling)?;
+    Ok(all_passed)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn types_table_covers_each_type() {
+        let mut table = Vec::new();
+        assert!(super::types_table(&mut table).expect("writing table"));
+        let table = String::from_utf8(table).unwrap();
+        assert_eq!(table.lines().count(), 9, "{}", table);
+        assert!(
+            table.contains("4     list        encode and decode"),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn bundled_fixtures_pass() {
+        let mut report = Vec::new();
+        assert!(super::run(&mut report).expect("writing report"));
+        assert_eq!(String::from_utf8(report).unwrap().lines().count(), 2);
+    }
+}
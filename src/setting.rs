@@ -0,0 +1,751 @@
+use crate::args::ColorFormat;
+use crate::simple::{section_mut, section_name, ModSetting, ModSettings, ModSettingsValue};
+use crate::transform::{format_color, glob_match};
+use crate::types::FactorioVersion;
+use anyhow::anyhow;
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// The location of a single setting, written `section/key`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SettingPath {
+    pub section: &'static str,
+    pub key: String,
+}
+
+impl FromStr for SettingPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (section, key) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Expected section/key, found {:?}", s))?;
+        let section = section_name(section)?;
+        if key.is_empty() {
+            return Err(anyhow!("Setting key is empty in {:?}", s));
+        }
+        Ok(SettingPath {
+            section,
+            key: key.to_owned(),
+        })
+    }
+}
+
+impl Display for SettingPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.section, self.key)
+    }
+}
+
+/// A setting and the value to give it, written `section/key=value` or `section/key:type=value`.
+///
+/// Without a type, the value is a bool if it is `true` or `false`, an integer if it parses as
+/// one, a double if it parses as a finite one, and a string otherwise, so `inf` and `nan` are only
+/// doubles when typed as such. A `:` in the key only starts a type when one of [`VALUE_TYPES`]
+/// follows it, so other keys may contain a colon.
+#[derive(Clone, Debug)]
+pub struct Assignment {
+    pub path: SettingPath,
+    pub value: ModSettingsValue,
+    /// The value as written, to read again as another type
+    text: String,
+    typed: bool,
+}
+
+impl FromStr for Assignment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, text) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected section/key=value, found {:?}", s))?;
+        let (path, value_type) = match target.rsplit_once(':') {
+            Some((path, value_type))
+                if VALUE_TYPES
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(value_type)) =>
+            {
+                (path, Some(value_type))
+            }
+            _ => (target, None),
+        };
+        let path = path.parse()?;
+        let value = parse_value(value_type, text)
+            .map_err(|err| err.context(format!("Parsing the value of {}", path)))?;
+        Ok(Assignment {
+            path,
+            value,
+            text: text.to_owned(),
+            typed: value_type.is_some(),
+        })
+    }
+}
+
+impl Assignment {
+    /// The value to replace `current` with. Without an explicit type, the value is read as the
+    /// current value's type when it parses as one, so `1` keeps a double setting a double.
+    pub fn value_like(&self, current: &ModSettingsValue) -> ModSettingsValue {
+        if self.typed || *current == ModSettingsValue::None {
+            return self.value.clone();
+        }
+        parse_value(Some(current.type_name()), &self.text).unwrap_or_else(|_| self.value.clone())
+    }
+}
+
+/// Builds settings holding only the given assignments. Since Factorio requires setting names to
+/// be unique across sections, naming a key twice is an error, even in different sections.
+pub fn build_settings(
+    version: FactorioVersion,
+    assignments: &[Assignment],
+) -> anyhow::Result<ModSettings> {
+    let mut settings = ModSettings::empty(version);
+    let mut seen = IndexMap::new();
+    for assignment in assignments {
+        let path = &assignment.path;
+        if let Some(earlier) = seen.insert(path.key.as_str(), path) {
+            return Err(anyhow!("{} conflicts with {}", path, earlier));
+        }
+        section_mut(&mut settings, path.section)
+            .insert(path.key.clone(), assignment.value.clone().into());
+    }
+    Ok(settings)
+}
+
+/// The types a value can be given on the command line
+pub const VALUE_TYPES: [&str; 6] = ["bool", "double", "integer", "string", "color", "none"];
+
+/// Parses a value written on the command line, optionally with an explicit type: `bool`,
+/// `double`, `integer`, `string`, `color` (as `r,g,b,a`) or `none`.
+pub fn parse_value(value_type: Option<&str>, text: &str) -> anyhow::Result<ModSettingsValue> {
+    match value_type.map(str::to_ascii_lowercase).as_deref() {
+        None => Ok(match text {
+            "true" => ModSettingsValue::Bool(true),
+            "false" => ModSettingsValue::Bool(false),
+            _ => {
+                if let Ok(i) = text.parse() {
+                    ModSettingsValue::Integer(i)
+                } else if let Some(f) = text.parse::<f64>().ok().filter(|f| f.is_finite()) {
+                    ModSettingsValue::Double(f)
+                } else {
+                    ModSettingsValue::String(text.to_owned())
+                }
+            }
+        }),
+        Some("bool") => {
+            Ok(ModSettingsValue::Bool(text.parse().map_err(|_| {
+                anyhow!("Expected true or false, found {:?}", text)
+            })?))
+        }
+        Some("double") => {
+            Ok(ModSettingsValue::Double(text.parse().map_err(|_| {
+                anyhow!("Expected a number, found {:?}", text)
+            })?))
+        }
+        Some("integer") => {
+            Ok(ModSettingsValue::Integer(text.parse().map_err(|_| {
+                anyhow!("Expected an integer, found {:?}", text)
+            })?))
+        }
+        Some("string") => Ok(ModSettingsValue::String(text.to_owned())),
+        Some("color") => {
+            let channels = text
+                .split(',')
+                .map(|c| c.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| anyhow!("Expected r,g,b,a numbers, found {:?}", text))?;
+            match channels[..] {
+                [r, g, b, a] => Ok(ModSettingsValue::Color { r, g, b, a }),
+                [r, g, b] => Ok(ModSettingsValue::Color { r, g, b, a: 1.0 }),
+                _ => Err(anyhow!("Expected r,g,b or r,g,b,a, found {:?}", text)),
+            }
+        }
+        Some("none") => Ok(ModSettingsValue::None),
+        Some(other) => Err(anyhow!(
+            "Unknown type {:?}, expected one of {}",
+            other,
+            VALUE_TYPES.join(", ")
+        )),
+    }
+}
+
+/// A value as plain text, for scripts: the form `parse_value` reads back, with colors in hex
+/// when asked and hex can hold them exactly
+pub fn raw_value(value: &ModSettingsValue, color_format: ColorFormat) -> String {
+    match value {
+        ModSettingsValue::Bool(b) => b.to_string(),
+        ModSettingsValue::Double(n) => n.to_string(),
+        ModSettingsValue::Integer(i) => i.to_string(),
+        ModSettingsValue::String(s) => s.clone(),
+        ModSettingsValue::Color { r, g, b, a } => {
+            let color = serde_json::json!({"r": r, "g": g, "b": b, "a": a});
+            match format_color(&color, color_format) {
+                Some(serde_json::Value::String(hex)) => hex,
+                _ => format!("{},{},{},{}", r, g, b, a),
+            }
+        }
+        ModSettingsValue::None => String::new(),
+    }
+}
+
+/// Whether a value equals a query written on the command line. Numbers compare numerically, so
+/// `2` matches both an integer and a double of two, and strings compare as written.
+pub fn value_matches(value: &ModSettingsValue, query: &str) -> bool {
+    match value {
+        ModSettingsValue::Bool(b) => query.parse() == Ok(*b),
+        ModSettingsValue::Integer(i) => {
+            query.parse() == Ok(*i) || query.parse::<f64>() == Ok(*i as f64)
+        }
+        ModSettingsValue::Double(f) => query.parse() == Ok(*f),
+        ModSettingsValue::String(s) => s == query,
+        ModSettingsValue::None | ModSettingsValue::Color { .. } => false,
+    }
+}
+
+/// A condition on the value of settings, written `[section/]key OP value`. The key may be a glob,
+/// and the operator is one of `=`, `!=`, `<`, `<=`, `>` or `>=`.
+///
+/// `=` and `!=` compare as [`value_matches`] does. The ordering operators need a number and only
+/// match integer and double settings.
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    section: Option<&'static str>,
+    key: String,
+    op: Comparison,
+    value: String,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl FromStr for Predicate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let at = s.find(['=', '!', '<', '>']).ok_or_else(|| {
+            anyhow!(
+                "Expected key OP value with OP one of = != < <= > >=, found {:?}",
+                s
+            )
+        })?;
+        let (target, rest) = s.split_at(at);
+        let (op, value) = [
+            ("!=", Comparison::NotEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("=", Comparison::Equal),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+        ]
+        .into_iter()
+        .find_map(|(token, op)| Some((op, rest.strip_prefix(token)?)))
+        .ok_or_else(|| anyhow!("Expected an operator after {:?}, found {:?}", target, rest))?;
+        let (target, value) = (target.trim(), value.trim());
+        let (section, key) = match target.split_once('/') {
+            Some((section, key)) => (Some(section_name(section)?), key),
+            None => (None, target),
+        };
+        if key.is_empty() {
+            return Err(anyhow!("Setting key is empty in {:?}", s));
+        }
+        let ordering = !matches!(op, Comparison::Equal | Comparison::NotEqual);
+        if ordering && value.parse::<f64>().is_err() {
+            return Err(anyhow!(
+                "Expected a number to compare with, found {:?}",
+                value
+            ));
+        }
+        Ok(Predicate {
+            section,
+            key: key.to_owned(),
+            op,
+            value: value.to_owned(),
+        })
+    }
+}
+
+impl Predicate {
+    /// Whether the setting at `section/key` satisfies the condition. Settings the key doesn't name
+    /// never do.
+    pub fn matches(&self, section: &str, key: &str, value: &ModSettingsValue) -> bool {
+        if self.section.is_some_and(|s| s != section) || !glob_match(&self.key, key) {
+            return false;
+        }
+        let number = match value {
+            ModSettingsValue::Integer(i) => Some(*i as f64),
+            ModSettingsValue::Double(f) => Some(*f),
+            _ => None,
+        };
+        let bound = self.value.parse::<f64>();
+        match (self.op, number, bound) {
+            (Comparison::Equal, ..) => value_matches(value, &self.value),
+            (Comparison::NotEqual, ..) => !value_matches(value, &self.value),
+            (Comparison::Less, Some(n), Ok(bound)) => n < bound,
+            (Comparison::LessOrEqual, Some(n), Ok(bound)) => n <= bound,
+            (Comparison::Greater, Some(n), Ok(bound)) => n > bound,
+            (Comparison::GreaterOrEqual, Some(n), Ok(bound)) => n >= bound,
+            _ => false,
+        }
+    }
+}
+
+/// The paths of every setting whose value matches the query, in file order
+pub fn find_value(settings: &ModSettings, query: &str) -> Vec<SettingPath> {
+    settings
+        .sections()
+        .into_iter()
+        .flat_map(|(section, map)| {
+            map.iter()
+                .filter(|(_, setting)| value_matches(&setting.value, query))
+                .map(move |(key, _)| SettingPath {
+                    section,
+                    key: key.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Settings in one section that share a value
+#[derive(Debug, Serialize)]
+pub struct Duplicates<'a> {
+    pub section: &'static str,
+    #[serde(skip)]
+    pub setting: &'a ModSetting,
+    pub value: &'a ModSettingsValue,
+    pub keys: Vec<&'a str>,
+}
+
+/// Groups the settings in each section that have equal values, for groups of two or more, in order
+/// of each group's first setting. Bools and empty values are left out: nearly every file has
+/// many settings that are just `true` or `false`, which says nothing about redundancy.
+pub fn find_duplicates(settings: &ModSettings) -> Vec<Duplicates<'_>> {
+    let mut found = Vec::new();
+    for (section, map) in settings.sections() {
+        let mut groups: Vec<Duplicates> = Vec::new();
+        for (key, setting) in map {
+            if matches!(
+                setting.value,
+                ModSettingsValue::Bool(_) | ModSettingsValue::None
+            ) {
+                continue;
+            }
+            match groups
+                .iter_mut()
+                .find(|group| *group.value == setting.value)
+            {
+                Some(group) => group.keys.push(key),
+                None => groups.push(Duplicates {
+                    section,
+                    setting,
+                    value: &setting.value,
+                    keys: vec![key],
+                }),
+            }
+        }
+        found.extend(groups.into_iter().filter(|group| group.keys.len() > 1));
+    }
+    found
+}
+
+/// Settings whose key isn't known but is within a couple of edits of one that is, paired with the
+/// closest known key. Factorio ignores settings it doesn't know, so these are likely typos.
+pub fn misspellings<'a>(
+    settings: &ModSettings,
+    known: &'a IndexSet<String>,
+) -> Vec<(SettingPath, &'a str)> {
+    let mut found = Vec::new();
+    for (section, map) in settings.sections() {
+        for key in map.keys().filter(|key| !known.contains(*key)) {
+            let closest = known
+                .iter()
+                .map(|candidate| (edit_distance(key, candidate), candidate))
+                .filter(|(distance, candidate)| *distance <= 2 && distance * 4 <= candidate.len())
+                .min_by_key(|(distance, _)| *distance);
+            if let Some((_, candidate)) = closest {
+                found.push((
+                    SettingPath {
+                        section,
+                        key: key.clone(),
+                    },
+                    candidate.as_str(),
+                ));
+            }
+        }
+    }
+    found
+}
+
+/// The Levenshtein distance between two strings, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_settings, edit_distance, find_duplicates, find_value, misspellings, parse_value,
+        raw_value, value_matches, Assignment, Predicate, SettingPath,
+    };
+    use crate::args::ColorFormat;
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+    use indexmap::IndexSet;
+
+    #[test]
+    fn raw_values_parse_back() {
+        let color = ModSettingsValue::Color {
+            r: 1.0,
+            g: 0.2,
+            b: 0.0,
+            a: 1.0,
+        };
+        assert_eq!(raw_value(&color, ColorFormat::Object), "1,0.2,0,1");
+        assert_eq!(raw_value(&color, ColorFormat::Hex), "#ff3300ff");
+        let inexact = ModSettingsValue::Color {
+            r: 0.1,
+            g: 0.2,
+            b: 0.3,
+            a: 1.0,
+        };
+        assert_eq!(raw_value(&inexact, ColorFormat::Hex), "0.1,0.2,0.3,1");
+        assert_eq!(raw_value(&ModSettingsValue::None, ColorFormat::Object), "");
+
+        for (value_type, value) in [
+            ("bool", ModSettingsValue::Bool(false)),
+            ("double", ModSettingsValue::Double(0.1)),
+            ("integer", ModSettingsValue::Integer(-9_007_199_254_740_993)),
+            ("string", ModSettingsValue::String(" spaced ".to_owned())),
+            ("color", color),
+        ] {
+            let text = raw_value(&value, ColorFormat::Object);
+            assert_eq!(
+                parse_value(Some(value_type), &text).unwrap(),
+                value,
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("bnl-glow", "bnl-gloww"), 1);
+        assert_eq!(edit_distance("bnl-glwo", "bnl-glow"), 2);
+        assert_eq!(edit_distance("caf\u{e9}", "cafe"), 1);
+    }
+
+    #[test]
+    fn suggests_close_keys() {
+        let mut settings = ModSettings::empty(FactorioVersion::new(1, 1, 0, 0));
+        for key in ["bnl-glow", "bnl-gloww", "bnl-enable", "other-mod-x", "ab"] {
+            settings
+                .startup
+                .insert(key.to_owned(), ModSettingsValue::Bool(true).into());
+        }
+        let known = ["bnl-glow", "bnl-enabled", "abc"]
+            .map(str::to_owned)
+            .into_iter()
+            .collect::<IndexSet<_>>();
+        let found = misspellings(&settings, &known)
+            .into_iter()
+            .map(|(path, known)| (path.to_string(), known))
+            .collect::<Vec<_>>();
+        // "ab" is one edit from "abc", but that's too much of a three-letter key
+        assert_eq!(
+            found,
+            [
+                ("startup/bnl-gloww".to_owned(), "bnl-glow"),
+                ("startup/bnl-enable".to_owned(), "bnl-enabled"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_path() {
+        let path: SettingPath = "runtime-global/a/b".parse().expect("parsing");
+        assert_eq!(path.section, "runtime-global");
+        assert_eq!(path.key, "a/b");
+        assert!("startup".parse::<SettingPath>().is_err());
+        assert!("settings/a".parse::<SettingPath>().is_err());
+        assert!("startup/".parse::<SettingPath>().is_err());
+    }
+
+    #[test]
+    fn parse_inferred_values() {
+        let value = |s: &str| s.parse::<Assignment>().expect("parsing").value;
+        assert_eq!(value("startup/a=true"), ModSettingsValue::Bool(true));
+        assert_eq!(value("startup/a=12"), ModSettingsValue::Integer(12));
+        assert_eq!(value("startup/a=1.5"), ModSettingsValue::Double(1.5));
+        assert_eq!(
+            value("startup/a=hello=world"),
+            ModSettingsValue::String("hello=world".to_owned())
+        );
+        for text in ["inf", "-infinity", "NaN"] {
+            assert_eq!(
+                value(&format!("startup/a={}", text)),
+                ModSettingsValue::String(text.to_owned())
+            );
+        }
+        assert!(matches!(
+            value("startup/a:double=-inf"),
+            ModSettingsValue::Double(f) if f == f64::NEG_INFINITY
+        ));
+    }
+
+    #[test]
+    fn colons_in_keys() {
+        let parse = |s: &str| s.parse::<Assignment>().expect("parsing");
+        let assignment = parse("startup/mod:option=1");
+        assert_eq!(assignment.path.key, "mod:option");
+        assert_eq!(assignment.value, ModSettingsValue::Integer(1));
+        let assignment = parse("startup/mod:option:String=1");
+        assert_eq!(assignment.path.key, "mod:option");
+        assert_eq!(assignment.value, ModSettingsValue::String("1".to_owned()));
+    }
+
+    #[test]
+    fn parse_typed_values() {
+        let value = |s: &str| s.parse::<Assignment>().expect("parsing").value;
+        assert_eq!(value("startup/a:double=12"), ModSettingsValue::Double(12.0));
+        assert_eq!(
+            value("startup/a:string=true"),
+            ModSettingsValue::String("true".to_owned())
+        );
+        assert_eq!(
+            value("startup/a:color=0.1, 0.2, 0.3"),
+            ModSettingsValue::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0
+            }
+        );
+        assert_eq!(value("startup/a:none="), ModSettingsValue::None);
+        assert!("startup/a:bool=yes".parse::<Assignment>().is_err());
+        assert!("startup/a:color=1".parse::<Assignment>().is_err());
+    }
+
+    #[test]
+    fn builds_from_assignments() {
+        let version = FactorioVersion::new(1, 1, 82, 0);
+        let parse = |items: &[&str]| {
+            items
+                .iter()
+                .map(|s| s.parse::<Assignment>().expect("parsing"))
+                .collect::<Vec<_>>()
+        };
+        let settings = build_settings(
+            version,
+            &parse(&["startup/foo:bool=true", "runtime-global/bar=hello"]),
+        )
+        .expect("building");
+        assert_eq!(settings.factorio_version, version);
+        assert_eq!(settings.startup["foo"].value, ModSettingsValue::Bool(true));
+        assert_eq!(
+            settings.runtime_global["bar"].value,
+            ModSettingsValue::String("hello".to_owned())
+        );
+        assert!(settings.runtime_per_user.is_empty());
+
+        let err = build_settings(version, &parse(&["startup/foo=1", "runtime-global/foo=2"]))
+            .expect_err("conflict");
+        assert_eq!(
+            err.to_string(),
+            "runtime-global/foo conflicts with startup/foo"
+        );
+    }
+
+    #[test]
+    fn values_take_the_current_type() {
+        let like = |s: &str, current: ModSettingsValue| {
+            s.parse::<Assignment>()
+                .expect("parsing")
+                .value_like(&current)
+        };
+        assert_eq!(
+            like("startup/a=1", ModSettingsValue::Double(0.5)),
+            ModSettingsValue::Double(1.0)
+        );
+        assert_eq!(
+            like("startup/a=true", ModSettingsValue::String("x".to_owned())),
+            ModSettingsValue::String("true".to_owned())
+        );
+        assert_eq!(
+            like(
+                "startup/a=1,0,0",
+                ModSettingsValue::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                }
+            ),
+            ModSettingsValue::Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }
+        );
+        // an explicit type or a value that doesn't parse as the current type wins
+        assert_eq!(
+            like("startup/a:integer=1", ModSettingsValue::Double(0.5)),
+            ModSettingsValue::Integer(1)
+        );
+        assert_eq!(
+            like("startup/a=fast", ModSettingsValue::Integer(2)),
+            ModSettingsValue::String("fast".to_owned())
+        );
+        assert_eq!(
+            like("startup/a=1", ModSettingsValue::None),
+            ModSettingsValue::Integer(1)
+        );
+    }
+
+    #[test]
+    fn match_values() {
+        assert!(value_matches(&ModSettingsValue::Bool(false), "false"));
+        assert!(!value_matches(&ModSettingsValue::Bool(false), "0"));
+        assert!(value_matches(&ModSettingsValue::Integer(2), "2"));
+        assert!(value_matches(&ModSettingsValue::Integer(2), "2.0"));
+        assert!(value_matches(&ModSettingsValue::Double(2.0), "2"));
+        assert!(!value_matches(&ModSettingsValue::Double(2.5), "2"));
+        assert!(value_matches(
+            &ModSettingsValue::String("2".to_owned()),
+            "2"
+        ));
+        assert!(!value_matches(&ModSettingsValue::None, ""));
+    }
+
+    #[test]
+    fn predicates() {
+        let matches = |predicate: &str, key: &str, value: ModSettingsValue| {
+            let predicate = predicate.parse::<Predicate>().expect("parsing");
+            predicate.matches("startup", key, &value)
+        };
+        assert!(matches(
+            "startup/difficulty>2",
+            "difficulty",
+            ModSettingsValue::Integer(3)
+        ));
+        assert!(!matches(
+            "difficulty > 2",
+            "difficulty",
+            ModSettingsValue::Integer(2)
+        ));
+        assert!(matches(
+            "difficulty>=2",
+            "difficulty",
+            ModSettingsValue::Double(2.0)
+        ));
+        assert!(matches(
+            "difficulty<2.5",
+            "difficulty",
+            ModSettingsValue::Integer(2)
+        ));
+        assert!(!matches(
+            "difficulty<3",
+            "difficulty",
+            ModSettingsValue::String("1".to_owned())
+        ));
+        assert!(matches(
+            "mode=easy",
+            "mode",
+            ModSettingsValue::String("easy".to_owned())
+        ));
+        assert!(matches(
+            "bnl-*!=true",
+            "bnl-a",
+            ModSettingsValue::Bool(false)
+        ));
+        assert!(!matches(
+            "bnl-*!=true",
+            "other",
+            ModSettingsValue::Bool(false)
+        ));
+        assert!(!matches(
+            "runtime-global/a=1",
+            "a",
+            ModSettingsValue::Integer(1)
+        ));
+    }
+
+    #[test]
+    fn predicate_errors() {
+        for predicate in ["difficulty", "difficulty>easy", "=2", "settings/a=1", "a<="] {
+            assert!(predicate.parse::<Predicate>().is_err(), "{}", predicate);
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut settings = ModSettings {
+            factorio_version: FactorioVersion::FIRST_WITH_HEADER,
+            startup: Default::default(),
+            runtime_global: Default::default(),
+            runtime_per_user: Default::default(),
+        };
+        for (key, value) in [
+            ("a", ModSettingsValue::Integer(2)),
+            ("b", ModSettingsValue::Bool(true)),
+            ("c", ModSettingsValue::Bool(true)),
+            ("d", ModSettingsValue::String("x".to_owned())),
+            ("e", ModSettingsValue::Integer(2)),
+            ("f", ModSettingsValue::Double(2.0)),
+        ] {
+            settings.startup.insert(key.to_owned(), value.into());
+        }
+        settings
+            .runtime_global
+            .insert("g".to_owned(), ModSettingsValue::Integer(2).into());
+        let found = find_duplicates(&settings);
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert_eq!(found[0].section, "startup");
+        assert_eq!(found[0].keys, ["a", "e"]);
+    }
+
+    #[test]
+    fn find_values() {
+        let mut settings = ModSettings {
+            factorio_version: FactorioVersion::FIRST_WITH_HEADER,
+            startup: Default::default(),
+            runtime_global: Default::default(),
+            runtime_per_user: Default::default(),
+        };
+        settings
+            .startup
+            .insert("a".to_owned(), ModSettingsValue::Bool(false).into());
+        settings
+            .startup
+            .insert("b".to_owned(), ModSettingsValue::Bool(true).into());
+        settings
+            .runtime_per_user
+            .insert("c".to_owned(), ModSettingsValue::Bool(false).into());
+        let found = find_value(&settings, "false")
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(found, ["startup/a", "runtime-per-user/c"]);
+    }
+}
@@ -1,23 +1,258 @@
 use crate::codec::{Property, PropertyValue, Settings};
 use crate::types::FactorioVersion;
+use anyhow::Context;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
-#[cfg_attr(test, derive(PartialEq))]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ModSettings {
     pub factorio_version: FactorioVersion,
-    pub startup: IndexMap<String, ModSettingsValue>,
+    pub startup: IndexMap<String, ModSetting>,
     #[serde(rename = "runtime-global")]
-    pub runtime_global: IndexMap<String, ModSettingsValue>,
+    pub runtime_global: IndexMap<String, ModSetting>,
     #[serde(rename = "runtime-per-user")]
-    pub runtime_per_user: IndexMap<String, ModSettingsValue>,
+    pub runtime_per_user: IndexMap<String, ModSetting>,
+}
+
+pub const STARTUP: &str = "startup";
+pub const RUNTIME_GLOBAL: &str = "runtime-global";
+pub const RUNTIME_PER_USER: &str = "runtime-per-user";
+
+/// Resolves a section name to its canonical spelling
+pub fn section_name(name: &str) -> anyhow::Result<&'static str> {
+    [STARTUP, RUNTIME_GLOBAL, RUNTIME_PER_USER]
+        .into_iter()
+        .find(|section| *section == name)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown section {:?}, expected one of {}, {}, {}",
+                name,
+                STARTUP,
+                RUNTIME_GLOBAL,
+                RUNTIME_PER_USER
+            )
+        })
+}
+
+pub fn section<'a>(settings: &'a ModSettings, name: &str) -> &'a IndexMap<String, ModSetting> {
+    match name {
+        RUNTIME_GLOBAL => &settings.runtime_global,
+        RUNTIME_PER_USER => &settings.runtime_per_user,
+        _ => &settings.startup,
+    }
+}
+
+pub fn section_mut<'a>(
+    settings: &'a mut ModSettings,
+    name: &str,
+) -> &'a mut IndexMap<String, ModSetting> {
+    match name {
+        RUNTIME_GLOBAL => &mut settings.runtime_global,
+        RUNTIME_PER_USER => &mut settings.runtime_per_user,
+        _ => &mut settings.startup,
+    }
+}
+
+impl ModSettings {
+    /// Settings for a version with all three sections empty
+    pub fn empty(factorio_version: FactorioVersion) -> ModSettings {
+        ModSettings {
+            factorio_version,
+            startup: IndexMap::new(),
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        }
+    }
+
+    /// Each section paired with its name, in file order
+    pub fn sections(&self) -> [(&'static str, &IndexMap<String, ModSetting>); 3] {
+        [
+            (STARTUP, &self.startup),
+            (RUNTIME_GLOBAL, &self.runtime_global),
+            (RUNTIME_PER_USER, &self.runtime_per_user),
+        ]
+    }
+
+    /// Each section paired with its name, in file order, mutably
+    pub fn sections_mut(&mut self) -> [(&'static str, &mut IndexMap<String, ModSetting>); 3] {
+        [
+            (STARTUP, &mut self.startup),
+            (RUNTIME_GLOBAL, &mut self.runtime_global),
+            (RUNTIME_PER_USER, &mut self.runtime_per_user),
+        ]
+    }
+
+    /// Checks the settings for values Factorio would reject or misinterpret, returning every
+    /// problem found rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        for (section, map) in self.sections() {
+            for (key, value) in map {
+                let mut error = |message: String| {
+                    errors.push(ValidationError {
+                        section,
+                        key: key.clone(),
+                        message,
+                    })
+                };
+                if key.is_empty() {
+                    error("key is empty".to_owned());
+                }
+                match &value.value {
+                    ModSettingsValue::Double(f) if !f.is_finite() => {
+                        error(format!("double is not finite: {}", f))
+                    }
+                    ModSettingsValue::Color { r, g, b, a } => {
+                        for (name, channel) in [("r", r), ("g", g), ("b", b), ("a", a)] {
+                            if !channel.is_finite() {
+                                error(format!("color channel {} is not finite: {}", name, channel));
+                            } else if !(0.0..=1.0).contains(channel) {
+                                error(format!(
+                                    "color channel {} is outside 0 to 1: {}",
+                                    name, channel
+                                ));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Reads newline-delimited JSON, one record per line, without buffering the whole input. Each
+    /// setting is a `{"section", "key", "type", "value"}` record, added to its section in the order
+    /// read; a `{"factorio_version"}` record gives the version. Blank lines are skipped. A setting
+    /// or the version given on more than one line is an error, as a repeated key is in TOML.
+    pub fn from_ndjson(reader: impl BufRead) -> anyhow::Result<ModSettings> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct VersionRecord {
+            factorio_version: FactorioVersion,
+        }
+        #[derive(Deserialize)]
+        struct SettingRecord {
+            section: String,
+            key: String,
+            #[serde(flatten)]
+            setting: ModSetting,
+        }
+
+        let mut version = None;
+        // the line each setting was read from, to name it when the setting is repeated
+        let mut lines: HashMap<(&'static str, String), usize> = HashMap::new();
+        let mut settings = ModSettings {
+            factorio_version: FactorioVersion::LEGACY,
+            startup: IndexMap::new(),
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        };
+        for (index, line) in reader.lines().enumerate() {
+            let line_context = || format!("Line {}", index + 1);
+            let line = line.with_context(line_context)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&line)
+                .with_context(line_context)?;
+            if record.contains_key("factorio_version") {
+                let record = serde_json::from_value::<VersionRecord>(record.into())
+                    .with_context(line_context)?;
+                if version.replace(record.factorio_version).is_some() {
+                    return Err(anyhow::anyhow!("factorio_version is given more than once"))
+                        .with_context(line_context);
+                }
+            } else {
+                let record = serde_json::from_value::<SettingRecord>(record.into())
+                    .with_context(line_context)?;
+                let section = section_name(&record.section).with_context(line_context)?;
+                if let Some(first) = lines.insert((section, record.key.clone()), index + 1) {
+                    return Err(anyhow::anyhow!(
+                        "{}/{} is already set on line {}",
+                        section,
+                        record.key,
+                        first
+                    ))
+                    .with_context(line_context);
+                }
+                section_mut(&mut settings, section).insert(record.key, record.setting);
+            }
+        }
+        settings.factorio_version =
+            version.ok_or_else(|| anyhow::anyhow!("No factorio_version record"))?;
+        Ok(settings)
+    }
+}
+
+/// How faithfully the simple model captured a decoded file
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct ConversionStats {
+    /// Settings read into the simple model
+    pub settings: usize,
+    /// Dictionary values assumed to be colors
+    pub colors: usize,
+    /// Settings whose dictionaries had keys beside `value`, kept as raw properties
+    pub with_extra: usize,
+    /// Warnings printed while reading the file
+    pub warnings: usize,
+}
+
+impl ConversionStats {
+    pub fn of(settings: &ModSettings, warnings: usize) -> ConversionStats {
+        let mut stats = ConversionStats {
+            warnings,
+            ..Default::default()
+        };
+        for (_, map) in settings.sections() {
+            for setting in map.values() {
+                stats.settings += 1;
+                if matches!(setting.value, ModSettingsValue::Color { .. }) {
+                    stats.colors += 1;
+                }
+                if !setting.extra.is_empty() {
+                    stats.with_extra += 1;
+                }
+            }
+        }
+        stats
+    }
+}
+
+impl Display for ConversionStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "settings simplified: {}", self.settings)?;
+        writeln!(f, "colors read from dictionaries: {}", self.colors)?;
+        writeln!(f, "settings with extra properties: {}", self.with_extra)?;
+        write!(f, "warnings: {}", self.warnings)
+    }
+}
+
+/// A problem with a single setting found by [`ModSettings::validate`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    pub section: &'static str,
+    pub key: String,
+    pub message: String,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}: {}", self.section, self.key, self.message)
+    }
 }
 
 fn property_map_parse(
     root: &IndexMap<String, Property>,
     key: &str,
-) -> Result<IndexMap<String, ModSettingsValue>, anyhow::Error> {
+) -> Result<IndexMap<String, ModSetting>, anyhow::Error> {
     let map = root
         .get(key)
         .ok_or(anyhow::anyhow!("Missing {} settings", key))?
@@ -25,7 +260,7 @@ fn property_map_parse(
         .as_dictionary()
         .ok_or(anyhow::anyhow!("{} settings is not a dictionary", key))?;
     map.iter()
-        .map(|(key, value)| ModSettingsValue::try_from(value).map(|a| (key.clone(), a)))
+        .map(|(key, value)| ModSetting::try_from(value).map(|a| (key.clone(), a)))
         .collect::<Result<IndexMap<_, _>, _>>()
 }
 
@@ -33,14 +268,16 @@ impl TryFrom<&Settings> for ModSettings {
     type Error = anyhow::Error;
 
     fn try_from(value: &Settings) -> Result<Self, Self::Error> {
-        let root = value
-            .properties
-            .value
-            .as_dictionary()
-            .ok_or(anyhow::anyhow!("Main properties is not a dictionary"))?;
-        let startup = property_map_parse(root, "startup")?;
-        let runtime_global = property_map_parse(root, "runtime-global")?;
-        let runtime_per_user = property_map_parse(root, "runtime-per-user")?;
+        let root = value.properties.value.as_dictionary().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Main properties is not a dictionary but a {}. To inspect it, decode the raw \
+                     property tree with --kind map-settings or --schema-only",
+                value.properties.value.type_name()
+            )
+        })?;
+        let startup = property_map_parse(root, STARTUP)?;
+        let runtime_global = property_map_parse(root, RUNTIME_GLOBAL)?;
+        let runtime_per_user = property_map_parse(root, RUNTIME_PER_USER)?;
         Ok(Self {
             factorio_version: value.version,
             startup,
@@ -50,76 +287,503 @@ impl TryFrom<&Settings> for ModSettings {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[cfg_attr(test, derive(PartialEq))]
+/// A setting's value, along with any properties stored beside `value` in the setting's
+/// dictionary. Factorio only writes `value`, but other tools may add siblings such as hints, which
+/// are kept as raw properties so they survive a round trip, in their order around `value`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ModSetting {
+    #[serde(flatten)]
+    pub value: ModSettingsValue,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub extra: IndexMap<String, Property>,
+    /// How many of the `extra` properties come before `value` in the dictionary. Only serialized
+    /// when some do.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub value_index: usize,
+    #[serde(default, skip_serializing_if = "AnyFlags::is_clear")]
+    pub any_flag: AnyFlags,
+}
+
+/// Which of a setting's properties have their any_flag set. Factorio writes it clear, so it's
+/// only serialized when set, for files that set it to round-trip.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnyFlags {
+    /// The flag of the setting's dictionary
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub setting: bool,
+    /// The flag of the property under `value`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub value: bool,
+}
+
+impl AnyFlags {
+    fn is_clear(&self) -> bool {
+        *self == AnyFlags::default()
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+impl From<ModSettingsValue> for ModSetting {
+    fn from(value: ModSettingsValue) -> Self {
+        ModSetting {
+            value,
+            extra: IndexMap::new(),
+            value_index: 0,
+            any_flag: AnyFlags::default(),
+        }
+    }
+}
+
+impl TryFrom<&Property> for ModSetting {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Property) -> Result<Self, Self::Error> {
+        match &value.value {
+            PropertyValue::Dictionary(dict) => {
+                let (value_index, _, property) = dict.get_full("value").ok_or(anyhow::anyhow!(
+                    "Mod setting dictionary missing value property"
+                ))?;
+                Ok(ModSetting {
+                    value: ModSettingsValue::try_from(property)?,
+                    extra: dict
+                        .iter()
+                        .filter(|(key, _)| *key != "value")
+                        .map(|(key, property)| (key.clone(), property.clone()))
+                        .collect(),
+                    value_index,
+                    any_flag: AnyFlags {
+                        setting: value.any_flag,
+                        value: property.any_flag,
+                    },
+                })
+            }
+            _ => Err(anyhow::anyhow!("Mod setting should be a dictionary")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", content = "value")]
 pub enum ModSettingsValue {
     None,
     Bool(bool),
     Double(f64),
     String(String),
-    Color { r: f64, g: f64, b: f64, a: f64 },
+    #[serde(deserialize_with = "deserialize_color")]
+    Color {
+        r: f64,
+        g: f64,
+        b: f64,
+        a: f64,
+    },
     Integer(i64),
 }
 
+impl ModSettingsValue {
+    /// A lowercase name for this value's type, as written in `section/key:type=value`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Bool(_) => "bool",
+            Self::Double(_) => "double",
+            Self::String(_) => "string",
+            Self::Color { .. } => "color",
+            Self::Integer(_) => "integer",
+        }
+    }
+}
+
+/// Reads a color written in any of the forms of `--color-format`: `{r, g, b, a}`, `[r, g, b, a]`
+/// or `"#rrggbbaa"`. Alpha is optional in each and defaults to 1.
+fn deserialize_color<'de, D>(deserializer: D) -> Result<(f64, f64, f64, f64), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Color {
+        Object {
+            r: f64,
+            g: f64,
+            b: f64,
+            #[serde(default = "opaque")]
+            a: f64,
+        },
+        Array(Vec<f64>),
+        Hex(String),
+    }
+    fn opaque() -> f64 {
+        1.0
+    }
+    match Color::deserialize(deserializer)? {
+        Color::Object { r, g, b, a } => Ok((r, g, b, a)),
+        Color::Array(channels) => match channels[..] {
+            [r, g, b, a] => Ok((r, g, b, a)),
+            [r, g, b] => Ok((r, g, b, 1.0)),
+            _ => Err(serde::de::Error::custom(format!(
+                "A color array has 3 or 4 channels, not {}",
+                channels.len()
+            ))),
+        },
+        Color::Hex(hex) => parse_hex_color(&hex).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "Invalid hex color {:?}, expected #rrggbb or #rrggbbaa",
+                hex
+            ))
+        }),
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa` into channels between 0 and 1
+fn parse_hex_color(hex: &str) -> Option<(f64, f64, f64, f64)> {
+    let digits = hex.strip_prefix('#')?;
+    if !matches!(digits.len(), 6 | 8) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| {
+        digits.get(i..i + 2).map_or(Some(1.0), |pair| {
+            Some(f64::from(u8::from_str_radix(pair, 16).ok()?) / 255.0)
+        })
+    };
+    Some((channel(0)?, channel(2)?, channel(4)?, channel(6)?))
+}
+
+/// Reads the property stored under a setting's `value` key
 impl TryFrom<&Property> for ModSettingsValue {
     type Error = anyhow::Error;
 
     fn try_from(value: &Property) -> Result<Self, Self::Error> {
         match &value.value {
+            PropertyValue::Bool(b) => Ok(ModSettingsValue::Bool(*b)),
+            PropertyValue::Double(n) => Ok(ModSettingsValue::Double(*n)),
+            PropertyValue::String(s) => Ok(ModSettingsValue::String(s.clone())),
             PropertyValue::Dictionary(dict) => {
-                let value = dict.get("value").ok_or(anyhow::anyhow!(
-                    "Mod setting dictionary missing value property"
-                ))?;
-                match &value.value {
-                    PropertyValue::Bool(b) => Ok(ModSettingsValue::Bool(*b)),
-                    PropertyValue::Double(n) => Ok(ModSettingsValue::Double(*n)),
-                    PropertyValue::String(s) => Ok(ModSettingsValue::String(s.clone())),
-                    PropertyValue::Dictionary(dict) => {
-                        let r = *dict.get("r")
-                            .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing r (red) value: {:?}", dict))?
-                            .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - r (red) value is not number"))?;
-                        let g = *dict.get("g")
-                            .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing g (green) value: {:?}", dict))?
-                            .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - g (green) value is not number"))?;
-                        let b = *dict.get("b")
-                            .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing b (blue) value: {:?}", dict))?
-                            .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - b (blue) value is not number"))?;
-                        let a = *dict.get("a")
-                            .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing a (alpha) value: {:?}", dict))?
-                            .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - a (alpha) value is not number"))?;
-                        Ok(ModSettingsValue::Color { r, g, b, a })
-                    }
-                    PropertyValue::Integer(i) => Ok(ModSettingsValue::Integer(*i)),
-                    b => Err(anyhow::anyhow!(
-                        "Mod setting value: Invalid type for value parameter: {:?}",
-                        b
-                    )),
-                }
+                let r = *dict.get("r")
+                    .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing r (red) value: {:?}", dict))?
+                    .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - r (red) value is not number"))?;
+                let g = *dict.get("g")
+                    .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing g (green) value: {:?}", dict))?
+                    .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - g (green) value is not number"))?;
+                let b = *dict.get("b")
+                    .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing b (blue) value: {:?}", dict))?
+                    .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - b (blue) value is not number"))?;
+                let a = *dict.get("a")
+                    .ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - missing a (alpha) value: {:?}", dict))?
+                    .value.as_double().ok_or(anyhow::anyhow!("Mod setting value is dictionary - assuming color - a (alpha) value is not number"))?;
+                Ok(ModSettingsValue::Color { r, g, b, a })
             }
-            _ => Err(anyhow::anyhow!("Mod setting should be a dictionary")),
+            PropertyValue::Integer(i) => Ok(ModSettingsValue::Integer(*i)),
+            PropertyValue::None => Ok(ModSettingsValue::None),
+            b => Err(anyhow::anyhow!(
+                "Mod setting value: Invalid type for value parameter: {:?}",
+                b
+            )),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ModSettings;
+    use super::{ConversionStats, ModSettings, ModSettingsValue};
     use crate::codec;
     use crate::types::FactorioVersion;
+    use hex_literal::hex;
     use indexmap::IndexMap;
     use std::fs::File;
-    use std::io::{BufReader, BufWriter, Write};
+    use std::io::{BufReader, BufWriter, Cursor, Write};
+
+    #[test]
+    fn color_forms() {
+        let read = |value: &str| {
+            serde_json::from_str::<ModSettingsValue>(&format!(
+                r#"{{"type": "Color", "value": {}}}"#,
+                value
+            ))
+        };
+        let expected = ModSettingsValue::Color {
+            r: 1.0,
+            g: 0.2,
+            b: 0.0,
+            a: 1.0,
+        };
+        for value in [
+            r#"{"r": 1, "g": 0.2, "b": 0, "a": 1}"#,
+            r#"{"r": 1, "g": 0.2, "b": 0}"#,
+            "[1, 0.2, 0, 1]",
+            "[1, 0.2, 0]",
+            r##""#ff3300ff""##,
+            r##""#FF3300""##,
+        ] {
+            assert_eq!(read(value).expect(value), expected, "{}", value);
+        }
+        for value in ["[1, 0.2]", r#""ff3300""#, r##""#ff33""##, r##""#gg3300""##] {
+            assert!(read(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn decodes_each_color_channel() {
+        let file = File::open("test_data/golden.dat").expect("opening file");
+        let settings =
+            codec::Settings::from_reader(&mut BufReader::new(file)).expect("decoding settings");
+        let settings = ModSettings::try_from(&settings).expect("to modsettings");
+        assert_eq!(
+            settings.runtime_per_user["golden-color"].value,
+            ModSettingsValue::Color {
+                r: 0.25,
+                g: 0.5,
+                b: 1.0,
+                a: 1.0 / 3.0,
+            }
+        );
+    }
+
+    #[test]
+    fn decodes_doubles() {
+        let file = File::open("test_data/golden.dat").expect("opening file");
+        let settings =
+            codec::Settings::from_reader(&mut BufReader::new(file)).expect("decoding settings");
+        let settings = ModSettings::try_from(&settings).expect("to modsettings");
+        let double = |key: &str| match settings.startup[key].value {
+            ModSettingsValue::Double(n) => n,
+            ref other => panic!("{} is {:?}", key, other),
+        };
+        assert_eq!(double("golden-double"), 0.1);
+        assert_eq!(double("golden-tiny-double"), f64::from_bits(1));
+        let zero = double("golden-negative-zero");
+        assert!(zero == 0.0 && zero.is_sign_negative());
+    }
+
+    #[test]
+    fn color_from_property() {
+        let channel = |value| codec::Property {
+            any_flag: false,
+            value,
+        };
+        let mut dict = ["r", "g", "b", "a"]
+            .into_iter()
+            .zip([0.25, 0.5, 1.0, 0.75])
+            .map(|(key, n)| (key.to_owned(), channel(codec::PropertyValue::Double(n))))
+            .collect::<IndexMap<_, _>>();
+        let color = channel(codec::PropertyValue::Dictionary(dict.clone()));
+        assert_eq!(
+            ModSettingsValue::try_from(&color).expect("converting"),
+            ModSettingsValue::Color {
+                r: 0.25,
+                g: 0.5,
+                b: 1.0,
+                a: 0.75,
+            }
+        );
+
+        dict.insert("g".to_owned(), channel(codec::PropertyValue::Integer(1)));
+        let err = ModSettingsValue::try_from(&channel(codec::PropertyValue::Dictionary(dict)))
+            .expect_err("integer channel");
+        assert!(err.to_string().contains("g (green) value is not number"));
+    }
+
+    #[test]
+    fn ndjson_records() {
+        let input = concat!(
+            r#"{"section": "runtime-global", "key": "b", "type": "Integer", "value": 2}"#,
+            "\n\n",
+            r#"{"factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0}}"#,
+            "\n",
+            r#"{"section": "startup", "key": "z", "type": "Bool", "value": true}"#,
+            "\n",
+            r#"{"section": "startup", "key": "a", "type": "String", "value": "x"}"#,
+            "\n",
+        );
+        let settings = ModSettings::from_ndjson(input.as_bytes()).expect("reading");
+        assert_eq!(settings.factorio_version.minor, 1);
+        let startup = settings
+            .startup
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(startup, ["z", "a"]);
+        assert_eq!(
+            settings.runtime_global["b"].value,
+            ModSettingsValue::Integer(2)
+        );
+
+        let err = ModSettings::from_ndjson(&input.as_bytes()[..input.len() - 10])
+            .expect_err("truncated line");
+        assert!(format!("{:#}", err).starts_with("Line 5"), "{:#}", err);
+        let err = ModSettings::from_ndjson(
+            &b"{\"section\": \"nowhere\", \"key\": \"k\", \"type\": \"None\"}"[..],
+        )
+        .expect_err("unknown section");
+        assert!(format!("{:#}", err).starts_with("Line 1"), "{:#}", err);
+        assert!(ModSettings::from_ndjson(&b""[..]).is_err(), "no version");
+
+        let repeated = format!(
+            "{}\n{}",
+            input, r#"{"section": "startup", "key": "z", "type": "Bool", "value": false}"#
+        );
+        let err = ModSettings::from_ndjson(repeated.as_bytes()).expect_err("repeated setting");
+        assert_eq!(
+            format!("{:#}", err),
+            "Line 7: startup/z is already set on line 4"
+        );
+        let version = r#"{"factorio_version": {"major": 2, "minor": 0, "patch": 0, "build": 0}}"#;
+        let err = ModSettings::from_ndjson(format!("{}{}", input, version).as_bytes())
+            .expect_err("repeated version");
+        assert!(
+            format!("{:#}", err).starts_with("Line 6: factorio_version"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    fn empty_sections() {
+        let data = hex!("01 00 01 00 52 00 04 00 00 05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 00 00 00 00 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00");
+        let settings = codec::Settings::from_reader(&mut Cursor::new(data)).expect("decoding");
+        let simple = ModSettings::try_from(&settings).expect("simplifying");
+        assert!(simple.startup.is_empty());
+        assert!(simple.runtime_global.is_empty());
+        assert!(simple.runtime_per_user.is_empty());
+
+        let mut encoded = Vec::new();
+        codec::Settings::from_simple(&simple)
+            .encode_to_writer(&mut encoded)
+            .expect("encoding");
+        assert_eq!(&data[..], &encoded[..]);
+    }
+
+    #[test]
+    fn empty_setting_dictionary_is_an_error() {
+        // startup contains "b" whose dictionary has no value
+        let data = hex!("01 00 01 00 52 00 04 00 00 05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 00 01 62 05 00 00 00 00 00 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00");
+        let settings = codec::Settings::from_reader(&mut Cursor::new(data)).expect("decoding");
+        let err = ModSettings::try_from(&settings).expect_err("simplifying");
+        assert!(err.to_string().contains("missing value"), "{}", err);
+    }
+
+    #[test]
+    fn sibling_keys_round_trip() {
+        let property = |value| codec::Property {
+            any_flag: false,
+            value,
+        };
+        let setting = property(codec::PropertyValue::Dictionary(IndexMap::from([
+            (
+                "min".to_owned(),
+                property(codec::PropertyValue::Double(0.0)),
+            ),
+            (
+                "value".to_owned(),
+                property(codec::PropertyValue::Double(0.5)),
+            ),
+            (
+                "hint".to_owned(),
+                property(codec::PropertyValue::String("fraction".to_owned())),
+            ),
+        ])));
+        let section = |entries: Vec<(String, codec::Property)>| {
+            property(codec::PropertyValue::Dictionary(
+                entries.into_iter().collect(),
+            ))
+        };
+        let settings = codec::Settings {
+            version: FactorioVersion::FIRST_WITH_HEADER,
+            properties: section(vec![
+                (
+                    "startup".to_owned(),
+                    section(vec![("a".to_owned(), setting)]),
+                ),
+                ("runtime-global".to_owned(), section(vec![])),
+                ("runtime-per-user".to_owned(), section(vec![])),
+            ]),
+        };
+        let mut data = Vec::new();
+        settings.encode_to_writer(&mut data).expect("encoding");
+
+        let simple = ModSettings::try_from(&settings).expect("simplifying");
+        assert_eq!(simple.startup["a"].value, ModSettingsValue::Double(0.5));
+        assert_eq!(
+            simple.startup["a"].extra.keys().collect::<Vec<_>>(),
+            ["min", "hint"]
+        );
+        assert_eq!(simple.startup["a"].value_index, 1);
+        for deserialized in [
+            serde_json::from_str::<ModSettings>(&serde_json::to_string(&simple).unwrap()).unwrap(),
+            toml::from_str::<ModSettings>(&toml::to_string(&simple).unwrap()).unwrap(),
+        ] {
+            assert_eq!(deserialized, simple);
+            let mut encoded = Vec::new();
+            codec::Settings::from(deserialized)
+                .encode_to_writer(&mut encoded)
+                .expect("encoding");
+            assert_eq!(encoded, data);
+        }
+    }
+
+    #[test]
+    fn conversion_stats() {
+        let mut settings = load_complex_settings();
+        let stats = ConversionStats::of(&settings, 0);
+        assert!(stats.settings > 0);
+        assert_eq!(stats.with_extra, 0);
+        settings.startup.insert(
+            "color".to_owned(),
+            ModSettingsValue::Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }
+            .into(),
+        );
+        let with_color = ConversionStats::of(&settings, 1);
+        assert_eq!(with_color.settings, stats.settings + 1);
+        assert_eq!(with_color.colors, stats.colors + 1);
+        assert_eq!(with_color.warnings, 1);
+    }
+
+    #[test]
+    fn validate_complex_settings() {
+        load_complex_settings()
+            .validate()
+            .expect("fixture is valid");
+    }
+
+    #[test]
+    fn validate_reports_all_errors() {
+        let mut settings = load_complex_settings();
+        settings
+            .startup
+            .insert("".to_owned(), ModSettingsValue::Bool(true).into());
+        settings
+            .runtime_global
+            .insert("nan".to_owned(), ModSettingsValue::Double(f64::NAN).into());
+        settings.runtime_per_user.insert(
+            "color".to_owned(),
+            ModSettingsValue::Color {
+                r: 1.5,
+                g: 0.0,
+                b: f64::INFINITY,
+                a: 1.0,
+            }
+            .into(),
+        );
+        let errors = settings.validate().expect_err("should be invalid");
+        let described = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert_eq!(described.len(), 4, "{:?}", described);
+        assert_eq!(described[0], "startup/: key is empty");
+        assert!(described[1].starts_with("runtime-global/nan"));
+        assert!(described[2].contains("channel r is outside"));
+        assert!(described[3].contains("channel b is not finite"));
+    }
 
     #[test]
     fn serialize_empty() {
         let settings = ModSettings {
-            factorio_version: FactorioVersion {
-                major: 2,
-                minor: 0,
-                build: 26,
-                patch: 2,
-            },
+            factorio_version: FactorioVersion::new(2, 0, 26, 2),
             startup: IndexMap::new(),
             runtime_global: IndexMap::new(),
             runtime_per_user: IndexMap::new(),
@@ -144,6 +808,7 @@ mod tests {
     #[test]
     fn serialize_complex_json() {
         let settings = load_complex_settings();
+        std::fs::create_dir_all("test_output").expect("creating output directory");
         serde_json::to_writer_pretty(
             BufWriter::new(
                 File::create("test_output/simplified-complex.json").expect("creating output file"),
@@ -157,6 +822,7 @@ mod tests {
     fn serialize_complex_toml() {
         let settings = load_complex_settings();
         let s_toml = toml::to_string_pretty(&settings).expect("serializing");
+        std::fs::create_dir_all("test_output").expect("creating output directory");
         let mut file = BufWriter::new(
             File::create("test_output/simplified-complex.toml").expect("creating output file"),
         );
@@ -180,4 +846,95 @@ mod tests {
             "Json Toml settings equal each other"
         );
     }
+
+    #[test]
+    fn every_variant_round_trips_through_toml() {
+        let mut settings = ModSettings {
+            factorio_version: FactorioVersion::FIRST_WITH_HEADER,
+            startup: IndexMap::new(),
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        };
+        let values = [
+            ModSettingsValue::None,
+            ModSettingsValue::Bool(false),
+            ModSettingsValue::Double(-2.5),
+            ModSettingsValue::String(String::new()),
+            ModSettingsValue::Color {
+                r: 0.0,
+                g: 0.25,
+                b: 0.5,
+                a: 1.0,
+            },
+            ModSettingsValue::Integer(i64::MIN),
+        ];
+        for (index, value) in values.into_iter().enumerate() {
+            settings
+                .startup
+                .insert(format!("setting-{}", index), value.into());
+        }
+        let s_toml = toml::to_string_pretty(&settings).expect("serializing toml");
+        assert!(
+            s_toml.contains("[startup.setting-0]\ntype = \"None\"\n"),
+            "{}",
+            s_toml
+        );
+        let toml_settings: ModSettings = toml::from_str(&s_toml).expect("deserializing toml");
+        assert_eq!(settings, toml_settings);
+    }
+
+    #[test]
+    fn integers_round_trip_through_binary() {
+        let mut settings = ModSettings::empty(FactorioVersion::FIRST_WITH_HEADER);
+        // 2^53 + 1 has no exact double, so it only survives as an integer
+        for (key, value) in [("small", 3), ("large", (1 << 53) + 1), ("min", i64::MIN)] {
+            settings
+                .startup
+                .insert(key.to_owned(), ModSettingsValue::Integer(value).into());
+        }
+        settings
+            .startup
+            .insert("double".to_owned(), ModSettingsValue::Double(3.0).into());
+
+        let mut encoded = Vec::new();
+        codec::Settings::from_simple(&settings)
+            .encode_to_writer(&mut encoded)
+            .expect("encoding");
+        let decoded = codec::Settings::from_reader(&mut Cursor::new(&encoded)).expect("decoding");
+        assert_eq!(
+            decoded.properties.value.as_dictionary().expect("root")["startup"]
+                .value
+                .as_dictionary()
+                .expect("startup")["small"]
+                .value
+                .as_dictionary()
+                .expect("setting")["value"]
+                .value,
+            codec::PropertyValue::Integer(3)
+        );
+        let decoded = ModSettings::try_from(&decoded).expect("simplifying");
+        assert_eq!(decoded, settings);
+
+        // integers and doubles stay apart in text
+        let json = serde_json::to_value(&decoded).expect("serializing json");
+        assert_eq!(
+            json["startup"]["large"],
+            serde_json::json!({"type": "Integer", "value": 9007199254740993_i64})
+        );
+        assert_eq!(
+            json["startup"]["double"],
+            serde_json::json!({"type": "Double", "value": 3.0})
+        );
+        let toml = toml::to_string(&decoded).expect("serializing toml");
+        assert!(
+            toml.contains("[startup.small]\ntype = \"Integer\"\nvalue = 3\n"),
+            "{}",
+            toml
+        );
+        assert!(
+            toml.contains("[startup.double]\ntype = \"Double\"\nvalue = 3.0\n"),
+            "{}",
+            toml
+        );
+    }
 }
@@ -0,0 +1,125 @@
+use crate::simple::{RUNTIME_GLOBAL, RUNTIME_PER_USER, STARTUP};
+use indexmap::IndexMap;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use toml::Spanned;
+
+/// A position in a text document, counted from 1
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of a byte offset into the text
+    fn of(text: &str, offset: usize) -> Location {
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Where each setting's key was written in a TOML document, so problems found after parsing can
+/// point back at the line to fix
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    locations: HashMap<(&'static str, String), Location>,
+}
+
+impl SourceMap {
+    /// Maps the settings in TOML text. The text should already have parsed as settings; anything
+    /// this can't read is simply left unmapped.
+    pub fn of_toml(text: &str) -> SourceMap {
+        type Keys = IndexMap<Spanned<String>, IgnoredAny>;
+        #[derive(Deserialize)]
+        struct Sections {
+            #[serde(default)]
+            startup: Keys,
+            #[serde(default, rename = "runtime-global")]
+            runtime_global: Keys,
+            #[serde(default, rename = "runtime-per-user")]
+            runtime_per_user: Keys,
+        }
+
+        let mut map = SourceMap::default();
+        let Ok(sections) = toml::from_str::<Sections>(text) else {
+            return map;
+        };
+        for (section, keys) in [
+            (STARTUP, sections.startup),
+            (RUNTIME_GLOBAL, sections.runtime_global),
+            (RUNTIME_PER_USER, sections.runtime_per_user),
+        ] {
+            for key in keys.into_keys() {
+                let location = Location::of(text, key.span().start);
+                map.locations.insert((section, key.into_inner()), location);
+            }
+        }
+        map
+    }
+
+    pub fn locate(&self, section: &'static str, key: &str) -> Option<Location> {
+        self.locations.get(&(section, key.to_owned())).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Location, SourceMap};
+
+    #[test]
+    fn locations() {
+        let text = "é = 1\nab = 2";
+        assert_eq!(Location::of(text, 0), Location { line: 1, column: 1 });
+        assert_eq!(Location::of(text, 4), Location { line: 1, column: 4 });
+        assert_eq!(Location::of(text, 8), Location { line: 2, column: 2 });
+    }
+
+    #[test]
+    fn maps_keys_in_any_table_form() {
+        let text = concat!(
+            "[factorio_version]\nmajor = 1\nminor = 1\npatch = 0\nbuild = 0\n\n",
+            "[startup.first]\ntype = \"Bool\"\nvalue = true\n\n",
+            "[startup]\n  second = { type = \"Integer\", value = 2 }\n\n",
+            "[runtime-per-user.\"quoted key\"]\ntype = \"None\"\n",
+        );
+        let map = SourceMap::of_toml(text);
+        assert_eq!(
+            map.locate("startup", "first"),
+            Some(Location {
+                line: 7,
+                column: 10
+            })
+        );
+        assert_eq!(
+            map.locate("startup", "second"),
+            Some(Location {
+                line: 12,
+                column: 3
+            })
+        );
+        assert_eq!(
+            map.locate("runtime-per-user", "quoted key"),
+            Some(Location {
+                line: 14,
+                column: 19
+            })
+        );
+        assert_eq!(map.locate("runtime-global", "first"), None);
+        assert!(SourceMap::of_toml("not toml [")
+            .locate("startup", "first")
+            .is_none());
+    }
+}
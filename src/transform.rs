@@ -0,0 +1,1062 @@
+use crate::args::ColorFormat;
+use crate::codec::{Property, PropertyValue};
+use crate::setting::SettingPath;
+use crate::simple::{
+    section_name, ModSetting, ModSettings, ModSettingsValue, RUNTIME_GLOBAL, RUNTIME_PER_USER,
+    STARTUP,
+};
+use indexmap::IndexMap;
+
+/// Reorders each section to follow the key order of the same section in `template`. Keys the
+/// template doesn't mention keep their relative order and are placed after the known keys.
+pub fn order_like(settings: &mut ModSettings, template: &ModSettings) {
+    for ((_, section), (_, template_section)) in
+        settings.sections_mut().into_iter().zip(template.sections())
+    {
+        order_section_like(section, template_section);
+    }
+}
+
+/// Applies an overlay: each of its settings is added to `settings`, replacing any with the same
+/// key in place. Returns the paths of the settings it set.
+pub fn overlay(settings: &mut ModSettings, overlay: ModSettings) -> Vec<SettingPath> {
+    let mut set = Vec::new();
+    for ((section, map), overlay_map) in settings.sections_mut().into_iter().zip([
+        overlay.startup,
+        overlay.runtime_global,
+        overlay.runtime_per_user,
+    ]) {
+        for (key, setting) in overlay_map {
+            set.push(SettingPath {
+                section,
+                key: key.clone(),
+            });
+            map.insert(key, setting);
+        }
+    }
+    set
+}
+
+/// Removes the settings whose value is None, returning how many were removed
+pub fn omit_none(settings: &mut ModSettings) -> usize {
+    let mut omitted = 0;
+    for (_, map) in settings.sections_mut() {
+        let before = map.len();
+        map.retain(|_, setting| setting.value != ModSettingsValue::None);
+        omitted += before - map.len();
+    }
+    omitted
+}
+
+/// Trims leading and trailing whitespace from String values, returning the settings changed
+pub fn trim_strings(settings: &mut ModSettings) -> Vec<SettingPath> {
+    let mut trimmed = Vec::new();
+    for (section, map) in settings.sections_mut() {
+        for (key, setting) in map {
+            if let ModSettingsValue::String(value) = &mut setting.value {
+                if value.trim() != value {
+                    *value = value.trim().to_owned();
+                    trimmed.push(SettingPath {
+                        section,
+                        key: key.clone(),
+                    });
+                }
+            }
+        }
+    }
+    trimmed
+}
+
+/// The group for settings without a recognizable mod prefix. Prefixes end at the first `-`, so
+/// no mod's group can have this name, even a mod called `unknown`.
+pub const UNKNOWN_MOD: &str = "unknown-mod";
+
+/// The conventional mod prefix of a setting key: the part before the first `-` or `_`
+pub fn mod_prefix(key: &str) -> Option<&str> {
+    key.split_once(['-', '_'])
+        .map(|(prefix, _)| prefix)
+        .filter(|prefix| !prefix.is_empty())
+}
+
+/// Settings keyed by inferred mod name, then section, then setting key
+pub type ModGroups = IndexMap<String, IndexMap<&'static str, IndexMap<String, ModSetting>>>;
+
+/// Regroups settings by the mod prefix of their keys, in order of first appearance. Keys without a
+/// prefix are grouped under [`UNKNOWN_MOD`], which is placed last.
+pub fn group_by_mod(settings: &ModSettings) -> ModGroups {
+    let mut groups = ModGroups::new();
+    let mut unknown = IndexMap::new();
+    for (section, map) in settings.sections() {
+        for (key, value) in map {
+            let group = match mod_prefix(key) {
+                Some(prefix) => groups.entry(prefix.to_owned()).or_default(),
+                None => &mut unknown,
+            };
+            group
+                .entry(section)
+                .or_insert_with(IndexMap::new)
+                .insert(key.clone(), value.clone());
+        }
+    }
+    if !unknown.is_empty() {
+        groups.insert(UNKNOWN_MOD.to_owned(), unknown);
+    }
+    groups
+}
+
+/// Which settings may be written, from lists of glob patterns. A pattern may be limited to one
+/// section by prefixing it with the section name, as in `startup/bnl-*`.
+#[derive(Debug, Default)]
+pub struct KeyFilter {
+    /// When present, only settings matching one of these are kept
+    allow: Option<Vec<KeyPattern>>,
+    /// Settings matching any of these are dropped, even if allowed
+    deny: Vec<KeyPattern>,
+}
+
+#[derive(Debug)]
+struct KeyPattern {
+    section: Option<&'static str>,
+    glob: String,
+}
+
+impl KeyPattern {
+    fn parse(line: &str) -> KeyPattern {
+        match line
+            .split_once('/')
+            .and_then(|(section, glob)| Some((section_name(section).ok()?, glob)))
+        {
+            Some((section, glob)) => KeyPattern {
+                section: Some(section),
+                glob: glob.to_owned(),
+            },
+            None => KeyPattern {
+                section: None,
+                glob: line.to_owned(),
+            },
+        }
+    }
+
+    fn matches(&self, section: &str, key: &str) -> bool {
+        self.section.is_none_or(|s| s == section) && glob_match(&self.glob, key)
+    }
+}
+
+/// Parses a pattern list with one pattern per line, ignoring blank lines and `#` comments
+fn parse_patterns(text: &str) -> Vec<KeyPattern> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(KeyPattern::parse)
+        .collect()
+}
+
+impl KeyFilter {
+    /// Creates a filter from the text of the allow and deny pattern lists
+    pub fn new(allow: Option<&str>, deny: Option<&str>) -> KeyFilter {
+        KeyFilter {
+            allow: allow.map(parse_patterns),
+            deny: deny.map(parse_patterns).unwrap_or_default(),
+        }
+    }
+
+    fn allows(&self, section: &str, key: &str) -> bool {
+        let allowed = match &self.allow {
+            Some(allow) => allow.iter().any(|p| p.matches(section, key)),
+            None => true,
+        };
+        allowed && !self.deny.iter().any(|p| p.matches(section, key))
+    }
+
+    /// Drops the settings the filter doesn't allow, returning how many were dropped
+    pub fn apply(&self, settings: &mut ModSettings) -> usize {
+        let mut dropped = 0;
+        for (section, map) in settings.sections_mut() {
+            let before = map.len();
+            map.retain(|key, _| self.allows(section, key));
+            dropped += before - map.len();
+        }
+        dropped
+    }
+}
+
+/// Renames settings, for carrying values over when a mod renames its settings. The map has one
+/// `old = new` rule per line, ignoring blank lines and `#` comments. The old key may be limited to
+/// one section by prefixing it with the section name, as in `startup/old-key = new-key`, and a
+/// trailing `*` on both sides renames every key with the prefix, as in `old-mod-* = new-mod-*`.
+/// The first matching rule applies.
+#[derive(Debug, Default)]
+pub struct RenameMap {
+    rules: Vec<RenameRule>,
+}
+
+#[derive(Debug)]
+struct RenameRule {
+    section: Option<&'static str>,
+    from: String,
+    to: String,
+    /// Whether `from` and `to` are prefixes, the rest of the key being kept
+    prefix: bool,
+}
+
+/// What applying a [`RenameMap`] did
+#[derive(Debug, Default)]
+pub struct Renamed {
+    pub count: usize,
+    /// Settings left alone because their new key was already present, with that key
+    pub collisions: Vec<(SettingPath, String)>,
+}
+
+impl RenameRule {
+    fn rename(&self, section: &str, key: &str) -> Option<String> {
+        if self.section.is_some_and(|s| s != section) {
+            return None;
+        }
+        if self.prefix {
+            key.strip_prefix(&self.from)
+                .map(|rest| format!("{}{}", self.to, rest))
+        } else {
+            (key == self.from).then(|| self.to.clone())
+        }
+    }
+}
+
+impl RenameMap {
+    pub fn parse(text: &str) -> anyhow::Result<RenameMap> {
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fail =
+                |message: &str| anyhow::anyhow!("Line {}: {}: {:?}", index + 1, message, line);
+            let (from, to) = line
+                .split_once('=')
+                .map(|(from, to)| (from.trim(), to.trim()))
+                .ok_or_else(|| fail("expected old = new"))?;
+            let pattern = KeyPattern::parse(from);
+            let from_prefix = pattern.glob.strip_suffix('*');
+            let to_prefix = to.strip_suffix('*');
+            if from_prefix.is_some() != to_prefix.is_some() {
+                return Err(fail("a prefix rename needs a trailing * on both sides"));
+            }
+            let from = from_prefix.unwrap_or(&pattern.glob);
+            let to = to_prefix.unwrap_or(to);
+            if from.contains(['*', '?']) || to.contains(['*', '?']) {
+                return Err(fail("only a trailing * is supported"));
+            }
+            if to.is_empty() || (from.is_empty() && from_prefix.is_none()) {
+                return Err(fail("keys can't be empty"));
+            }
+            rules.push(RenameRule {
+                section: pattern.section,
+                from: from.to_owned(),
+                to: to.to_owned(),
+                prefix: from_prefix.is_some(),
+            });
+        }
+        Ok(RenameMap { rules })
+    }
+
+    /// Renames the matching settings, keeping their positions. Keys not in the map are unchanged.
+    /// Collisions are judged by the keys after renaming, so keys can be swapped, but a setting is
+    /// never renamed onto a key another setting keeps or an earlier setting is renamed to.
+    pub fn apply(&self, settings: &mut ModSettings) -> Renamed {
+        let mut renamed = Renamed::default();
+        for (section, map) in settings.sections_mut() {
+            let mut entries = std::mem::take(map)
+                .into_iter()
+                .map(|(key, setting)| {
+                    let new = self
+                        .rules
+                        .iter()
+                        .find_map(|rule| rule.rename(section, &key));
+                    (key, setting, new)
+                })
+                .collect::<Vec<_>>();
+            // undo one colliding rename at a time, since its setting then keeps its old key, which
+            // another rename may have been going to
+            while let Some(index) = first_collision(&entries) {
+                let (key, _, new) = &mut entries[index];
+                let new = new.take().expect("only renames collide");
+                let path = SettingPath {
+                    section,
+                    key: key.clone(),
+                };
+                renamed.collisions.push((path, new));
+            }
+            for (key, setting, new) in entries {
+                if new.is_some() {
+                    renamed.count += 1;
+                }
+                map.insert(new.unwrap_or(key), setting);
+            }
+        }
+        renamed
+    }
+}
+
+/// The index of the first rename onto a key that a kept setting or an earlier rename claims
+fn first_collision(entries: &[(String, ModSetting, Option<String>)]) -> Option<usize> {
+    let mut claimed = entries
+        .iter()
+        .filter(|(_, _, new)| new.is_none())
+        .map(|(key, _, _)| key.as_str())
+        .collect::<std::collections::HashSet<_>>();
+    entries
+        .iter()
+        .position(|(_, _, new)| new.as_deref().is_some_and(|new| !claimed.insert(new)))
+}
+
+/// Matches text against a glob where `*` matches any run of characters and `?` matches one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // the position of the last `*` and the text position it was tried at, to backtrack to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Sorts the keys of every object in the value, recursively. Arrays keep their order.
+pub fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, sort_keys(value)))
+                .collect()
+        }
+        serde_json::Value::Array(items) => items.into_iter().map(sort_keys).collect(),
+        other => other,
+    }
+}
+
+/// Sorts the keys of every table in a TOML value, recursively, as [`sort_keys`] does for JSON
+pub fn sort_toml_keys(value: toml::Value) -> toml::Value {
+    match value {
+        toml::Value::Table(table) => {
+            let mut entries = table.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            toml::Value::Table(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_toml_keys(value)))
+                    .collect(),
+            )
+        }
+        toml::Value::Array(items) => {
+            toml::Value::Array(items.into_iter().map(sort_toml_keys).collect())
+        }
+        other => other,
+    }
+}
+
+/// Rewrites each color in serialized settings, the `value` and `default` of any object whose
+/// `type` is "Color", in the given form. Returns the paths of colors left as objects because hex
+/// can't hold their channels exactly.
+pub fn format_colors(value: &mut serde_json::Value, format: ColorFormat) -> Vec<String> {
+    let mut inexact = Vec::new();
+    format_colors_at(value, "", format, &mut inexact);
+    inexact
+}
+
+fn format_colors_at(
+    value: &mut serde_json::Value,
+    path: &str,
+    format: ColorFormat,
+    inexact: &mut Vec<String>,
+) {
+    match value {
+        serde_json::Value::Object(map)
+            if map.get("type").and_then(serde_json::Value::as_str) == Some("Color") =>
+        {
+            let mut exact = true;
+            for field in ["value", "default"] {
+                if let Some(color) = map.get_mut(field) {
+                    match format_color(color, format) {
+                        Some(formatted) => *color = formatted,
+                        None => exact = false,
+                    }
+                }
+            }
+            if !exact {
+                inexact.push(path.to_owned());
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, child) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}/{}", path, key)
+                };
+                format_colors_at(child, &path, format, inexact);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                format_colors_at(item, path, format, inexact);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A color object in the given form, or None if hex can't hold its channels exactly
+pub fn format_color(color: &serde_json::Value, format: ColorFormat) -> Option<serde_json::Value> {
+    let channels = ["r", "g", "b", "a"].map(|c| color.get(c).and_then(serde_json::Value::as_f64));
+    let [Some(r), Some(g), Some(b), Some(a)] = channels else {
+        return Some(color.clone());
+    };
+    match format {
+        ColorFormat::Object => Some(color.clone()),
+        ColorFormat::Array => Some(serde_json::json!([r, g, b, a])),
+        ColorFormat::Hex => hex_color([r, g, b, a]).map(Into::into),
+    }
+}
+
+/// Rewrites each color in settings serialized as TOML, as [`format_colors`] does for JSON. Unlike
+/// JSON's values, TOML's hold doubles such as NaN and infinity.
+pub fn format_toml_colors(value: &mut toml::Value, format: ColorFormat) -> Vec<String> {
+    let mut inexact = Vec::new();
+    format_toml_colors_at(value, "", format, &mut inexact);
+    inexact
+}
+
+fn format_toml_colors_at(
+    value: &mut toml::Value,
+    path: &str,
+    format: ColorFormat,
+    inexact: &mut Vec<String>,
+) {
+    match value {
+        toml::Value::Table(table)
+            if table.get("type").and_then(toml::Value::as_str) == Some("Color") =>
+        {
+            let mut exact = true;
+            for field in ["value", "default"] {
+                if let Some(color) = table.get_mut(field) {
+                    match format_toml_color(color, format) {
+                        Some(formatted) => *color = formatted,
+                        None => exact = false,
+                    }
+                }
+            }
+            if !exact {
+                inexact.push(path.to_owned());
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, child) in table {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}/{}", path, key)
+                };
+                format_toml_colors_at(child, &path, format, inexact);
+            }
+        }
+        toml::Value::Array(items) => {
+            for item in items {
+                format_toml_colors_at(item, path, format, inexact);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn format_toml_color(color: &toml::Value, format: ColorFormat) -> Option<toml::Value> {
+    let channels = ["r", "g", "b", "a"].map(|c| color.get(c).and_then(toml::Value::as_float));
+    let [Some(r), Some(g), Some(b), Some(a)] = channels else {
+        return Some(color.clone());
+    };
+    match format {
+        ColorFormat::Object => Some(color.clone()),
+        ColorFormat::Array => Some(toml::Value::Array(
+            [r, g, b, a].map(toml::Value::Float).to_vec(),
+        )),
+        ColorFormat::Hex => hex_color([r, g, b, a]).map(toml::Value::String),
+    }
+}
+
+/// The channels written as a hex color, or None if hex can't hold them exactly
+fn hex_color(channels: [f64; 4]) -> Option<String> {
+    let mut hex = String::from("#");
+    for channel in channels {
+        let step = (channel * 255.0).round();
+        if !(0.0..=255.0).contains(&step) || step / 255.0 != channel {
+            return None;
+        }
+        hex.push_str(&format!("{:02x}", step as u8));
+    }
+    Some(hex)
+}
+
+/// Turns the numbers 0 and 1 into bools in serialized settings, for settings whose value the
+/// schema says is a bool. Returns the paths of the settings changed.
+pub fn coerce_bools(settings: &mut serde_json::Value, schema: &serde_json::Value) -> Vec<String> {
+    let mut coerced = Vec::new();
+    for section in [STARTUP, RUNTIME_GLOBAL, RUNTIME_PER_USER] {
+        let Some(map) = settings.get_mut(section).and_then(|s| s.as_object_mut()) else {
+            continue;
+        };
+        for (key, setting) in map {
+            if schema[section][key]["value"] != "bool" {
+                continue;
+            }
+            let number = setting.get("value").and_then(serde_json::Value::as_f64);
+            let Some(value) = number.filter(|n| *n == 0.0 || *n == 1.0) else {
+                continue;
+            };
+            setting["type"] = "Bool".into();
+            setting["value"] = (value == 1.0).into();
+            coerced.push(format!("{}/{}", section, key));
+        }
+    }
+    coerced
+}
+
+/// The shape of a property tree without its values: dictionaries and lists keep their structure,
+/// and every other property is replaced by the name of its type
+pub fn schema(property: &Property) -> serde_json::Value {
+    match &property.value {
+        PropertyValue::Dictionary(dict) => dict
+            .iter()
+            .map(|(key, value)| (key.clone(), schema(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        PropertyValue::List(list) => list.iter().map(|item| schema(&item.property)).collect(),
+        value => value.type_name().into(),
+    }
+}
+
+/// A place where a property tree doesn't have the shape a schema from [`schema`] describes
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct SchemaViolation {
+    /// The keys and list indexes leading to the property, joined with `/`
+    pub path: String,
+    /// The type the schema has there, or "nothing" for a property it doesn't have
+    pub expected: String,
+    /// The type the tree has there, or "nothing" for a missing property
+    pub found: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "(root)"
+        } else {
+            &self.path
+        };
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            path, self.expected, self.found
+        )
+    }
+}
+
+/// Compares the schema of a property tree against an expected one, as written by --schema-only,
+/// listing every property missing, unexpected or of a different type
+pub fn schema_violations(
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+) -> Vec<SchemaViolation> {
+    let mut violations = Vec::new();
+    compare_schemas(Some(expected), Some(actual), String::new(), &mut violations);
+    violations
+}
+
+fn compare_schemas(
+    expected: Option<&serde_json::Value>,
+    actual: Option<&serde_json::Value>,
+    path: String,
+    violations: &mut Vec<SchemaViolation>,
+) {
+    use serde_json::Value;
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}/{}", path, key)
+        }
+    };
+    match (expected, actual) {
+        (Some(Value::Object(expected)), Some(Value::Object(actual))) => {
+            for (key, value) in expected {
+                compare_schemas(Some(value), actual.get(key), child(key), violations);
+            }
+            for (key, value) in actual
+                .iter()
+                .filter(|(key, _)| !expected.contains_key(*key))
+            {
+                compare_schemas(None, Some(value), child(key), violations);
+            }
+        }
+        (Some(Value::Array(expected)), Some(Value::Array(actual))) => {
+            for index in 0..expected.len().max(actual.len()) {
+                compare_schemas(
+                    expected.get(index),
+                    actual.get(index),
+                    child(&index.to_string()),
+                    violations,
+                );
+            }
+        }
+        (Some(Value::String(expected)), Some(Value::String(actual))) if expected == actual => {}
+        _ => violations.push(SchemaViolation {
+            path,
+            expected: schema_type(expected),
+            found: schema_type(actual),
+        }),
+    }
+}
+
+/// The property type a node of a schema stands for
+fn schema_type(node: Option<&serde_json::Value>) -> String {
+    match node {
+        None => "nothing".to_owned(),
+        Some(serde_json::Value::Object(_)) => "dictionary".to_owned(),
+        Some(serde_json::Value::Array(_)) => "list".to_owned(),
+        Some(serde_json::Value::String(name)) => name.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn order_section_like(
+    section: &mut IndexMap<String, ModSetting>,
+    template: &IndexMap<String, ModSetting>,
+) {
+    let mut remaining = std::mem::take(section);
+    let mut ordered = IndexMap::with_capacity(remaining.len());
+    for key in template.keys() {
+        if let Some((key, value)) = remaining.shift_remove_entry(key) {
+            ordered.insert(key, value);
+        }
+    }
+    ordered.extend(remaining);
+    *section = ordered;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        coerce_bools, format_colors, format_toml_colors, glob_match, group_by_mod, mod_prefix,
+        omit_none, order_like, overlay, schema, schema_violations, sort_keys, sort_toml_keys,
+        trim_strings, KeyFilter, RenameMap,
+    };
+    use crate::args::ColorFormat;
+    use crate::codec::Settings;
+    use crate::simple::{ModSettings, ModSettingsValue};
+    use crate::types::FactorioVersion;
+    use indexmap::IndexMap;
+
+    fn settings(startup: &[&str]) -> ModSettings {
+        ModSettings {
+            factorio_version: FactorioVersion::new(1, 1, 82, 0),
+            startup: startup
+                .iter()
+                .map(|key| (key.to_string(), ModSettingsValue::Bool(true).into()))
+                .collect(),
+            runtime_global: IndexMap::new(),
+            runtime_per_user: IndexMap::new(),
+        }
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(mod_prefix("bnl-enable"), Some("bnl"));
+        assert_eq!(mod_prefix("YARM_debug"), Some("YARM"));
+        assert_eq!(mod_prefix("standalone"), None);
+        assert_eq!(mod_prefix("-leading"), None);
+    }
+
+    #[test]
+    fn groups_by_prefix() {
+        let mut input = settings(&["a-one", "plain", "b-one", "a-two", "unknown-one"]);
+        input
+            .runtime_global
+            .insert("a-three".to_owned(), ModSettingsValue::Bool(false).into());
+        let groups = group_by_mod(&input);
+        assert_eq!(
+            groups.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["a", "b", "unknown", "unknown-mod"]
+        );
+        assert_eq!(groups["a"]["startup"].len(), 2);
+        assert_eq!(groups["a"]["runtime-global"].len(), 1);
+        assert!(groups["unknown"]["startup"].contains_key("unknown-one"));
+        assert!(groups["unknown-mod"]["startup"].contains_key("plain"));
+        assert!(!groups["unknown-mod"]["startup"].contains_key("unknown-one"));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("bnl-*", "bnl-enable"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*-debug-*", "mod-debug-level"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(!glob_match("bnl-*", "yarm-enable"));
+        assert!(!glob_match("abc", "abcd"));
+    }
+
+    #[test]
+    fn key_filter() {
+        let mut input = settings(&["a-one", "a-debug", "b-one", "c-one"]);
+        input
+            .runtime_global
+            .insert("b-two".to_owned(), ModSettingsValue::Bool(false).into());
+        let filter = KeyFilter::new(
+            Some("# approved mods\na-*\n\nstartup/b-*\n"),
+            Some("*-debug"),
+        );
+        assert_eq!(filter.apply(&mut input), 3);
+        let keys = input.startup.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, ["a-one", "b-one"]);
+        assert!(input.runtime_global.is_empty());
+    }
+
+    #[test]
+    fn coerces_numbers_the_schema_says_are_bools() {
+        let schema = serde_json::json!({
+            "startup": {"on": {"value": "bool"}, "off": {"value": "bool"}, "two": {"value": "bool"}},
+            "runtime-global": {"count": {"value": "integer"}},
+        });
+        let mut settings = serde_json::json!({
+            "startup": {
+                "on": {"type": "Bool", "value": 1},
+                "off": {"type": "Integer", "value": 0},
+                "two": {"type": "Integer", "value": 2},
+                "unknown": {"type": "Integer", "value": 1},
+            },
+            "runtime-global": {"count": {"type": "Integer", "value": 1}},
+        });
+        assert_eq!(
+            coerce_bools(&mut settings, &schema),
+            ["startup/on", "startup/off"]
+        );
+        assert_eq!(
+            settings["startup"]["on"],
+            serde_json::json!({"type": "Bool", "value": true})
+        );
+        assert_eq!(
+            settings["startup"]["off"],
+            serde_json::json!({"type": "Bool", "value": false})
+        );
+        assert_eq!(settings["startup"]["two"]["value"], 2);
+        assert_eq!(settings["startup"]["unknown"]["value"], 1);
+        assert_eq!(settings["runtime-global"]["count"]["value"], 1);
+    }
+
+    #[test]
+    fn schema_mismatches() {
+        let expected = serde_json::json!({
+            "startup": {"a": {"value": "bool"}, "b": {"value": "integer"}},
+            "runtime-global": {"list": ["string", "string"]},
+        });
+        let actual = serde_json::json!({
+            "startup": {"a": {"value": "double"}, "c": {"value": "bool"}},
+            "runtime-global": {"list": ["string"]},
+        });
+        assert!(schema_violations(&expected, &expected).is_empty());
+        let found = schema_violations(&expected, &actual)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                "startup/a/value: expected bool, found double",
+                "startup/b: expected dictionary, found nothing",
+                "startup/c: expected nothing, found dictionary",
+                "runtime-global/list/1: expected string, found nothing",
+            ]
+        );
+        assert_eq!(
+            schema_violations(&expected, &serde_json::json!("none"))[0].to_string(),
+            "(root): expected dictionary, found none"
+        );
+    }
+
+    #[test]
+    fn schema_of_settings() {
+        let mut input = settings(&["a"]);
+        input.runtime_global.insert(
+            "color".to_owned(),
+            ModSettingsValue::Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }
+            .into(),
+        );
+        let tree = Settings::from_simple(&input);
+        assert_eq!(
+            schema(&tree.properties),
+            serde_json::json!({
+                "startup": {"a": {"value": "bool"}},
+                "runtime-global": {
+                    "color": {"value": {"r": "double", "g": "double", "b": "double", "a": "double"}}
+                },
+                "runtime-per-user": {}
+            })
+        );
+    }
+
+    #[test]
+    fn overlays_replace_in_place_and_append() {
+        let mut base = settings(&["a", "b"]);
+        let mut top = settings(&["c", "a"]);
+        top.startup
+            .insert("a".to_owned(), ModSettingsValue::Integer(2).into());
+        let set = overlay(&mut base, top);
+        assert_eq!(
+            set.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["startup/c", "startup/a"]
+        );
+        let keys = base.startup.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "b", "c"]);
+        assert_eq!(base.startup["a"].value, ModSettingsValue::Integer(2));
+    }
+
+    #[test]
+    fn omits_none_values() {
+        let mut settings = settings(&["a", "b", "c"]);
+        settings
+            .runtime_global
+            .insert("d".to_owned(), ModSettingsValue::None.into());
+        settings.startup["b"].value = ModSettingsValue::None;
+        assert_eq!(omit_none(&mut settings), 2);
+        let keys = settings
+            .startup
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "c"]);
+        assert!(settings.runtime_global.is_empty());
+    }
+
+    #[test]
+    fn trims_string_values() {
+        let mut settings = settings(&["a", " b"]);
+        settings.startup["a"].value = ModSettingsValue::String(" server \t".to_owned());
+        settings.startup[" b"].value = ModSettingsValue::String("name".to_owned());
+        settings.runtime_global.insert(
+            "c".to_owned(),
+            ModSettingsValue::String("\n".to_owned()).into(),
+        );
+        let trimmed = trim_strings(&mut settings);
+        assert_eq!(
+            trimmed.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["startup/a", "runtime-global/c"]
+        );
+        assert_eq!(
+            settings.startup["a"].value,
+            ModSettingsValue::String("server".to_owned())
+        );
+        assert!(settings.startup.contains_key(" b"), "keys are left alone");
+        assert_eq!(
+            settings.runtime_global["c"].value,
+            ModSettingsValue::String(String::new())
+        );
+    }
+
+    #[test]
+    fn renames() {
+        let mut settings = settings(&["old-a", "keep", "old-b", "taken", "legacy-x"]);
+        settings
+            .runtime_global
+            .insert("old-a".to_owned(), ModSettingsValue::Integer(1).into());
+        let map = RenameMap::parse(
+            "# migrations\n\
+             startup/old-* = new-*\n\
+             legacy-x = taken\n\
+             \n\
+             runtime-global/old-a = renamed-a\n",
+        )
+        .expect("parsing");
+        let renamed = map.apply(&mut settings);
+        assert_eq!(renamed.count, 3);
+        let keys = settings
+            .startup
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["new-a", "keep", "new-b", "taken", "legacy-x"]);
+        assert_eq!(
+            settings.runtime_global.keys().collect::<Vec<_>>(),
+            ["renamed-a"]
+        );
+        assert_eq!(renamed.collisions.len(), 1);
+        assert_eq!(renamed.collisions[0].0.to_string(), "startup/legacy-x");
+        assert_eq!(renamed.collisions[0].1, "taken");
+    }
+
+    #[test]
+    fn renames_judge_collisions_after_renaming() {
+        let mut swapped = settings(&["a", "b", "c", "d", "e"]);
+        swapped
+            .startup
+            .insert("a".to_owned(), ModSettingsValue::Integer(1).into());
+        // a and b swap, c and e move onto keys freed by d and c, and d's new key isn't renamed again
+        let map = RenameMap::parse("a = b\nb = a\nd = x\nc = d\ne = c\nx = y").expect("parsing");
+        let renamed = map.apply(&mut swapped);
+        let keys = swapped
+            .startup
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["b", "a", "d", "x", "c"]);
+        assert_eq!(swapped.startup["b"].value, ModSettingsValue::Integer(1));
+        assert_eq!(renamed.count, 5);
+        assert!(renamed.collisions.is_empty(), "{:?}", renamed.collisions);
+
+        // two keys renamed to one: the first gets it, and the second keeps its key
+        let mut merged = settings(&["a", "b"]);
+        let renamed = RenameMap::parse("a = z\nb = z")
+            .expect("parsing")
+            .apply(&mut merged);
+        assert_eq!(merged.startup.keys().collect::<Vec<_>>(), ["z", "b"]);
+        assert_eq!(renamed.collisions.len(), 1);
+        assert_eq!(renamed.collisions[0].0.to_string(), "startup/b");
+
+        // a rename that collides keeps its key, so a rename onto that key collides in turn
+        let mut chained = settings(&["a", "b", "c"]);
+        let renamed = RenameMap::parse("a = b\nb = c")
+            .expect("parsing")
+            .apply(&mut chained);
+        assert_eq!(chained.startup.keys().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(renamed.count, 0);
+        assert_eq!(renamed.collisions.len(), 2);
+    }
+
+    #[test]
+    fn rename_map_errors() {
+        for (text, message) in [
+            ("old-key", "Line 1: expected old = new"),
+            ("\nold-* = new", "Line 2: a prefix rename needs"),
+            ("o*d = new", "Line 1: only a trailing *"),
+            ("old =", "Line 1: keys can't be empty"),
+        ] {
+            let err = RenameMap::parse(text).expect_err(text);
+            assert!(err.to_string().starts_with(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn order_follows_template() {
+        let mut input = settings(&["d", "b", "a", "c"]);
+        let template = settings(&["a", "x", "b", "c"]);
+        order_like(&mut input, &template);
+        let keys = input.startup.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn unknown_keys_keep_relative_order() {
+        let mut input = settings(&["z", "a", "y"]);
+        let template = settings(&["a"]);
+        order_like(&mut input, &template);
+        let keys = input.startup.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "z", "y"]);
+    }
+
+    #[test]
+    fn sorts_keys_recursively() {
+        let value = serde_json::json!({"b": [{"z": 1, "a": 2}], "a": {"d": null, "c": true}});
+        assert_eq!(
+            sort_keys(value).to_string(),
+            r#"{"a":{"c":true,"d":null},"b":[{"a":2,"z":1}]}"#
+        );
+    }
+
+    #[test]
+    fn formats_colors() {
+        let settings = serde_json::json!({
+            "startup": {
+                "red": {"type": "Color", "value": {"r": 1.0, "g": 0.0, "b": 0.2, "a": 1.0}},
+                "half": {"type": "Color", "value": {"r": 0.5, "g": 0.0, "b": 0.0, "a": 1.0}},
+                "name": {"type": "String", "value": {"r": 1.0}},
+            }
+        });
+        let mut array = settings.clone();
+        assert!(format_colors(&mut array, ColorFormat::Array).is_empty());
+        assert_eq!(
+            array["startup"]["red"]["value"],
+            serde_json::json!([1.0, 0.0, 0.2, 1.0])
+        );
+        assert_eq!(array["startup"]["name"], settings["startup"]["name"]);
+
+        let mut hex = settings.clone();
+        assert_eq!(format_colors(&mut hex, ColorFormat::Hex), ["startup/half"]);
+        assert_eq!(hex["startup"]["red"]["value"], "#ff0033ff");
+        assert_eq!(hex["startup"]["half"], settings["startup"]["half"]);
+    }
+
+    #[test]
+    fn formats_toml_colors_keeping_non_finite_doubles() {
+        let mut settings = toml::from_str::<toml::Table>(
+            r#"
+            [startup.red]
+            type = "Color"
+            value = { r = 1.0, g = 0.0, b = 0.2, a = 1.0 }
+            [startup.dim]
+            type = "Color"
+            value = { r = nan, g = 0.0, b = 0.0, a = inf }
+            [startup.zz]
+            type = "Double"
+            value = nan
+            "#,
+        )
+        .expect("parsing");
+        let mut hex = toml::Value::Table(settings.clone());
+        assert_eq!(
+            format_toml_colors(&mut hex, ColorFormat::Hex),
+            ["startup/dim"]
+        );
+        assert_eq!(hex["startup"]["red"]["value"].as_str(), Some("#ff0033ff"));
+        assert!(hex["startup"]["zz"]["value"].as_float().unwrap().is_nan());
+
+        let mut array = toml::Value::Table(settings.clone());
+        assert!(format_toml_colors(&mut array, ColorFormat::Array).is_empty());
+        let channels = array["startup"]["dim"]["value"].as_array().unwrap();
+        assert!(channels[0].as_float().unwrap().is_nan());
+        assert_eq!(channels[3].as_float(), Some(f64::INFINITY));
+
+        settings.insert("a".to_owned(), toml::Value::Integer(1));
+        let sorted = sort_toml_keys(toml::Value::Table(settings));
+        let keys = sorted["startup"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["dim", "red", "zz"]);
+        assert_eq!(sorted.as_table().unwrap().keys().next().unwrap(), "a");
+    }
+}
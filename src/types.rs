@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub struct FactorioVersion {
@@ -8,6 +10,31 @@ pub struct FactorioVersion {
     pub patch: u16,
     pub build: u16,
 }
+
+impl FactorioVersion {
+    /// The first version to write a version header at the start of `mod-settings.dat`
+    pub const FIRST_WITH_HEADER: FactorioVersion = FactorioVersion::new(0, 17, 0, 0);
+
+    /// Stands in for the version of files from before 0.17, which do not record it
+    pub const LEGACY: FactorioVersion = FactorioVersion::new(0, 16, 0, 0);
+
+    /// The version `major.minor.patch.build`, in the order it's written. Prefer this to a struct
+    /// literal, where patch and build are easily swapped.
+    pub const fn new(major: u16, minor: u16, patch: u16, build: u16) -> FactorioVersion {
+        FactorioVersion {
+            major,
+            minor,
+            patch,
+            build,
+        }
+    }
+
+    /// Whether files of this version begin with a version header
+    pub fn has_header(&self) -> bool {
+        *self >= Self::FIRST_WITH_HEADER
+    }
+}
+
 impl Ord for FactorioVersion {
     fn cmp(&self, other: &Self) -> Ordering {
         match self.major.cmp(&other.major) {
@@ -27,3 +54,133 @@ impl PartialOrd for FactorioVersion {
         Some(self.cmp(other))
     }
 }
+
+/// The dotted form with all four components, e.g. `1.1.82.0`
+impl Display for FactorioVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.patch, self.build
+        )
+    }
+}
+
+/// Parses the dotted form, `major.minor.patch` or `major.minor.patch.build`. The build defaults to 0.
+impl FromStr for FactorioVersion {
+    type Err = ParseVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.trim().split('.').collect::<Vec<_>>();
+        if !(3..=4).contains(&parts.len()) {
+            return Err(ParseVersionError::ComponentCount(parts.len()));
+        }
+        let component = |index: usize, name: &'static str| {
+            parts
+                .get(index)
+                .map(|part| {
+                    part.parse::<u16>()
+                        .map_err(|_| ParseVersionError::Component {
+                            name,
+                            value: (*part).to_owned(),
+                        })
+                })
+                .unwrap_or(Ok(0))
+        };
+        Ok(FactorioVersion::new(
+            component(0, "major")?,
+            component(1, "minor")?,
+            component(2, "patch")?,
+            component(3, "build")?,
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseVersionError {
+    /// The version did not have three or four dot-separated components
+    ComponentCount(usize),
+    /// A component was not a number in the range of a u16
+    Component { name: &'static str, value: String },
+}
+
+impl Display for ParseVersionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseVersionError::ComponentCount(count) => write!(
+                f,
+                "expected major.minor.patch or major.minor.patch.build, found {} components",
+                count
+            ),
+            ParseVersionError::Component { name, value } => {
+                write!(f, "invalid {} version component: {:?}", name, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseVersionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{FactorioVersion, ParseVersionError};
+
+    #[test]
+    fn components_in_written_order() {
+        let version = FactorioVersion::new(1, 2, 3, 4);
+        assert_eq!(
+            (version.major, version.minor, version.patch, version.build),
+            (1, 2, 3, 4)
+        );
+        assert_eq!(version.to_string(), "1.2.3.4");
+        // patch outranks build
+        assert!(FactorioVersion::new(1, 1, 2, 0) > FactorioVersion::new(1, 1, 1, 9));
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(
+            "1.1.82".parse::<FactorioVersion>(),
+            Ok(FactorioVersion::new(1, 1, 82, 0))
+        );
+        assert_eq!(
+            "2.0.26.2".parse::<FactorioVersion>(),
+            Ok(FactorioVersion::new(2, 0, 26, 2))
+        );
+    }
+
+    #[test]
+    fn parse_version_errors() {
+        assert_eq!(
+            "1.1".parse::<FactorioVersion>(),
+            Err(ParseVersionError::ComponentCount(2))
+        );
+        assert_eq!(
+            "1.x.82".parse::<FactorioVersion>(),
+            Err(ParseVersionError::Component {
+                name: "minor",
+                value: "x".to_owned()
+            })
+        );
+        assert_eq!(
+            "1.1.82.70000".parse::<FactorioVersion>(),
+            Err(ParseVersionError::Component {
+                name: "build",
+                value: "70000".to_owned()
+            })
+        );
+        let err = "1.1.x".parse::<FactorioVersion>().unwrap_err();
+        assert_eq!(err.to_string(), "invalid patch version component: \"x\"");
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for version in [
+            FactorioVersion::new(0, 16, 0, 0),
+            FactorioVersion::new(1, 1, 82, 4),
+            FactorioVersion::new(u16::MAX, 0, 1, u16::MAX),
+        ] {
+            assert_eq!(version.to_string().parse(), Ok(version));
+        }
+    }
+}
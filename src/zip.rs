@@ -0,0 +1,326 @@
+use crate::input::invalid_compressed;
+use anyhow::{anyhow, Context};
+use flate2::read::DeflateDecoder;
+use flate2::CrcReader;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Take};
+
+/// The name of the settings file inside an archive
+pub const SETTINGS_NAME: &str = "mod-settings.dat";
+
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ENTRY_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+const END_LEN: usize = 22;
+/// The end record is followed by a comment of at most this many bytes
+const MAX_COMMENT: usize = 0xffff;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// An entry of a zip archive as its central directory describes it
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    local_offset: u32,
+}
+
+/// The decompressed contents of one zip entry, read from the archive as they're needed. They're
+/// checked against the CRC and size the central directory records once read to the end.
+pub struct ZipEntry<R> {
+    contents: CrcReader<Contents<R>>,
+    crc: u32,
+    size: u32,
+}
+
+enum Contents<R> {
+    Stored(Take<R>),
+    Deflated(Box<DeflateDecoder<Take<R>>>),
+}
+
+impl<R: Read> Read for Contents<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Contents::Stored(stored) => stored.read(buf),
+            Contents::Deflated(deflated) => deflated.read(buf).map_err(invalid_compressed),
+        }
+    }
+}
+
+impl<R: Read> Read for ZipEntry<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.contents.read(buf)?;
+        if count == 0 && !buf.is_empty() {
+            let read = self.contents.crc();
+            if read.amount() != self.size || read.sum() != self.crc {
+                return Err(std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    "Damaged zip entry: its CRC or size doesn't match its data",
+                ));
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Reads the central directory, found through the end record at the end of the archive
+fn entries(archive: &mut (impl Read + Seek)) -> anyhow::Result<Vec<Entry>> {
+    let len = archive.seek(SeekFrom::End(0))?;
+    let tail_len = len.min((END_LEN + MAX_COMMENT) as u64);
+    archive.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = Vec::with_capacity(tail_len as usize);
+    archive.read_to_end(&mut tail)?;
+    let end = (0..=tail.len().saturating_sub(END_LEN))
+        .rev()
+        .find(|&at| tail.len() >= END_LEN && u32_at(&tail, at) == END_SIGNATURE)
+        .ok_or_else(|| anyhow!("Not a zip archive: no end of central directory record"))?;
+    let count = u16_at(&tail, end + 10);
+    let directory_len = u32_at(&tail, end + 12);
+    let directory_offset = u32_at(&tail, end + 16);
+    if count == u16::MAX || directory_offset == u32::MAX {
+        return Err(anyhow!("Zip64 archives aren't supported"));
+    }
+
+    // the sizes are checked before allocating, since they're only as trustworthy as the archive
+    let directory_end = u64::from(directory_offset).checked_add(u64::from(directory_len));
+    if directory_end.is_none_or(|end| end > len) {
+        return Err(anyhow!(
+            "Damaged central directory: it extends past the end of the archive"
+        ));
+    }
+    archive.seek(SeekFrom::Start(u64::from(directory_offset)))?;
+    let mut directory = vec![0; directory_len as usize];
+    archive
+        .read_exact(&mut directory)
+        .context("Reading the central directory")?;
+    let mut entries = Vec::with_capacity(usize::from(count));
+    let mut at = 0;
+    for _ in 0..count {
+        if directory.len() < at + 46 || u32_at(&directory, at) != ENTRY_SIGNATURE {
+            return Err(anyhow!("Damaged central directory"));
+        }
+        let name_len = usize::from(u16_at(&directory, at + 28));
+        let extra_len = usize::from(u16_at(&directory, at + 30));
+        let comment_len = usize::from(u16_at(&directory, at + 32));
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| anyhow!("Damaged central directory"))?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(&directory, at + 10),
+            crc: u32_at(&directory, at + 16),
+            compressed_size: u32_at(&directory, at + 20),
+            size: u32_at(&directory, at + 24),
+            local_offset: u32_at(&directory, at + 42),
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Opens the archive's single entry named `mod-settings.dat`, at its root or in any directory.
+/// Fails if there's no such entry, or more than one, since there's no telling which is meant.
+pub fn open_settings<R: Read + Seek>(mut archive: R) -> anyhow::Result<ZipEntry<R>> {
+    let entries = entries(&mut archive)?;
+    let mut candidates = entries
+        .iter()
+        .filter(|entry| entry.name.rsplit(['/', '\\']).next() == Some(SETTINGS_NAME));
+    let entry = candidates
+        .next()
+        .ok_or_else(|| anyhow!("The archive has no {}", SETTINGS_NAME))?;
+    let others = candidates
+        .map(|entry| entry.name.as_str())
+        .collect::<Vec<_>>();
+    if !others.is_empty() {
+        return Err(anyhow!(
+            "The archive has more than one {}: {}, {}",
+            SETTINGS_NAME,
+            entry.name,
+            others.join(", ")
+        ));
+    }
+    open_entry(archive, entry)
+}
+
+/// Opens the `settings.lua` of a zipped mod, which sits in the mod's folder at the root of the
+/// archive. A mod that declares no settings has none, so that isn't an error.
+pub fn open_mod_settings<R: Read + Seek>(mut archive: R) -> anyhow::Result<Option<ZipEntry<R>>> {
+    let entries = entries(&mut archive)?;
+    let entry = entries.into_iter().find(|entry| {
+        let mut parts = entry.name.split(['/', '\\']);
+        parts.next().is_some() && parts.next() == Some("settings.lua") && parts.next().is_none()
+    });
+    entry.map(|entry| open_entry(archive, &entry)).transpose()
+}
+
+/// Starts reading an entry's contents, from just after its local header
+fn open_entry<R: Read + Seek>(mut archive: R, entry: &Entry) -> anyhow::Result<ZipEntry<R>> {
+    archive.seek(SeekFrom::Start(u64::from(entry.local_offset)))?;
+    let mut local = [0; 30];
+    archive.read_exact(&mut local)?;
+    if u32_at(&local, 0) != LOCAL_SIGNATURE {
+        return Err(anyhow!("Damaged local header for {}", entry.name));
+    }
+    let skip = i64::from(u16_at(&local, 26)) + i64::from(u16_at(&local, 28));
+    archive.seek(SeekFrom::Current(skip))?;
+    let data = archive.take(u64::from(entry.compressed_size));
+    let contents = match entry.method {
+        METHOD_STORED => Contents::Stored(data),
+        METHOD_DEFLATED => Contents::Deflated(Box::new(DeflateDecoder::new(data))),
+        method => {
+            return Err(anyhow!(
+            "{} is compressed with method {}, but only stored and deflated entries are supported",
+            entry.name,
+            method
+        ))
+        }
+    };
+    Ok(ZipEntry {
+        contents: CrcReader::new(contents),
+        crc: entry.crc,
+        size: entry.size,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open_mod_settings, open_settings};
+    use std::io::{Cursor, Read};
+
+    /// An archive of stored entries
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for (name, contents) in files {
+            let offset = data.len() as u32;
+            let mut crc = flate2::Crc::new();
+            crc.update(contents);
+            let crc = crc.sum();
+            let mut fields = Vec::new();
+            fields.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            fields.extend_from_slice(&crc.to_le_bytes());
+            fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&[0, 0]);
+            data.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+            data.extend_from_slice(&fields);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(contents);
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&[20, 0]);
+            directory.extend_from_slice(&fields);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        let offset = data.len() as u32;
+        data.extend_from_slice(&directory);
+        data.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        data.extend_from_slice(&offset.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data
+    }
+
+    fn read_settings(files: &[(&str, &[u8])]) -> anyhow::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        open_settings(Cursor::new(archive(files)))?.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn finds_the_settings_entry() {
+        let files: &[(&str, &[u8])] = &[
+            ("mods/mod-list.json", b"{}"),
+            ("mods/mod-settings.dat", b"settings"),
+        ];
+        assert_eq!(read_settings(files).expect("reading"), b"settings");
+
+        let err = read_settings(&[("mod-list.json", b"{}")]).expect_err("missing");
+        assert_eq!(err.to_string(), "The archive has no mod-settings.dat");
+        let err = read_settings(&[("a/mod-settings.dat", b"a"), ("mod-settings.dat", b"b")])
+            .expect_err("ambiguous");
+        assert_eq!(
+            err.to_string(),
+            "The archive has more than one mod-settings.dat: a/mod-settings.dat, mod-settings.dat"
+        );
+        assert!(open_settings(Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+
+    #[test]
+    fn finds_mod_settings() {
+        let data = archive(&[
+            ("my-mod_1.0.0/info.json", b"{}"),
+            ("my-mod_1.0.0/prototypes/settings.lua", b"nested"),
+            ("my-mod_1.0.0/settings.lua", b"data:extend({})"),
+        ]);
+        let mut contents = Vec::new();
+        open_mod_settings(Cursor::new(data))
+            .expect("opening")
+            .expect("found")
+            .read_to_end(&mut contents)
+            .expect("reading");
+        assert_eq!(contents, b"data:extend({})");
+
+        let data = archive(&[("my-mod_1.0.0/prototypes/settings.lua", b"nested")]);
+        assert!(open_mod_settings(Cursor::new(data))
+            .expect("opening")
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_damaged_archives() {
+        let mut data = archive(&[("mod-settings.dat", b"settings")]);
+        let contents = data.windows(12).position(|w| w == b".datsettings").unwrap();
+        data[contents + 4] = b'S';
+        let mut read = Vec::new();
+        let err = open_settings(Cursor::new(data))
+            .expect("opening")
+            .read_to_end(&mut read)
+            .expect_err("bad crc");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // only an end record, claiming a directory of nearly 4 GiB
+        let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+        end.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        end.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+        end.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        let err = open_settings(Cursor::new(end.clone()))
+            .err()
+            .expect("oversized");
+        assert_eq!(
+            err.to_string(),
+            "Damaged central directory: it extends past the end of the archive"
+        );
+        // a directory that starts within the archive but runs past its end
+        end[12..16].copy_from_slice(&10u32.to_le_bytes());
+        end[16..20].copy_from_slice(&20u32.to_le_bytes());
+        assert!(open_settings(Cursor::new(end)).is_err());
+    }
+
+    #[test]
+    fn reads_deflated_entries() {
+        // written by Python's zipfile, with the settings deflated
+        let data = std::fs::read("test_data/mods-backup.zip").expect("reading file");
+        let mut contents = Vec::new();
+        open_settings(Cursor::new(data))
+            .expect("opening")
+            .read_to_end(&mut contents)
+            .expect("reading");
+        assert_eq!(contents, std::fs::read("test_data/golden.dat").unwrap());
+    }
+}
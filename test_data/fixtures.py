@@ -0,0 +1,122 @@
+#!/usr/bin/env python3
+"""Writes the hand-built .dat fixtures from the file format, without using this crate, so tests
+compare the codec against bytes it didn't produce. Run from the repository root."""
+
+import struct
+
+
+def string(text):
+    data = text.encode("utf-8")
+    if len(data) < 255:
+        length = struct.pack("<B", len(data))
+    else:
+        length = b"\xff" + struct.pack("<I", len(data))
+    # an empty flag byte, left clear, comes before the length
+    return b"\x00" + length + data
+
+
+def header(major, minor, patch, build):
+    return struct.pack("<HHHHB", major, minor, patch, build, 0)
+
+
+def none():
+    return b"\x00\x00"
+
+
+def boolean(value):
+    return b"\x01\x00" + struct.pack("<B", value)
+
+
+def double(value):
+    return b"\x02\x00" + struct.pack("<d", value)
+
+
+def text(value):
+    return b"\x03\x00" + string(value)
+
+
+def integer(value):
+    return b"\x06\x00" + struct.pack("<q", value)
+
+
+def listing(*items):
+    """A list of properties, each a property or a (key, property) pair for a keyed item"""
+    body = struct.pack("<I", len(items))
+    for item in items:
+        key, value = item if isinstance(item, tuple) else ("", item)
+        body += string(key) + value
+    return b"\x04\x00" + body
+
+
+def dictionary(**entries):
+    body = struct.pack("<I", len(entries))
+    for key, value in entries.items():
+        body += string(key.replace("_", "-")) + value
+    return b"\x05\x00" + body
+
+
+def setting(value):
+    return dictionary(value=value)
+
+
+def golden():
+    """The settings in golden.toml"""
+    long_string = (
+        "This string is longer than two hundred and fifty-four bytes, so its length is written"
+        " in the five-byte form rather than the one-byte form that shorter strings use. It keeps"
+        " going a little longer just to be sure the boundary is passed with room to spare, and"
+        " then some."
+    )
+    color = dictionary(r=double(0.25), g=double(0.5), b=double(1.0), a=double(1 / 3))
+    return header(1, 1, 82, 4) + dictionary(
+        startup=dictionary(
+            golden_bool=setting(boolean(True)),
+            golden_double=setting(double(0.1)),
+            golden_tiny_double=setting(double(5e-324)),
+            golden_negative_zero=setting(double(-0.0)),
+            golden_integer=setting(integer(-9007199254740993)),
+        ),
+        runtime_global=dictionary(
+            golden_line_endings=setting(text("unix\nwindows\r\nmac\r")),
+            golden_paths=setting(text("C:\\Games\\Factorio/mods/settings.dat")),
+            golden_unicode=setting(text("Z\u00fcrich \u2713")),
+            golden_long_string=setting(text(long_string)),
+        ),
+        runtime_per_user=dictionary(
+            golden_color=setting(color),
+            golden_empty_string=setting(text("")),
+        ),
+    )
+
+
+def list_settings():
+    return header(2, 0, 26, 2) + dictionary(
+        startup=dictionary(
+            list_mod_recent_items=dictionary(
+                value=text("iron-plate"),
+                history=listing(text("iron-plate"), text("copper-plate"), integer(3)),
+            ),
+            list_mod_enabled=dictionary(value=boolean(True)),
+        ),
+        runtime_global=dictionary(
+            list_mod_filters=dictionary(value=text(""), entries=listing()),
+        ),
+        runtime_per_user=dictionary(
+            list_mod_nested=dictionary(
+                value=integer(7),
+                layers=listing(
+                    listing(boolean(False)),
+                    ("top", dictionary(x=integer(1))),
+                ),
+            ),
+        ),
+    )
+
+
+if __name__ == "__main__":
+    for path, contents in [
+        ("test_data/golden.dat", golden()),
+        ("test_data/list-settings.dat", list_settings()),
+    ]:
+        with open(path, "wb") as file:
+            file.write(contents)
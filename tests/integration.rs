@@ -1 +1,2778 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
 
+fn command() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_factorio-settings"))
+}
+
+fn run(args: &[&str]) -> Output {
+    command().args(args).output().expect("running binary")
+}
+
+fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = command()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawning binary");
+    // the binary may exit without reading its input, e.g. on a usage error
+    let _ = child.stdin.take().expect("stdin").write_all(stdin);
+    child.wait_with_output().expect("waiting for binary")
+}
+
+/// A path in the system temporary directory unique to the named test
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("factorio-settings-tests");
+    std::fs::create_dir_all(&dir).expect("creating temp dir");
+    dir.join(format!("{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn exit_code_success() {
+    let output = run(&["test_data/complex-settings.dat", "-f", "json"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!output.stdout.is_empty());
+}
+
+#[test]
+fn exit_code_conversion_error() {
+    let output = run_with_stdin(&["-m", "decode", "-f", "json", "-"], b"not a settings file");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], b"{ broken json");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn exit_code_usage_error() {
+    // mode cannot be inferred without extensions or content
+    let output = run_with_stdin(&["-"], b"");
+    assert_eq!(output.status.code(), Some(2));
+
+    // format cannot be inferred for decoding to stdout
+    let output = run(&["test_data/complex-settings.dat"]);
+    assert_eq!(output.status.code(), Some(2));
+
+    // rejected by the argument parser
+    let output = run(&["--no-such-flag", "test_data/complex-settings.dat"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn modes_are_exclusive() {
+    const INPUT: &str = "test_data/complex-settings.dat";
+    let output = run(&["--lint", "--find-duplicates", INPUT]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+
+    let output = run(&[
+        "--has",
+        "startup/non-combat-mode",
+        "--get",
+        "startup/non-combat-mode",
+        INPUT,
+    ]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+
+    // --explain only annotates --extract-bytes and --encode-single
+    let output = run(&["--find-value", "true", "--explain", INPUT]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    let output = run(&[
+        "--extract-bytes",
+        "startup/non-combat-mode",
+        "--explain",
+        INPUT,
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+}
+
+#[test]
+fn exit_code_io_error() {
+    let output = run(&["does/not/exist.dat", "-f", "json"]);
+    assert_eq!(output.status.code(), Some(4));
+
+    let output = run(&[
+        "test_data/complex-settings.dat",
+        "does/not/exist/output.json",
+    ]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn exit_code_round_trip_through_files() {
+    let json = temp_path("exit-code-round-trip.json");
+    let dat = temp_path("exit-code-round-trip.dat");
+    let output = run(&[
+        "test_data/settings-2.0.dat",
+        json.to_str().expect("utf8 path"),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let output = run(&[json.to_str().unwrap(), dat.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        std::fs::read("test_data/settings-2.0.dat").unwrap(),
+        std::fs::read(&dat).unwrap()
+    );
+    let _ = std::fs::remove_file(json);
+    let _ = std::fs::remove_file(dat);
+}
+
+#[test]
+fn count_bytes_report() {
+    let output = run(&["--count-bytes", "test_data/settings-2.0.dat"]);
+    assert_eq!(output.status.code(), Some(0));
+    let report = String::from_utf8(output.stdout).expect("utf8 output");
+    let total = report
+        .lines()
+        .find(|line| line.starts_with("total"))
+        .expect("total line");
+    let size = std::fs::metadata("test_data/settings-2.0.dat")
+        .unwrap()
+        .len();
+    assert!(total.contains(&size.to_string()), "{}", total);
+}
+
+#[test]
+fn failed_conversion_keeps_existing_output() {
+    let dat = temp_path("failed-conversion-keeps-output.dat");
+    std::fs::write(&dat, b"original").unwrap();
+    let output = run_with_stdin(
+        &["-m", "encode", "-f", "json", "-", dat.to_str().unwrap()],
+        b"{ broken json",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(std::fs::read(&dat).unwrap(), b"original");
+    let _ = std::fs::remove_file(dat);
+}
+
+#[cfg(unix)]
+#[test]
+fn output_to_fifo() {
+    let fifo = temp_path("output-to-fifo.json");
+    let _ = std::fs::remove_file(&fifo);
+    let status = Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .expect("running mkfifo");
+    assert!(status.success());
+    let reader = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || std::fs::read(fifo).expect("reading fifo"))
+    };
+    let output = run(&["test_data/settings-2.0.dat", fifo.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let data = reader.join().expect("reader thread");
+    serde_json::from_slice::<serde_json::Value>(&data).expect("output should be JSON");
+    let _ = std::fs::remove_file(fifo);
+}
+
+#[test]
+fn assume_version_recovers_body() {
+    let output = run(&[
+        "--assume-version",
+        "2.0.26",
+        "--body-offset",
+        "9",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("reconstructed"));
+    let recovered: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(recovered["factorio_version"]["patch"], 26);
+    assert_eq!(recovered["factorio_version"]["build"], 0);
+
+    let output = run(&["-f", "json", "test_data/settings-2.0.dat"]);
+    let original: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(recovered["startup"], original["startup"]);
+
+    // the warning is reported like any other, and batches name files by the assumed version
+    let dir = temp_path("assumed-batch");
+    let output = run(&[
+        "--assume-version",
+        "2.0.99",
+        "--body-offset",
+        "9",
+        "--warnings-format",
+        "json",
+        "--output-template",
+        "{version}/{stem}.json",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(r#"{"level":"warn","message":"output is reconstructed"#),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(r#""warnings":1"#), "{}", stderr);
+    assert!(dir.join("2.0.99.0/settings-2.0.json").is_file());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn order_template_restores_byte_parity() {
+    let output = run(&["-f", "json", "test_data/settings-2.0.dat"]);
+    let mut json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let startup = json["startup"].as_object().unwrap();
+    let reversed = startup
+        .iter()
+        .rev()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect::<serde_json::Map<_, _>>();
+    json["startup"] = serde_json::Value::Object(reversed);
+
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--order-template",
+            "test_data/settings-2.0.dat",
+            "-",
+        ],
+        &serde_json::to_vec(&json).unwrap(),
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        output.stdout,
+        std::fs::read("test_data/settings-2.0.dat").unwrap()
+    );
+}
+
+#[test]
+fn lint_reports_invalid_values() {
+    let output = run(&["--lint", "test_data/settings-2.0.dat"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let json = br#"{
+        "factorio_version": { "major": 1, "minor": 1, "patch": 82, "build": 0 },
+        "startup": {
+            "bad-color": { "type": "Color", "value": { "r": 2.0, "g": 0.0, "b": 0.0, "a": -1.0 } }
+        },
+        "runtime-global": {},
+        "runtime-per-user": {}
+    }"#;
+    let output = run_with_stdin(&["--lint", "-f", "json", "-"], json);
+    assert_eq!(output.status.code(), Some(1));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(report.lines().count(), 2, "{}", report);
+    assert!(report.contains("startup/bad-color: color channel r"));
+}
+
+const SIMPLE_HEX: &str = "01 00 01 00 52 00 04 00 00 05 00 03 00 00 00 00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 00 11 6D 79 2D 73 74 72 69 6E 67 2D 73 65 74 74 69 6E 67 05 00 01 00 00 00 00 05 76 61 6C 75 65 03 00 00 08 64 65 61 64 62 65 65 66 00 0E 72 75 6E 74 69 6D 65 2D 67 6C 6F 62 61 6C 05 00 00 00 00 00 00 10 72 75 6E 74 69 6D 65 2D 70 65 72 2D 75 73 65 72 05 00 00 00 00 00";
+
+#[test]
+fn hex_input_from_argument_and_stdin() {
+    let output = run(&["--hex", "-f", "json", SIMPLE_HEX]);
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["startup"]["my-string-setting"]["value"], "deadbeef");
+
+    let prefixed = SIMPLE_HEX
+        .split(' ')
+        .map(|byte| format!("0x{}", byte))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = run_with_stdin(&["--hex", "-f", "json", "-"], prefixed.as_bytes());
+    assert_eq!(output.status.code(), Some(0));
+    let from_stdin: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json, from_stdin);
+}
+
+/// SIMPLE_HEX in standard base64
+const SIMPLE_BASE64: &str = "AQABAFIABAAABQADAAAAAAdzdGFydHVwBQABAAAAABFteS1zdHJpbmctc2V0dGluZwUAAQAAAAAFdmFsdWUDAAAIZGVhZGJlZWYADnJ1bnRpbWUtZ2xvYmFsBQAAAAAAABBydW50aW1lLXBlci11c2VyBQAAAAAA";
+
+#[test]
+fn base64_input_from_argument_and_stdin() {
+    let expected = run(&["--hex", "-f", "json", SIMPLE_HEX]).stdout;
+    let output = run(&["--base64", "-f", "json", SIMPLE_BASE64]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, expected);
+
+    let url_safe = SIMPLE_BASE64
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_");
+    let output = run_with_stdin(&["--base64", "-f", "json", "-"], url_safe.as_bytes());
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, expected);
+}
+
+#[test]
+fn encode_single_setting() {
+    let output = run(&[
+        "--encode-single",
+        "startup/my-string-setting=deadbeef",
+        "--factorio-version",
+        "1.1.82.4",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let hex = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(hex.trim(), SIMPLE_HEX.to_ascii_lowercase());
+
+    let output = run(&[
+        "--encode-single",
+        "startup/flag:bool=true",
+        "--factorio-version",
+        "2.0.26",
+        "--explain",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("version 2.0.26.0"), "{}", text);
+    assert!(text.contains("bool true"), "{}", text);
+
+    let output = run(&["--encode-single", "startup/flag=true"]);
+    assert_eq!(output.status.code(), Some(2), "requires a version");
+}
+
+#[test]
+fn compressed_text_input_is_decompressed() {
+    let golden = std::fs::read("test_data/golden.dat").unwrap();
+    let json = run(&["test_data/golden.dat", "-f", "json", "--gzip"]);
+    assert_eq!(json.status.code(), Some(0), "{:?}", json);
+    assert!(json.stdout.starts_with(&[0x1f, 0x8b]));
+    let output = run_with_stdin(&["-m", "encode", "-"], &json.stdout);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout == golden);
+
+    let output = run_with_stdin(
+        &["-m", "encode", "-f", "json", "-"],
+        &[0x1f, 0x8b, 0x08, 0x00, 0x00],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid compressed data"));
+}
+
+#[test]
+fn damaged_gzip_is_a_conversion_error() {
+    let dat = std::fs::read("test_data/golden.dat.gz").unwrap();
+    let json = run(&["test_data/golden.dat", "-f", "json", "--gzip"]).stdout;
+    for (compressed, args) in [
+        (dat, &["-m", "decode", "--passthrough", "-"][..]),
+        (json, &["-m", "encode", "-f", "json", "-"]),
+    ] {
+        let mut bad_crc = compressed.clone();
+        let crc = bad_crc.len() - 8;
+        bad_crc[crc] ^= 1;
+        let truncated = &compressed[..compressed.len() - 3];
+        for data in [&bad_crc[..], truncated] {
+            let output = run_with_stdin(args, data);
+            assert_eq!(output.status.code(), Some(1), "{:?} {:?}", args, output);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("Invalid compressed data"), "{}", stderr);
+        }
+    }
+}
+
+#[test]
+fn decode_from_zip() {
+    let output = temp_path("from-zip.json");
+    let decoded = run(&["test_data/mods-backup.zip", output.to_str().unwrap()]);
+    assert_eq!(decoded.status.code(), Some(0), "{:?}", decoded);
+    let expected = run(&["test_data/golden.dat", "-f", "json"]).stdout;
+    assert_eq!(std::fs::read(&output).unwrap(), expected);
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn damaged_zip_is_a_conversion_error() {
+    // an end record alone, claiming a central directory of nearly 4 GiB
+    let zip = temp_path("oversized-directory.zip");
+    let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+    end.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+    end.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+    end.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    std::fs::write(&zip, end).unwrap();
+    let output = run(&[zip.to_str().unwrap(), "-f", "json"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Damaged central directory"));
+    std::fs::remove_file(zip).unwrap();
+}
+
+#[test]
+fn gzip_settings_files() {
+    let golden = std::fs::read("test_data/golden.dat").unwrap();
+    let expected = run(&["test_data/golden.dat", "-f", "json"]).stdout;
+    let output = run(&["test_data/golden.dat.gz", "-f", "json"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(output.stdout, expected);
+
+    // a .dat.gz output is encoded, then compressed
+    let json = temp_path("gzip.json");
+    let compressed = temp_path("gzip.dat.gz");
+    std::fs::write(&json, &expected).unwrap();
+    let output = run(&[json.to_str().unwrap(), compressed.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let written = std::fs::read(&compressed).unwrap();
+    assert!(written.starts_with(&[0x1f, 0x8b]));
+    assert!(written.len() < golden.len());
+    let output = run(&[
+        "-m",
+        "decode",
+        "--passthrough",
+        compressed.to_str().unwrap(),
+    ]);
+    assert!(output.stdout == golden);
+
+    // --set keeps a compressed file compressed
+    let output = run(&[
+        "--set",
+        "startup/golden-bool=false",
+        compressed.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(std::fs::read(&compressed)
+        .unwrap()
+        .starts_with(&[0x1f, 0x8b]));
+    for path in [json, compressed] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn self_test_passes() {
+    let output = run(&["--self-test"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("pass  settings-2.0.dat"));
+}
+
+#[test]
+fn group_by_mod_output() {
+    let output = run(&[
+        "--group-by-mod",
+        "-f",
+        "json",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json["bnl"]["startup"]["bnl-enable"]["type"], "Bool",
+        "grouped under the prefix"
+    );
+}
+
+#[test]
+fn convert_requires_directional_formats() {
+    let json = run(&["-f", "json", "test_data/settings-2.0.dat"]).stdout;
+
+    let output = run_with_stdin(&["-m", "convert", "-f", "toml", "-"], &json);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--input-format"));
+
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "convert",
+            "--input-format",
+            "json",
+            "--output-format",
+            "toml",
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let toml = String::from_utf8(output.stdout).unwrap();
+    assert!(toml.contains("[startup.bnl-enable]"), "{}", toml);
+}
+
+#[test]
+fn convert_inferred_from_text_on_both_sides() {
+    let json = temp_path("transcode.json");
+    let toml = temp_path("transcode.toml");
+    let back = temp_path("transcode-back.json");
+    let decoded = run(&["test_data/golden.dat", json.to_str().unwrap()]);
+    assert_eq!(decoded.status.code(), Some(0), "{:?}", decoded);
+
+    let output = run(&[json.to_str().unwrap(), toml.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let output = run(&[toml.to_str().unwrap(), back.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(
+        std::fs::read_to_string(&back).unwrap(),
+        std::fs::read_to_string(&json).unwrap()
+    );
+    let encoded = run(&["-m", "encode", toml.to_str().unwrap()]);
+    assert!(encoded.stdout == std::fs::read("test_data/golden.dat").unwrap());
+
+    // with only the output format given, stdin to stdout
+    let output = run_with_stdin(
+        &["--input-format", "json", "--output-format", "toml", "-"],
+        &std::fs::read(&json).unwrap(),
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(output.stdout, std::fs::read(&toml).unwrap());
+    for path in [json, toml, back] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn side_formats_name_the_binary_file() {
+    let golden = std::fs::read("test_data/golden.dat").unwrap();
+    let decoded = run_with_stdin(
+        &["--input-format", "dat", "--output-format", "toml", "-"],
+        &golden,
+    );
+    assert_eq!(decoded.status.code(), Some(0), "{:?}", decoded);
+    assert!(String::from_utf8(decoded.stdout.clone())
+        .unwrap()
+        .contains("[startup"));
+
+    let encoded = run_with_stdin(
+        &["--input-format", "toml", "--output-format", "dat", "-"],
+        &decoded.stdout,
+    );
+    assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+    assert!(encoded.stdout == golden);
+
+    let output = run_with_stdin(&["-m", "encode", "--input-format", "dat", "-"], &golden);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("--input-format dat only applies when decoding"));
+}
+
+#[test]
+fn stdin_to_stdout_round_trip() {
+    let dat = std::fs::read("test_data/complex-settings.dat").unwrap();
+    let output = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &dat);
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON output");
+    assert_eq!(json["factorio_version"]["major"], 1);
+
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &output.stdout);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, dat);
+}
+
+#[test]
+fn max_input_size() {
+    let output = run(&[
+        "--max-input-size",
+        "1000",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("maximum of 1000 bytes"));
+
+    let dat = std::fs::read("test_data/settings-2.0.dat").unwrap();
+    let output = run_with_stdin(
+        &[
+            "--max-input-size",
+            "1000",
+            "-m",
+            "decode",
+            "-f",
+            "json",
+            "-",
+        ],
+        &dat,
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let limit = dat.len().to_string();
+    let output = run_with_stdin(
+        &[
+            "--max-input-size",
+            &limit,
+            "-m",
+            "decode",
+            "-f",
+            "json",
+            "-",
+        ],
+        &dat,
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // text forms of binary input are limited as they're read, not only once decoded
+    let hex = dat
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    let output = run_with_stdin(
+        &["--max-input-size", &limit, "--hex", "-", "-f", "json"],
+        hex.as_bytes(),
+    );
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("maximum of"));
+
+    let dir = temp_path("max-input-size-batch");
+    let output = run(&[
+        "--max-input-size",
+        "1000",
+        "--output-template",
+        "{stem}.json",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("maximum of 1000 bytes"));
+    assert!(!dir.join("settings-2.0.json").exists());
+}
+
+#[test]
+fn decode_timeout() {
+    // a deadline of now has passed by the first entry
+    let output = run(&["--timeout", "0", "-f", "json", "test_data/settings-2.0.dat"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Decoding timed out at offset"));
+
+    let output = run(&[
+        "--timeout",
+        "60000",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+}
+
+#[test]
+fn map_settings_kind() {
+    // version 1.1.0.0 and a root dictionary {"seed": integer 42}, which isn't mod settings
+    let hex = "01 00 01 00 00 00 00 00 00 05 00 01 00 00 00 00 04 73 65 65 64 06 00 2a 00 00 00 00 00 00 00";
+    let output = run(&["--hex", "-f", "json", hex]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run(&["--kind", "map-settings", "--hex", "-f", "json", hex]);
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON output");
+    assert_eq!(json["properties"]["value"]["seed"]["value"], 42);
+
+    let output = run_with_stdin(
+        &["--kind", "map-settings", "-m", "encode", "-f", "json", "-"],
+        &output.stdout,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, input_bytes(hex));
+}
+
+fn input_bytes(hex: &str) -> Vec<u8> {
+    hex.split_whitespace()
+        .map(|pair| u8::from_str_radix(pair, 16).expect("hex byte"))
+        .collect()
+}
+
+#[test]
+fn stats_on_stderr() {
+    let output = run(&[
+        "--stats",
+        "--assume-version",
+        "2.0.26",
+        "--body-offset",
+        "9",
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("settings simplified: "), "{}", stderr);
+    assert!(stderr.contains("warnings: 1"), "{}", stderr);
+    serde_json::from_slice::<serde_json::Value>(&output.stdout).expect("JSON output");
+}
+
+#[test]
+fn key_filters_drop_settings_when_encoding() {
+    let allow = temp_path("allow-keys.txt");
+    std::fs::write(&allow, "startup/*\n").unwrap();
+    let deny = temp_path("deny-keys.txt");
+    std::fs::write(&deny, "bnl-*\n").unwrap();
+    let json = run(&["-f", "json", "test_data/complex-settings.dat"]).stdout;
+
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--allow-keys",
+            allow.to_str().unwrap(),
+            "--deny-keys",
+            deny.to_str().unwrap(),
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Dropped"));
+    let output = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let filtered: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let startup = filtered["startup"].as_object().unwrap();
+    assert!(!startup.is_empty());
+    assert!(startup.keys().all(|key| !key.starts_with("bnl-")));
+    assert!(filtered["runtime-global"].as_object().unwrap().is_empty());
+
+    let _ = std::fs::remove_file(allow);
+    let _ = std::fs::remove_file(deny);
+}
+
+#[test]
+fn verify_after_encoding() {
+    let json = run(&["-f", "json", "test_data/complex-settings.dat"]).stdout;
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "--verify", "-"], &json);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        output.stdout,
+        std::fs::read("test_data/complex-settings.dat").unwrap()
+    );
+}
+
+#[test]
+fn verify_rejects_unfaithful_encoding() {
+    // an extra property named "value" replaces the setting's value when encoded
+    let json = br#"{
+        "factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0},
+        "startup": {"a": {"type": "Bool", "value": true, "extra": {"value": {"type": "bool", "value": false}}}},
+        "runtime-global": {},
+        "runtime-per-user": {}
+    }"#;
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], json);
+    assert_eq!(output.status.code(), Some(0));
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "--verify", "-"], json);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Verifying"));
+}
+
+#[test]
+fn doubles_are_locale_independent() {
+    let json = br#"{
+        "factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0},
+        "startup": {"a": {"type": "Double", "value": 1234.5}},
+        "runtime-global": {},
+        "runtime-per-user": {}
+    }"#;
+    let with_locale = |args: &[&str], stdin: &[u8]| {
+        let mut child = command()
+            .args(args)
+            .env("LC_ALL", "de_DE.UTF-8")
+            .env("LC_NUMERIC", "de_DE.UTF-8")
+            .env("LANG", "de_DE.UTF-8")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("spawning binary");
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        let output = child.wait_with_output().expect("waiting for binary");
+        assert_eq!(output.status.code(), Some(0));
+        output.stdout
+    };
+    let dat = with_locale(&["-m", "encode", "-f", "json", "-"], json);
+    assert_eq!(
+        dat,
+        run_with_stdin(&["-m", "encode", "-f", "json", "-"], json).stdout
+    );
+
+    for (format, expected) in [("json", "\"value\": 1234.5"), ("toml", "value = 1234.5")] {
+        let text =
+            String::from_utf8(with_locale(&["-m", "decode", "-f", format, "-"], &dat)).unwrap();
+        assert!(text.contains(expected), "{}", text);
+        assert!(!text.contains("1234,5"), "{}", text);
+        assert!(!text.contains("1.234"), "{}", text);
+    }
+}
+
+#[test]
+fn find_value_lists_paths() {
+    let output = run(&["--find-value", "false", "test_data/complex-settings.dat"]);
+    assert_eq!(output.status.code(), Some(0));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        text.lines().any(|line| line == "startup/non-combat-mode"),
+        "{}",
+        text
+    );
+
+    let output = run(&[
+        "--find-value",
+        "false",
+        "-f",
+        "json",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let paths: Vec<String> = serde_json::from_slice(&output.stdout).expect("JSON output");
+    assert_eq!(paths, text.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn diff_formats() {
+    let json = run(&["-f", "json", "test_data/complex-settings.dat"]).stdout;
+    let mut edited: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    edited["startup"]["non-combat-mode"]["value"] = serde_json::json!(true);
+    let other = temp_path("diff-other.json");
+    std::fs::write(&other, serde_json::to_vec(&edited).unwrap()).unwrap();
+    let other = other.to_str().unwrap();
+
+    let output = run(&["--diff", other, "test_data/complex-settings.dat"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Changed startup/non-combat-mode from false to true\n"
+    );
+
+    let output = run(&[
+        "--diff",
+        other,
+        "--diff-format",
+        "json",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    let patch: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON patch");
+    assert_eq!(patch[0]["op"], "set");
+    assert_eq!(patch[0]["path"], "startup/non-combat-mode");
+    assert_eq!(patch[0]["setting"]["value"], true);
+
+    let output = run(&[
+        "--diff",
+        other,
+        "--diff-format",
+        "unified",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    let unified = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        unified.starts_with("--- test_data/complex-settings.dat\n+++ "),
+        "{}",
+        unified
+    );
+    assert!(
+        unified.contains("\n-value = false\n+value = true\n"),
+        "{}",
+        unified
+    );
+
+    let output = run(&[
+        "--diff",
+        "test_data/complex-settings.dat",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let _ = std::fs::remove_file(other);
+}
+
+#[test]
+fn passthrough_reproduces_input() {
+    for fixture in [
+        "test_data/complex-settings.dat",
+        "test_data/settings-2.0.dat",
+    ] {
+        let output = run(&["--passthrough", fixture]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(output.stdout, std::fs::read(fixture).unwrap());
+
+        let output = run(&["--passthrough", "--check", fixture]);
+        assert_eq!(output.status.code(), Some(0));
+        assert!(output.stdout.is_empty());
+    }
+
+    // a string length written in the long form is read, but encoded in the short form
+    let long_length = "01 00 01 00 00 00 00 00 00 03 00 00 ff 01 00 00 00 61";
+    let output = run(&["--passthrough", "--check", "--hex", long_length]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn passthrough_check_leaves_the_output_alone() {
+    let fixture = std::fs::read("test_data/complex-settings.dat").unwrap();
+    let input = temp_path("check-in-place.dat");
+    let output_path = temp_path("check-output.json");
+    std::fs::write(&input, &fixture).unwrap();
+    std::fs::write(&output_path, b"{}").unwrap();
+    for output in [&input, &output_path] {
+        let run = run(&[
+            "--passthrough",
+            "--check",
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+        ]);
+        assert_eq!(run.status.code(), Some(0), "{:?}", run);
+    }
+    assert_eq!(std::fs::read(&input).unwrap(), fixture);
+    assert_eq!(std::fs::read(&output_path).unwrap(), b"{}");
+    let missing = temp_path("check-missing.dat");
+    run(&[
+        "--passthrough",
+        "--check",
+        input.to_str().unwrap(),
+        missing.to_str().unwrap(),
+    ]);
+    assert!(!missing.exists());
+    for path in [input, output_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn get_prints_one_value() {
+    let output = run(&["--hex", "--get", "startup/my-string-setting", SIMPLE_HEX]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(output.stdout, b"deadbeef\n");
+
+    let get = |path: &str| {
+        let output = run(&["--get", path, "test_data/golden.dat"]);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(get("startup/golden-integer"), "-9007199254740993\n");
+    assert_eq!(
+        get("runtime-per-user/golden-color"),
+        "0.25,0.5,1,0.3333333333333333\n"
+    );
+
+    let output = run(&["--get", "runtime-global/missing", "test_data/golden.dat"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn set_changes_one_value_in_place() {
+    let golden = std::fs::read("test_data/golden.dat").unwrap();
+    let path = temp_path("set.dat");
+    std::fs::write(&path, &golden).unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = run(&["--set", "startup/golden-double=2", path]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let output = run(&["--get", "startup/golden-double", "-f", "json", path]);
+    assert_eq!(output.stdout, b"2\n");
+    let output = run(&["--diff", "test_data/golden.dat", path]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Changed startup/golden-double from 2.0 to 0.1\n"
+    );
+
+    let output = run(&["--set", "startup/golden-new=1", path]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("add --create"));
+    let output = run(&["--set", "startup/golden-double=0.1", path]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(std::fs::read(path).unwrap() == golden);
+
+    let output = run(&["--set", "startup/golden-new=1", "--create", path]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let output = run(&["--get", "startup/golden-new", path]);
+    assert_eq!(output.stdout, b"1\n");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn kv_builds_settings() {
+    let output = run(&[
+        "--kv",
+        "startup/my-string-setting=deadbeef",
+        "--factorio-version",
+        "1.1.82.4",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let expected = SIMPLE_HEX
+        .split(' ')
+        .map(|byte| u8::from_str_radix(byte, 16).unwrap())
+        .collect::<Vec<_>>();
+    assert!(output.stdout == expected);
+
+    let output = run(&[
+        "--kv",
+        "startup/foo:bool=true",
+        "--kv",
+        "runtime-global/bar:string=hello",
+        "--factorio-version",
+        "1.1.82",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let decoded = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let json: serde_json::Value = serde_json::from_slice(&decoded.stdout).unwrap();
+    assert_eq!(json["startup"]["foo"]["value"], true);
+    assert_eq!(json["runtime-global"]["bar"]["value"], "hello");
+
+    for conflict in [
+        ["startup/foo=1", "startup/foo=2"],
+        ["startup/foo=1", "runtime-per-user/foo=1"],
+    ] {
+        let output = run(&[
+            "--kv",
+            conflict[0],
+            "--kv",
+            conflict[1],
+            "--factorio-version",
+            "1.1.82",
+        ]);
+        assert_eq!(output.status.code(), Some(2), "{:?}", output);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("conflicts with"));
+    }
+    let output = run(&[
+        "--kv",
+        "startup/foo:bool=yes",
+        "--factorio-version",
+        "1.1.82",
+    ]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+}
+
+#[test]
+fn has_setting_exit_codes() {
+    let output = run(&[
+        "--has",
+        "startup/non-combat-mode",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    let output = run(&[
+        "--has",
+        "runtime-global/non-combat-mode",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    // an OUTPUT that couldn't be created doesn't matter, since nothing is written
+    let output = run(&[
+        "--has",
+        "startup/non-combat-mode",
+        "test_data/complex-settings.dat",
+        "/nonexistent-directory/out.json",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+
+    let json = run(&["-f", "json", "test_data/complex-settings.dat"]).stdout;
+    let output = run_with_stdin(
+        &[
+            "--has",
+            "startup/non-combat-mode",
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn string_encoding_recovers_latin1() {
+    // {"startup": {"a": {"value": "caf\xe9"}}, ...} with the string in Latin-1
+    let hex = "01 00 01 00 00 00 00 00 00 05 00 03 00 00 00 \
+               00 07 73 74 61 72 74 75 70 05 00 01 00 00 00 \
+               00 01 61 05 00 01 00 00 00 00 05 76 61 6c 75 65 03 00 00 04 63 61 66 e9 \
+               00 0e 72 75 6e 74 69 6d 65 2d 67 6c 6f 62 61 6c 05 00 00 00 00 00 \
+               00 10 72 75 6e 74 69 6d 65 2d 70 65 72 2d 75 73 65 72 05 00 00 00 00 00";
+    let output = run(&["--hex", "-f", "json", hex]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run(&["--hex", "-f", "json", "--string-encoding", "latin1", hex]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("won't reproduce"));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["startup"]["a"]["value"], "caf\u{e9}");
+}
+
+#[test]
+fn length_stats_report() {
+    let output = run(&["--length-stats", "test_data/complex-settings.dat"]);
+    assert_eq!(output.status.code(), Some(0));
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.contains("\n5-byte lengths below 255: 0\n"), "{}", text);
+}
+
+#[cfg(unix)]
+#[test]
+fn output_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &PathBuf| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+    let output = temp_path("file-mode.json");
+    let _ = std::fs::remove_file(&output);
+    let path = output.to_str().unwrap();
+
+    let result = run(&["--file-mode", "0640", "test_data/settings-2.0.dat", path]);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(mode(&output), 0o640);
+
+    // replacing the file keeps its permissions
+    std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let result = run(&["test_data/settings-2.0.dat", path]);
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(mode(&output), 0o600);
+
+    let result = run(&["--file-mode", "0999", "test_data/settings-2.0.dat", path]);
+    assert_eq!(result.status.code(), Some(2));
+    let _ = std::fs::remove_file(output);
+
+    // --set without an OUTPUT rewrites the input with the mode
+    let input = temp_path("file-mode.dat");
+    std::fs::copy("test_data/settings-2.0.dat", &input).unwrap();
+    std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let path = input.to_str().unwrap();
+    let result = run(&[
+        "--file-mode",
+        "0600",
+        "--set",
+        "startup/x=1",
+        "--create",
+        path,
+    ]);
+    assert_eq!(result.status.code(), Some(0), "{:?}", result);
+    assert_eq!(mode(&input), 0o600);
+    let _ = std::fs::remove_file(input);
+}
+
+#[cfg(unix)]
+#[test]
+fn output_through_symlink() {
+    let dir = temp_path("symlinked-output");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("real")).unwrap();
+    let target = dir.join("real/settings.json");
+    std::fs::write(&target, "old").unwrap();
+    // a chain of links, the first relative to its directory
+    let middle = dir.join("middle.json");
+    let link = dir.join("link.json");
+    std::os::unix::fs::symlink(&target, &middle).unwrap();
+    std::os::unix::fs::symlink("middle.json", &link).unwrap();
+
+    let result = run(&["test_data/settings-2.0.dat", link.to_str().unwrap()]);
+    assert_eq!(result.status.code(), Some(0), "{:?}", result);
+    for path in [&link, &middle] {
+        assert!(std::fs::symlink_metadata(path).unwrap().is_symlink());
+    }
+    let expected = run(&["-f", "json", "test_data/settings-2.0.dat"]).stdout;
+    assert_eq!(std::fs::read(&target).unwrap(), expected);
+
+    // a dangling link is followed to create its target
+    std::fs::remove_file(&target).unwrap();
+    let result = run(&["test_data/settings-2.0.dat", link.to_str().unwrap()]);
+    assert_eq!(result.status.code(), Some(0), "{:?}", result);
+    assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+    assert_eq!(std::fs::read(&target).unwrap(), expected);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn decode_cache() {
+    let dir = temp_path("decode-cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let dir_arg = dir.to_str().unwrap();
+    let uncached = run(&["-f", "json", "test_data/settings-2.0.dat"]).stdout;
+
+    let output = run(&[
+        "--cache-dir",
+        dir_arg,
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, uncached);
+    let entries = std::fs::read_dir(&dir).unwrap().collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+
+    // a cached entry is returned as is, as long as the input is unchanged, and so are the
+    // warnings printed when it was stored
+    let entry = entries[0].as_ref().unwrap().path();
+    std::fs::write(
+        &entry,
+        r#"{"messages": [{"level": "warn", "key": null, "message": "stored"}], "output": "cached"}"#,
+    )
+    .unwrap();
+    let output = run(&[
+        "--cache-dir",
+        dir_arg,
+        "-f",
+        "json",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_eq!(output.stdout, b"cached\n");
+    assert_eq!(output.stderr, b"Warning: stored\n");
+    // a flag the key doesn't name explicitly still changes it
+    let output = run(&[
+        "--cache-dir",
+        dir_arg,
+        "-f",
+        "json",
+        "--timeout",
+        "60000",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_ne!(output.stdout, b"cached\n");
+    let output = run(&[
+        "--cache-dir",
+        dir_arg,
+        "-f",
+        "toml",
+        "test_data/settings-2.0.dat",
+    ]);
+    assert_ne!(output.stdout, b"cached");
+
+    // a NaN is warned about by a cached run just as by the run that stored it
+    let toml = "[factorio_version]\nmajor = 1\nminor = 1\npatch = 82\nbuild = 4\n\n\
+                [startup.not-a-number]\ntype = \"Double\"\nvalue = nan\n\n\
+                [runtime-global]\n\n[runtime-per-user]\n";
+    let encoded = run_with_stdin(&["-m", "encode", "-f", "toml", "-"], toml.as_bytes()).stdout;
+    let args = ["--cache-dir", dir_arg, "-m", "decode", "-f", "json", "-"];
+    let stored = run_with_stdin(&args, &encoded);
+    let replayed = run_with_stdin(&args, &encoded);
+    assert!(String::from_utf8_lossy(&stored.stderr).contains("won't read back exactly"));
+    assert_eq!(replayed.stderr, stored.stderr);
+    assert_eq!(replayed.stdout, stored.stdout);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn schema_only_decode() {
+    let output = run(&[
+        "--schema-only",
+        "-f",
+        "json",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON output");
+    assert_eq!(schema["startup"]["non-combat-mode"]["value"], "bool");
+    assert!(schema.get("factorio_version").is_none());
+}
+
+#[test]
+fn version_from_overlay() {
+    let overlay = temp_path("overlay-version.json");
+    std::fs::write(
+        &overlay,
+        r#"{"factorio_version": {"major": 2, "minor": 0, "patch": 28, "build": 0},
+            "startup": {"golden-bool": {"type": "Bool", "value": false}},
+            "runtime-global": {}, "runtime-per-user": {}}"#,
+    )
+    .unwrap();
+    let version = |extra: &[&str]| {
+        let mut args = vec![
+            "-f",
+            "json",
+            "--overlay",
+            overlay.to_str().unwrap(),
+            "test_data/golden.dat",
+        ];
+        args.extend(extra);
+        let output = run(&args);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        let merged: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(merged["startup"]["golden-bool"]["value"], false);
+        merged["factorio_version"]["major"].clone()
+    };
+    let golden: serde_json::Value =
+        serde_json::from_slice(&run(&["-f", "json", "test_data/golden.dat"]).stdout).unwrap();
+    assert_eq!(version(&[]), golden["factorio_version"]["major"]);
+    assert_eq!(version(&["--take-version-from-overlay"]), 2);
+    std::fs::remove_file(overlay).unwrap();
+}
+
+#[test]
+fn overlay_binary_onto_binary() {
+    let overlay = temp_path("overlay-binary.json");
+    let overlay_dat = temp_path("overlay-binary.dat");
+    let merged = temp_path("overlay-merged.dat");
+    std::fs::write(
+        &overlay,
+        r#"{"factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0},
+            "startup": {"golden-bool": {"type": "Bool", "value": false}},
+            "runtime-global": {}, "runtime-per-user": {}}"#,
+    )
+    .unwrap();
+    let output = run(&[overlay.to_str().unwrap(), overlay_dat.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+
+    let output = run(&[
+        "test_data/golden.dat",
+        merged.to_str().unwrap(),
+        "--overlay",
+        overlay_dat.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let decode = |path: &Path| {
+        let output = run(&["-f", "json", path.to_str().unwrap()]);
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    let mut expected = decode(Path::new("test_data/golden.dat"));
+    expected["startup"]["golden-bool"]["value"] = false.into();
+    assert_eq!(decode(&merged), expected);
+    for path in [overlay, overlay_dat, merged] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn overlays_apply_in_order() {
+    let base = temp_path("overlay-base.json");
+    let first = temp_path("overlay-first.json");
+    let second = temp_path("overlay-second.json");
+    let settings = |startup: serde_json::Value| {
+        serde_json::to_vec(&serde_json::json!({
+            "factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0},
+            "startup": startup,
+            "runtime-global": {},
+            "runtime-per-user": {}
+        }))
+        .unwrap()
+    };
+    std::fs::write(&base, settings(serde_json::json!({"a": {"type": "Integer", "value": 0}, "b": {"type": "Integer", "value": 0}}))).unwrap();
+    std::fs::write(&first, settings(serde_json::json!({"a": {"type": "Integer", "value": 1}, "c": {"type": "Integer", "value": 1}}))).unwrap();
+    std::fs::write(
+        &second,
+        settings(serde_json::json!({"a": {"type": "Integer", "value": 2}})),
+    )
+    .unwrap();
+
+    let dat = run(&[base.to_str().unwrap(), "-m", "encode"]).stdout;
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "decode",
+            "-f",
+            "json",
+            "--verbose",
+            "--overlay",
+            first.to_str().unwrap(),
+            "--overlay",
+            second.to_str().unwrap(),
+            "-",
+        ],
+        &dat,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let merged: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(merged["startup"]["a"]["value"], 2);
+    assert_eq!(merged["startup"]["b"]["value"], 0);
+    assert_eq!(merged["startup"]["c"]["value"], 1);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("startup/a: set by {}", second.display())),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(&format!("startup/c: set by {}", first.display())),
+        "{}",
+        stderr
+    );
+
+    for path in [base, first, second] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[test]
+fn encoding_binary_input_suggests_decoding() {
+    let dat = std::fs::read("test_data/complex-settings.dat").unwrap();
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &dat);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("looks like an already-encoded .dat; did you mean to decode?"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn ndjson_input_encodes() {
+    let records = concat!(
+        r#"{"factorio_version": {"major": 1, "minor": 1, "patch": 0, "build": 0}}"#,
+        "\n",
+        r#"{"section": "startup", "key": "b", "type": "Integer", "value": 1}"#,
+        "\n",
+        r#"{"section": "startup", "key": "a", "type": "Double", "value": 0.5}"#,
+        "\n",
+    );
+    let output = run_with_stdin(&["-m", "encode", "-f", "ndjson", "-"], records.as_bytes());
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let decoded = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let settings: serde_json::Value = serde_json::from_slice(&decoded.stdout).unwrap();
+    let keys = settings["startup"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["b", "a"]);
+    assert_eq!(settings["startup"]["a"]["value"], 0.5);
+
+    let malformed = format!("{}{{\"section\": \"startup\"\n", records);
+    let output = run_with_stdin(&["-m", "encode", "-f", "ndjson", "-"], malformed.as_bytes());
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Line 4"), "{}", stderr);
+
+    let output = run(&["test_data/complex-settings.dat", "-f", "ndjson"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn trailing_newline() {
+    for format in ["json", "toml"] {
+        let output = run(&["test_data/complex-settings.dat", "-f", format]);
+        assert!(output.stdout.ends_with(b"\n"), "{}", format);
+        assert!(!output.stdout.ends_with(b"\n\n"), "{}", format);
+
+        let output = run(&[
+            "test_data/complex-settings.dat",
+            "-f",
+            format,
+            "--no-trailing-newline",
+        ]);
+        assert_eq!(output.status.code(), Some(0));
+        assert!(!output.stdout.is_empty());
+        assert_ne!(output.stdout.last(), Some(&b'\n'), "{}", format);
+    }
+}
+
+#[test]
+fn inventory_of_directory() {
+    let output = run(&["--inventory", "-f", "json", "test_data"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let entries: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let files = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["file"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        [
+            "test_data/complex-settings.dat",
+            "test_data/golden.dat",
+            "test_data/list-settings.dat",
+            "test_data/settings-2.0.dat"
+        ]
+    );
+    assert!(entries[0]["startup"].as_u64().is_some());
+    assert_eq!(
+        entries[0]["version"].as_str().unwrap().split('.').count(),
+        4
+    );
+
+    let output = run(&["--inventory", "test_data/*.dat"]);
+    assert_eq!(output.status.code(), Some(0));
+    let table = String::from_utf8(output.stdout).unwrap();
+    assert!(table.starts_with("file"), "{}", table);
+    assert_eq!(table.lines().count(), 5);
+}
+
+#[test]
+fn compat_check_and_target_version() {
+    let output = run(&[
+        "test_data/settings-2.0.dat",
+        "--compat-check",
+        "--target-version",
+        "1.1.110",
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        report.contains("so all 872 settings belong to mods that Factorio 1.1.110.0 won't load"),
+        "{}",
+        report
+    );
+
+    let output = run(&[
+        "test_data/settings-2.0.dat",
+        "--compat-check",
+        "--target-version",
+        "2.0.99",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let json = run(&["test_data/settings-2.0.dat", "-f", "json"]).stdout;
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--target-version",
+            "1.1.110",
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Warning: the settings were written by Factorio 2.0"));
+    assert_eq!(output.stdout[..8], [1, 0, 1, 0, 110, 0, 0, 0]);
+}
+
+#[test]
+fn html_report() {
+    let output = run(&["test_data/complex-settings.dat", "-f", "html"]);
+    assert_eq!(output.status.code(), Some(0));
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"), "{}", &html[..100]);
+    assert!(html.contains("<h2>startup</h2>"));
+    assert!(html.contains("<code>non-combat-mode</code>"));
+
+    let output = run_with_stdin(&["-m", "encode", "-f", "html", "-"], b"<html></html>");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn inventory_failures() {
+    let dir = temp_path("inventory-failures");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a-broken.dat"), [1, 0, 1]).unwrap();
+    std::fs::copy("test_data/settings-2.0.dat", dir.join("b-good.dat")).unwrap();
+    std::fs::write(dir.join("c-broken.dat"), [5]).unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = run(&["--inventory", dir_arg]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 4);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("2 of 3 files failed:"), "{}", stderr);
+    assert!(stderr.contains("a-broken.dat") && stderr.contains("c-broken.dat"));
+
+    let output = run(&["--inventory", "--fail-fast", dir_arg]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("a-broken.dat"), "{}", stderr);
+    assert!(!stderr.contains("c-broken.dat"), "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rename_map_when_encoding() {
+    let map = temp_path("rename-map.txt");
+    std::fs::write(
+        &map,
+        "startup/bnl-* = renamed-bnl-*\nnon-combat-mode = peaceful\n",
+    )
+    .unwrap();
+    let json = run(&["test_data/complex-settings.dat", "-f", "json"]).stdout;
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--rename",
+            map.to_str().unwrap(),
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Renamed "), "{}", stderr);
+
+    let decoded = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let settings: serde_json::Value = serde_json::from_slice(&decoded.stdout).unwrap();
+    let startup = settings["startup"].as_object().unwrap();
+    assert_eq!(startup["peaceful"]["value"], false);
+    assert!(!startup.contains_key("non-combat-mode"));
+    assert!(startup.keys().any(|key| key.starts_with("renamed-bnl-")));
+    assert!(!startup.keys().any(|key| key.starts_with("bnl-")));
+    let _ = std::fs::remove_file(map);
+}
+
+#[test]
+fn source_lines_cite_toml() {
+    let toml = temp_path("source-lines.toml");
+    std::fs::write(
+        &toml,
+        concat!(
+            "[factorio_version]\nmajor = 1\nminor = 1\npatch = 0\nbuild = 0\n\n",
+            "[startup.fine]\ntype = \"Bool\"\nvalue = true\n\n",
+            "[runtime-global.bad-color]\ntype = \"Color\"\n",
+            "value = { r = 2.0, g = 0.0, b = 0.0, a = 1.0 }\n\n",
+            "[runtime-per-user]\n",
+        ),
+    )
+    .unwrap();
+    let output = run(&["--lint", "--source-lines", toml.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        report.starts_with("runtime-global/bad-color: color channel r is outside 0 to 1: 2 (at line 11, column 17)"),
+        "{}",
+        report
+    );
+    let _ = std::fs::remove_file(toml);
+}
+
+#[test]
+fn extract_setting_bytes() {
+    let output = run(&[
+        "--hex",
+        "--extract-bytes",
+        "startup/my-string-setting",
+        "--explain",
+        SIMPLE_HEX,
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let text = String::from_utf8(output.stdout).unwrap();
+    let mut lines = text.lines();
+    assert_eq!(
+        lines.next(),
+        Some("05 00 01 00 00 00 00 05 76 61 6c 75 65 03 00 00 08 64 65 61 64 62 65 65 66")
+    );
+    assert_eq!(lines.next(), Some(""));
+    assert!(lines.next().unwrap().starts_with("000000  05 00"));
+    assert!(text.contains("string \"deadbeef\""));
+
+    let raw = temp_path("extracted.bin");
+    let output = run(&[
+        "--hex",
+        "--extract-bytes",
+        "startup/my-string-setting",
+        SIMPLE_HEX,
+        raw.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(std::fs::read(&raw).unwrap()[..3], [5, 0, 1]);
+    let _ = std::fs::remove_file(raw);
+
+    let output = run(&["--hex", "--extract-bytes", "startup/missing", SIMPLE_HEX]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("The input has no setting startup/missing"));
+}
+
+#[test]
+fn non_dictionary_root_decodes_raw() {
+    const ROOT_STRING: &str = "01 00 01 00 52 00 04 00 00 03 00 00 03 61 62 63";
+    let output = run(&["--hex", "--kind", "map-settings", "-f", "json", ROOT_STRING]);
+    assert_eq!(output.status.code(), Some(0));
+    let raw: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(raw["properties"]["value"], "abc");
+
+    let output = run(&["--hex", "--passthrough", ROOT_STRING]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, input_bytes(ROOT_STRING));
+
+    let output = run(&["--hex", "-f", "json", ROOT_STRING]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--kind map-settings"));
+
+    let output = run(&["--hex", "--kind", "map-settings", "--lint", ROOT_STRING]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--lint only applies to --kind mod-settings"));
+    let output = run(&[
+        "--hex",
+        "--kind",
+        "map-settings",
+        "--where",
+        "a>1",
+        "-f",
+        "json",
+        ROOT_STRING,
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn encode_directory_of_sections() {
+    let dir = temp_path("sections");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("startup.json"),
+        r#"{"my-setting": {"type": "Integer", "value": 3}}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("runtime-per-user.toml"),
+        "[my-flag]\ntype = \"Bool\"\nvalue = true\n",
+    )
+    .unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = run(&[dir_arg]);
+    assert_eq!(output.status.code(), Some(2), "the version is required");
+    let output = run(&["--factorio-version", "1.1.82", dir_arg]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let decoded = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let settings: serde_json::Value = serde_json::from_slice(&decoded.stdout).unwrap();
+    assert_eq!(settings["startup"]["my-setting"]["value"], 3);
+    assert_eq!(settings["runtime-global"], serde_json::json!({}));
+    assert_eq!(settings["runtime-per-user"]["my-flag"]["value"], true);
+
+    std::fs::write(
+        dir.join("runtime-global.json"),
+        r#"{"startup": {"my-setting": {"type": "Integer", "value": 3}}}"#,
+    )
+    .unwrap();
+    let output = run(&["--factorio-version", "1.1.82", dir_arg]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("names a section"), "{}", stderr);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn golden_encoding() {
+    let golden = std::fs::read("test_data/golden.dat").unwrap();
+    let output = run(&["-m", "encode", "test_data/golden.toml"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout == golden, "encoding differs from golden.dat");
+
+    // A checkout that converts the fixture to CRLF line endings encodes the same bytes
+    let text = std::fs::read_to_string("test_data/golden.toml").unwrap();
+    let output = run_with_stdin(
+        &["-m", "encode", "-f", "toml", "-"],
+        text.replace('\n', "\r\n").as_bytes(),
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert!(output.stdout == golden, "CRLF input changed the encoding");
+
+    let output = run(&["test_data/golden.dat", "-f", "json"]);
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &output.stdout);
+    assert!(
+        output.stdout == golden,
+        "decoding to JSON and back changed the encoding"
+    );
+}
+
+#[test]
+fn where_keeps_matching_settings() {
+    let json = run(&["-f", "json", "test_data/complex-settings.dat"]).stdout;
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--where",
+            "startup/non-combat-mode=false",
+            "--where",
+            "startup/aircraft-realism-*>1",
+            "-",
+        ],
+        &json,
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let output = run_with_stdin(&["-m", "decode", "-f", "json", "-"], &output.stdout);
+    let filtered: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let startup = filtered["startup"].as_object().unwrap();
+    assert!(startup.contains_key("non-combat-mode"));
+    let numbers = startup
+        .iter()
+        .filter(|(key, _)| key.starts_with("aircraft-realism-"))
+        .map(|(_, setting)| setting["value"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert!(!numbers.is_empty());
+    assert!(numbers.iter().all(|n| *n > 1.0), "{:?}", numbers);
+    assert!(filtered["runtime-per-user"].as_object().unwrap().is_empty());
+
+    let output = run(&[
+        "-m",
+        "encode",
+        "--where",
+        "startup/difficulty>hard",
+        "in.json",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expected a number"));
+}
+
+#[test]
+fn reproducible_json() {
+    let output = run(&[
+        "test_data/complex-settings.dat",
+        "-f",
+        "json",
+        "--reproducible",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(text.lines().count(), 1, "compact output is one line");
+    let settings: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let keys = settings["startup"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect::<Vec<_>>();
+    assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    let output = run(&[
+        "test_data/complex-settings.dat",
+        "-f",
+        "toml",
+        "--reproducible",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = run(&["-m", "encode", "-f", "json", "--reproducible", "in.json"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn vcs_output_ignores_setting_order() {
+    for format in ["json", "toml"] {
+        let output = run(&["test_data/settings-2.0.dat", "-f", format, "--vcs"]);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        let text = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            text.lines().count() > 1,
+            "{} output is pretty-printed",
+            format
+        );
+        assert!(text.ends_with('\n') && !text.ends_with("\n\n") && !text.contains('\r'));
+
+        // the same settings saved in another order give the same text
+        let json = run(&["test_data/settings-2.0.dat", "-f", "json"]);
+        let mut settings: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+        let startup = settings["startup"].as_object().unwrap();
+        settings["startup"] = startup
+            .iter()
+            .rev()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+        let reordered = run_with_stdin(
+            &["-m", "encode", "-f", "json", "-"],
+            settings.to_string().as_bytes(),
+        );
+        assert_ne!(
+            reordered.stdout,
+            std::fs::read("test_data/settings-2.0.dat").unwrap()
+        );
+        let again = run_with_stdin(
+            &["-m", "decode", "-f", format, "--vcs", "-"],
+            &reordered.stdout,
+        );
+        assert_eq!(String::from_utf8(again.stdout).unwrap(), text, "{}", format);
+    }
+
+    let output = run(&[
+        "test_data/settings-2.0.dat",
+        "-f",
+        "json",
+        "--vcs",
+        "--no-trailing-newline",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn sniffs_format_of_files_and_pipes() {
+    let dat = std::fs::read("test_data/complex-settings.dat").unwrap();
+    let untyped = temp_path("sniff-settings");
+    std::fs::write(&untyped, &dat).unwrap();
+    let from_file = run(&["-f", "json", untyped.to_str().unwrap()]);
+    assert_eq!(from_file.status.code(), Some(0), "{:?}", from_file);
+    let from_pipe = run_with_stdin(&["-f", "json", "-"], &dat);
+    assert_eq!(from_pipe.status.code(), Some(0), "{:?}", from_pipe);
+    assert_eq!(from_file.stdout, from_pipe.stdout);
+
+    let toml = run(&["-f", "toml", "test_data/complex-settings.dat"]).stdout;
+    std::fs::write(&untyped, &toml).unwrap();
+    let encoded = temp_path("sniffed.dat");
+    let output = run(&[untyped.to_str().unwrap(), encoded.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(std::fs::read(&encoded).unwrap(), dat);
+    let output = run_with_stdin(&["-"], &from_pipe.stdout);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(output.stdout, dat);
+
+    let output = run_with_stdin(&["-m", "encode", "-"], &dat);
+    assert_eq!(output.status.code(), Some(2));
+    let _ = std::fs::remove_file(untyped);
+    let _ = std::fs::remove_file(encoded);
+}
+
+#[test]
+fn find_duplicates_report() {
+    let output = run(&[
+        "--find-duplicates",
+        "-f",
+        "json",
+        "test_data/complex-settings.dat",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let groups: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let groups = groups.as_array().unwrap();
+    assert!(!groups.is_empty());
+    for group in groups {
+        assert!(group["keys"].as_array().unwrap().len() > 1);
+        assert_ne!(group["value"]["type"], "Bool");
+    }
+
+    let output = run(&["--find-duplicates", "test_data/complex-settings.dat"]);
+    let text = String::from_utf8(output.stdout).unwrap();
+    assert!(text.starts_with("startup, "), "{}", text);
+}
+
+#[test]
+fn encode_version_only() {
+    let output = run(&["--encode-version-only", "--factorio-version", "1.1.82.4"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let mut expected = vec![1, 0, 1, 0, 82, 0, 4, 0, 0, 5, 0, 3, 0, 0, 0];
+    for section in ["startup", "runtime-global", "runtime-per-user"] {
+        expected.extend([0, section.len() as u8]);
+        expected.extend(section.as_bytes());
+        expected.extend([5, 0, 0, 0, 0, 0]);
+    }
+    assert_eq!(output.stdout, expected);
+
+    let output = run(&["--encode-version-only"]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn warnings_as_json() {
+    let rename = temp_path("warnings-rename.txt");
+    std::fs::write(&rename, "a = b\n").unwrap();
+    let settings = r#"{
+        "factorio_version": {"major": 1, "minor": 1, "patch": 82, "build": 4},
+        "startup": {
+            "a": {"type": "Integer", "value": 1},
+            "b": {"type": "Integer", "value": 2}
+        },
+        "runtime-global": {},
+        "runtime-per-user": {}
+    }"#;
+    let output = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "json",
+            "--rename",
+            rename.to_str().unwrap(),
+            "--warnings-format",
+            "json",
+            "-",
+        ],
+        settings.as_bytes(),
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let records = json_lines(&output.stderr);
+    assert_eq!(records.len(), 3, "{:?}", records);
+    assert_eq!(records[0]["level"], "warn");
+    assert_eq!(records[0]["key"], "startup/a");
+    assert!(records[0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("another setting has after renaming"));
+    assert_eq!(
+        records[1],
+        serde_json::json!({"level": "info", "message": "Renamed 0 settings"})
+    );
+    assert_eq!(
+        records[2],
+        serde_json::json!({"level": "summary", "warnings": 1})
+    );
+    let _ = std::fs::remove_file(rename);
+}
+
+/// Each line of output parsed as JSON, failing if any isn't
+fn json_lines(output: &[u8]) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|err| panic!("{:?}: {}", line, err)))
+        .collect()
+}
+
+#[test]
+fn json_warnings_leave_no_plain_text() {
+    let json = ["--warnings-format", "json"];
+    let output = run(&[
+        &[
+            "test_data/golden.dat",
+            "-f",
+            "json",
+            "--stats",
+            "--size-report",
+        ],
+        &json[..],
+    ]
+    .concat());
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let records = json_lines(&output.stderr);
+    let levels = records
+        .iter()
+        .map(|record| record["level"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(levels, ["size-report", "stats", "summary"]);
+    assert!(records[0]["bytes"]["binary"].as_u64().is_some());
+    assert!(records[1]["settings"].as_u64().is_some());
+
+    let output = run_with_stdin(
+        &[&["-m", "decode", "-f", "json", "-"], &json[..]].concat(),
+        b"x",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let records = json_lines(&output.stderr);
+    assert_eq!(records[0]["level"], "error");
+    assert!(records[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Decoding settings"));
+
+    let dir = temp_path("json-failures");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("broken.dat"), [1, 0, 1]).unwrap();
+    let output = run(&[&["--inventory", dir.to_str().unwrap()], &json[..]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    let records = json_lines(&output.stderr);
+    assert_eq!(records[0]["level"], "error");
+    assert!(records[0]["file"].as_str().unwrap().ends_with("broken.dat"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn toml_color_styles_round_trip() {
+    let original = std::fs::read("test_data/settings-2.0.dat").unwrap();
+    for (style, marker) in [("inline", "value = { r = "), ("table", ".value]\nr = ")] {
+        let toml = run(&[
+            "test_data/settings-2.0.dat",
+            "-f",
+            "toml",
+            "--toml-colors",
+            style,
+        ]);
+        assert_eq!(toml.status.code(), Some(0), "{:?}", toml);
+        assert!(
+            String::from_utf8_lossy(&toml.stdout).contains(marker),
+            "{}",
+            style
+        );
+        let encoded = run_with_stdin(&["-m", "encode", "-f", "toml", "-"], &toml.stdout);
+        assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+        assert!(
+            encoded.stdout == original,
+            "{} colors changed the encoding",
+            style
+        );
+    }
+}
+
+#[test]
+fn color_formats_round_trip() {
+    let original = std::fs::read("test_data/settings-2.0.dat").unwrap();
+    for (form, marker) in [
+        ("array", "value = [0.0, 0.0, 1.0, 1.0]"),
+        ("hex", "value = \"#0000ffff\""),
+    ] {
+        let toml = run(&[
+            "test_data/settings-2.0.dat",
+            "-f",
+            "toml",
+            "--color-format",
+            form,
+        ]);
+        assert_eq!(toml.status.code(), Some(0), "{:?}", toml);
+        assert!(
+            String::from_utf8_lossy(&toml.stdout).contains(marker),
+            "{}",
+            form
+        );
+        let encoded = run_with_stdin(&["-m", "encode", "-f", "toml", "-"], &toml.stdout);
+        assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+        assert!(
+            encoded.stdout == original,
+            "{} colors changed the encoding",
+            form
+        );
+    }
+    // channels between steps of 1/255 stay objects rather than being rounded
+    let hex = run(&[
+        "test_data/settings-2.0.dat",
+        "-f",
+        "json",
+        "--color-format",
+        "hex",
+    ]);
+    let stderr = String::from_utf8_lossy(&hex.stderr);
+    assert!(
+        stderr.contains("Color startup/bnl-color-low_power can't be written as hex"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn restyled_toml_keeps_non_finite_doubles() {
+    let toml = "[factorio_version]\nmajor = 1\nminor = 1\npatch = 82\nbuild = 4\n\n\
+                [startup.not-a-number]\ntype = \"Double\"\nvalue = nan\n\n\
+                [startup.tint]\ntype = \"Color\"\nvalue = { r = 1.0, g = 0.0, b = 0.2, a = inf }\n\n\
+                [runtime-global]\n\n[runtime-per-user]\n";
+    let encoded = run_with_stdin(&["-m", "encode", "-f", "toml", "-"], toml.as_bytes());
+    assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+    for style in [
+        &["--color-format", "hex"][..],
+        &["--color-format", "array"],
+        &["--vcs"],
+    ] {
+        let mut args = vec!["-m", "decode", "-f", "toml", "-"];
+        args.extend_from_slice(style);
+        let output = run_with_stdin(&args, &encoded.stdout);
+        assert_eq!(output.status.code(), Some(0), "{:?} {:?}", style, output);
+        let text = String::from_utf8_lossy(&output.stdout);
+        assert!(text.contains("value = nan"), "{:?}: {}", style, text);
+        assert!(text.contains("inf"), "{:?}: {}", style, text);
+    }
+}
+
+#[test]
+fn output_to_input_path() {
+    let path = temp_path("same-path.json");
+    let decoded = run(&["test_data/settings-2.0.dat", path.to_str().unwrap()]);
+    assert_eq!(decoded.status.code(), Some(0), "{:?}", decoded);
+    let original = std::fs::read(&path).unwrap();
+
+    // a conversion that fails partway leaves the input as it was
+    let failed = run(&[
+        "-m",
+        "decode",
+        "-f",
+        "json",
+        path.to_str().unwrap(),
+        path.to_str().unwrap(),
+    ]);
+    assert_ne!(failed.status.code(), Some(0));
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    // one that succeeds replaces it only once it has been read
+    let converted = run(&[
+        "-m",
+        "convert",
+        "--input-format",
+        "json",
+        "--output-format",
+        "json",
+        "--color-format",
+        "array",
+        path.to_str().unwrap(),
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(converted.status.code(), Some(0), "{:?}", converted);
+    let encoded = run(&["-m", "encode", path.to_str().unwrap()]);
+    assert_eq!(
+        encoded.stdout,
+        std::fs::read("test_data/settings-2.0.dat").unwrap()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn list_mods_from_mod_list() {
+    let mods = temp_path("list-mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let settings = mods.join("mod-settings.dat");
+    std::fs::copy("test_data/settings-2.0.dat", &settings).unwrap();
+    let listed = run(&["--list-mods", settings.to_str().unwrap()]);
+    assert_eq!(listed.status.code(), Some(4), "{:?}", listed);
+
+    std::fs::write(
+        mods.join("mod-list.json"),
+        r#"{"mods": [{"name": "base", "enabled": true}, {"name": "quality", "enabled": false}, {"name": "bnl", "enabled": true}]}"#,
+    )
+    .unwrap();
+    let listed = run(&["--list-mods", settings.to_str().unwrap()]);
+    assert_eq!(listed.status.code(), Some(0), "{:?}", listed);
+    assert_eq!(String::from_utf8_lossy(&listed.stdout), "base\nbnl\n");
+    let json = run(&["--list-mods", "-f", "json", settings.to_str().unwrap()]);
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&json.stdout).unwrap(),
+        serde_json::json!(["base", "bnl"])
+    );
+
+    let piped = run_with_stdin(&["--list-mods", "-"], b"");
+    assert_eq!(piped.status.code(), Some(2));
+    std::fs::remove_dir_all(&mods).unwrap();
+}
+
+#[test]
+fn omit_none_values() {
+    let json = br#"{
+        "factorio_version": {"major": 1, "minor": 1, "patch": 82, "build": 4},
+        "startup": {"a": {"type": "None"}, "b": {"type": "Integer", "value": 1}, "c": {"type": "None"}},
+        "runtime-global": {"d": {"type": "None"}},
+        "runtime-per-user": {"e": {"type": "Bool", "value": true}}
+    }"#;
+    let encoded = run_with_stdin(&["-m", "encode", "-f", "json", "-"], json);
+    assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+    let dat = temp_path("none.dat");
+    std::fs::write(&dat, &encoded.stdout).unwrap();
+
+    // None values are kept by default, and survive a round trip
+    let kept = run(&[dat.to_str().unwrap(), "-f", "json"]);
+    assert_eq!(kept.status.code(), Some(0), "{:?}", kept);
+    let reencoded = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &kept.stdout);
+    assert_eq!(reencoded.stdout, encoded.stdout);
+
+    let omitted = run(&[dat.to_str().unwrap(), "-f", "json", "--omit-none"]);
+    assert_eq!(omitted.status.code(), Some(0), "{:?}", omitted);
+    let value: serde_json::Value = serde_json::from_slice(&omitted.stdout).unwrap();
+    assert_eq!(
+        value["startup"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["b"]
+    );
+    assert!(value["runtime-global"].as_object().unwrap().is_empty());
+    assert_eq!(value["runtime-per-user"]["e"]["value"], true);
+
+    let converted = run_with_stdin(
+        &[
+            "-m",
+            "convert",
+            "--input-format",
+            "json",
+            "--output-format",
+            "toml",
+            "--omit-none",
+            "-",
+        ],
+        json,
+    );
+    assert!(!String::from_utf8_lossy(&converted.stdout).contains("None"));
+
+    let encode_omitting = run_with_stdin(&["-m", "encode", "-f", "json", "--omit-none", "-"], json);
+    assert_eq!(encode_omitting.status.code(), Some(2));
+    std::fs::remove_file(&dat).unwrap();
+}
+
+#[test]
+fn max_depth_report() {
+    for (file, depth) in [
+        ("test_data/complex-settings.dat", "4\n"),
+        ("test_data/settings-2.0.dat", "5\n"),
+    ] {
+        let output = run(&[file, "-f", "json", "--max-depth-report"]);
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stderr), depth, "{}", file);
+    }
+}
+
+#[test]
+fn emit_checksum_beside_output() {
+    let dir = temp_path("checksum");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("golden.dat");
+    let encoded = run(&[
+        "test_data/golden.toml",
+        output.to_str().unwrap(),
+        "--emit-checksum",
+    ]);
+    assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("golden.dat.sha256")).unwrap(),
+        "afbc68746f55e78c3c27c287e6e01bffdbda64bc55649b8b3a0c311581614eca  golden.dat\n"
+    );
+
+    let to_stdout = run(&["-m", "encode", "test_data/golden.toml", "--emit-checksum"]);
+    assert_eq!(to_stdout.status.code(), Some(0), "{:?}", to_stdout);
+    assert!(String::from_utf8_lossy(&to_stdout.stderr).contains("no checksum was written"));
+
+    // every command writing an output file writes its checksum, not only conversions
+    let copied = dir.join("copied.dat");
+    let output = run(&[
+        "--passthrough",
+        "test_data/golden.dat",
+        copied.to_str().unwrap(),
+        "--emit-checksum",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let checksum = std::fs::read_to_string(dir.join("copied.dat.sha256")).unwrap();
+    assert!(checksum.ends_with("  copied.dat\n"), "{}", checksum);
+    let output = run(&[
+        copied.to_str().unwrap(),
+        "--set",
+        "startup/golden-bool=false",
+        "--emit-checksum",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_ne!(
+        std::fs::read_to_string(dir.join("copied.dat.sha256")).unwrap(),
+        checksum
+    );
+
+    let output = run(&[
+        "--passthrough",
+        "--check",
+        "test_data/golden.dat",
+        copied.to_str().unwrap(),
+        "--emit-checksum",
+    ]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn decode_hexdump() {
+    let expected = run(&["test_data/golden.dat", "-f", "json"]);
+    let from_file = run(&["--hexdump", "test_data/golden.xxd", "-f", "json"]);
+    assert_eq!(from_file.status.code(), Some(0), "{:?}", from_file);
+    assert_eq!(from_file.stdout, expected.stdout);
+
+    let dump = std::fs::read("test_data/golden.xxd").unwrap();
+    let from_stdin = run_with_stdin(&["--hexdump", "-", "-f", "json"], &dump);
+    assert_eq!(from_stdin.stdout, expected.stdout);
+
+    let truncated = run_with_stdin(&["--hexdump", "-", "-f", "json"], &dump[..dump.len() / 2]);
+    assert_eq!(truncated.status.code(), Some(1));
+
+    // written by xxd, with | bytes in its gutter
+    let pipes = run(&["--hexdump", "test_data/pipes.xxd", "-f", "json"]);
+    assert_eq!(pipes.status.code(), Some(0), "{:?}", pipes);
+    let json: serde_json::Value = serde_json::from_slice(&pipes.stdout).unwrap();
+    assert_eq!(json["startup"]["column-separator"]["value"], "a|b");
+    assert_eq!(json["runtime-per-user"]["pipes"]["value"], "||");
+}
+
+#[test]
+fn schema_validate_text_input() {
+    let schema = temp_path("schema.json");
+    let written = run(&["--hex", SIMPLE_HEX, "--schema-only", "-f", "json"]);
+    std::fs::write(&schema, &written.stdout).unwrap();
+    let validate = |json: &str, format: &[&str]| {
+        let mut args = vec!["--schema-validate", "--schema", schema.to_str().unwrap()];
+        args.extend(format);
+        args.extend(["-m", "encode", "--input-format", "json", "-"]);
+        run_with_stdin(&args, json.as_bytes())
+    };
+    let header = r#""factorio_version": {"major": 1, "minor": 1, "patch": 82, "build": 4}"#;
+
+    let valid = validate(
+        &format!(
+            r#"{{{}, "startup": {{"my-string-setting": {{"type": "String", "value": "cafe"}}}}, "runtime-global": {{}}, "runtime-per-user": {{}}}}"#,
+            header
+        ),
+        &[],
+    );
+    assert_eq!(valid.status.code(), Some(0), "{:?}", valid);
+    assert!(valid.stdout.is_empty());
+
+    let invalid_json = format!(
+        r#"{{{}, "startup": {{"my-string-setting": {{"type": "Integer", "value": 1}}}}, "runtime-global": {{"extra": {{"type": "Bool", "value": true}}}}, "runtime-per-user": {{}}}}"#,
+        header
+    );
+    let invalid = validate(&invalid_json, &[]);
+    assert_eq!(invalid.status.code(), Some(1), "{:?}", invalid);
+    assert_eq!(
+        String::from_utf8_lossy(&invalid.stdout),
+        "startup/my-string-setting/value: expected string, found integer\n\
+         runtime-global/extra: expected nothing, found dictionary\n"
+    );
+    let as_json = validate(&invalid_json, &["-f", "json"]);
+    let violations: serde_json::Value = serde_json::from_slice(&as_json.stdout).unwrap();
+    assert_eq!(
+        violations[0],
+        serde_json::json!({"path": "startup/my-string-setting/value", "expected": "string", "found": "integer"})
+    );
+    std::fs::remove_file(&schema).unwrap();
+}
+
+#[test]
+fn spellcheck_against_reference() {
+    let reference = temp_path("known-keys.txt");
+    std::fs::write(
+        &reference,
+        "# keys from my mods\nmy-string-setting\nother-setting\n",
+    )
+    .unwrap();
+    let toml = "[factorio_version]\nmajor = 1\nminor = 1\npatch = 82\nbuild = 4\n\n\
+                [startup.my-strng-setting]\ntype = \"String\"\nvalue = \"a\"\n\n\
+                [startup.unrelated]\ntype = \"Bool\"\nvalue = true\n\n\
+                [runtime-global]\n\n[runtime-per-user]\n";
+    let encoded = run_with_stdin(
+        &[
+            "-m",
+            "encode",
+            "-f",
+            "toml",
+            "--source-lines",
+            "--spellcheck",
+            reference.to_str().unwrap(),
+            "-",
+        ],
+        toml.as_bytes(),
+    );
+    assert_eq!(encoded.status.code(), Some(0), "{:?}", encoded);
+    let stderr = String::from_utf8_lossy(&encoded.stderr);
+    assert!(
+        stderr.contains(
+            "startup/my-strng-setting isn't a known setting; did you mean my-string-setting? (at line 7"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("unrelated"), "{}", stderr);
+
+    // a settings file works as the reference too
+    let decoded = run(&[
+        "--hex",
+        SIMPLE_HEX,
+        "-f",
+        "json",
+        "--spellcheck",
+        "test_data/golden.toml",
+    ]);
+    assert_eq!(decoded.status.code(), Some(0), "{:?}", decoded);
+    std::fs::remove_file(&reference).unwrap();
+}
+
+#[test]
+fn dot_graph_of_property_tree() {
+    let output = run(&["--hex", SIMPLE_HEX, "--dot"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let dot = String::from_utf8(output.stdout).unwrap();
+    assert!(dot.starts_with("digraph settings {\n"), "{}", dot);
+    assert!(dot.contains("  n2 [label=\"my-string-setting: dictionary\"];\n  n1 -> n2;\n"));
+    assert!(dot.ends_with("}\n"));
+
+    // every property of a real file gets a node, and every node but the root an edge
+    let output = run(&["test_data/settings-2.0.dat", "--dot"]);
+    let dot = String::from_utf8(output.stdout).unwrap();
+    let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+    let edges = dot.lines().filter(|line| line.contains(" -> ")).count();
+    assert_eq!(nodes, edges + 1);
+}
+
+#[test]
+fn lenient_bools_follow_schema() {
+    let schema = temp_path("bool-schema.json");
+    std::fs::write(
+        &schema,
+        r#"{"startup": {"my-flag": {"value": "bool"}, "my-count": {"value": "integer"}}, "runtime-global": {}, "runtime-per-user": {}}"#,
+    )
+    .unwrap();
+    let settings = |flag: &str| {
+        format!(
+            r#"{{"factorio_version": {{"major": 1, "minor": 1, "patch": 82, "build": 4}}, "startup": {{"my-flag": {}, "my-count": {{"type": "Integer", "value": 1}}}}, "runtime-global": {{}}, "runtime-per-user": {{}}}}"#,
+            flag
+        )
+    };
+    let encode = |json: &str, extra: &[&str]| {
+        let mut args = vec!["-m", "encode", "--input-format", "json"];
+        args.extend(extra);
+        args.push("-");
+        run_with_stdin(&args, json.as_bytes())
+    };
+    let lenient = ["--lenient-bools", "--schema", schema.to_str().unwrap()];
+
+    let expected = encode(&settings(r#"{"type": "Bool", "value": true}"#), &[]);
+    assert_eq!(expected.status.code(), Some(0), "{:?}", expected);
+
+    let numeric = settings(r#"{"type": "Bool", "value": 1}"#);
+    let strict = encode(&numeric, &[]);
+    assert_eq!(strict.status.code(), Some(1), "{:?}", strict);
+    let coerced = encode(&numeric, &lenient);
+    assert_eq!(coerced.status.code(), Some(0), "{:?}", coerced);
+    assert_eq!(coerced.stdout, expected.stdout);
+    assert!(String::from_utf8_lossy(&coerced.stderr)
+        .contains("Read the number at startup/my-flag as a bool"));
+    // the schema also decides the type, and my-count stays an integer
+    let typed = encode(&settings(r#"{"type": "Integer", "value": 1}"#), &lenient);
+    assert_eq!(typed.stdout, expected.stdout);
+
+    let without_schema = encode(&numeric, &["--lenient-bools"]);
+    assert_eq!(without_schema.status.code(), Some(2));
+    std::fs::remove_file(&schema).unwrap();
+}
+
+#[test]
+fn trim_string_values() {
+    let json = br#"{
+        "factorio_version": {"major": 1, "minor": 1, "patch": 82, "build": 4},
+        "startup": {"server-name": {"type": "String", "value": " My Server "}, "mode": {"type": "String", "value": "fast"}},
+        "runtime-global": {},
+        "runtime-per-user": {}
+    }"#;
+    let decode = |encoded: &[u8]| {
+        let decoded = run_with_stdin(&["-m", "decode", "-f", "json", "-"], encoded);
+        serde_json::from_slice::<serde_json::Value>(&decoded.stdout).unwrap()
+    };
+
+    // strings are kept as written by default
+    let kept = run_with_stdin(&["-m", "encode", "-f", "json", "-"], json);
+    assert_eq!(kept.status.code(), Some(0), "{:?}", kept);
+    assert!(kept.stderr.is_empty());
+    assert_eq!(
+        decode(&kept.stdout)["startup"]["server-name"]["value"],
+        " My Server "
+    );
+
+    let trimmed = run_with_stdin(&["-m", "encode", "-f", "json", "--trim-strings", "-"], json);
+    assert_eq!(trimmed.status.code(), Some(0), "{:?}", trimmed);
+    assert_eq!(
+        String::from_utf8_lossy(&trimmed.stderr),
+        "Warning: trimmed whitespace from the value of startup/server-name\n"
+    );
+    let value = decode(&trimmed.stdout);
+    assert_eq!(value["startup"]["server-name"]["value"], "My Server");
+    assert_eq!(value["startup"]["mode"]["value"], "fast");
+
+    let decoding = run(&["--trim-strings", "--hex", SIMPLE_HEX, "-f", "json"]);
+    assert_eq!(decoding.status.code(), Some(2));
+}
+
+#[test]
+fn toml_section_count_comments() {
+    let output = run(&[
+        "test_data/golden.dat",
+        "-f",
+        "toml",
+        "--toml-section-counts",
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let toml = String::from_utf8(output.stdout).unwrap();
+    let comments = toml
+        .lines()
+        .filter(|line| line.starts_with('#'))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        comments,
+        [
+            "# startup: 5 settings",
+            "# runtime-global: 4 settings",
+            "# runtime-per-user: 2 settings"
+        ]
+    );
+    let reencoded = run_with_stdin(&["-m", "encode", "-f", "toml", "-"], toml.as_bytes());
+    assert_eq!(reencoded.status.code(), Some(0), "{:?}", reencoded);
+    assert!(reencoded.stdout == std::fs::read("test_data/golden.dat").unwrap());
+
+    let json = run(&[
+        "test_data/golden.dat",
+        "-f",
+        "json",
+        "--toml-section-counts",
+    ]);
+    assert_eq!(json.status.code(), Some(2));
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn decode_from_clipboard() {
+    use std::os::unix::fs::PermissionsExt;
+    // a wl-paste that prints settings pasted as hex
+    let bin = temp_path("clipboard-bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let paste = bin.join("wl-paste");
+    std::fs::write(&paste, format!("#!/bin/sh\nprintf '%s' '{}'\n", SIMPLE_HEX)).unwrap();
+    std::fs::set_permissions(&paste, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let clipboard = |path: &std::path::Path| {
+        command()
+            .args(["--clipboard", "-f", "json"])
+            .env("PATH", path)
+            .output()
+            .expect("running binary")
+    };
+
+    let output = clipboard(&bin);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let expected = run(&["--hex", SIMPLE_HEX, "-f", "json"]);
+    assert_eq!(output.stdout, expected.stdout);
+
+    std::fs::remove_file(&paste).unwrap();
+    let output = clipboard(&bin);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Reading the clipboard"));
+    std::fs::remove_dir(&bin).unwrap();
+}
+
+#[test]
+fn size_report_compares_formats() {
+    let output = run(&["test_data/golden.dat", "-f", "json", "--size-report"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let report = String::from_utf8(output.stderr).unwrap();
+    let rows = report
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 5, "{}", report);
+    let size = std::fs::metadata("test_data/golden.dat").unwrap().len();
+    assert_eq!(rows[1], ["binary", &size.to_string(), "1.00x"]);
+    // the JSON row measures the same text that's written
+    assert_eq!(rows[2][..2], ["json", &output.stdout.len().to_string()]);
+    assert_eq!(rows[3][..2], ["json", "compact"]);
+    // and the TOML row the default TOML output, with its colors inlined
+    let toml = run(&["test_data/golden.dat", "-f", "toml"]).stdout;
+    assert_eq!(rows[4][..2], ["toml", &toml.len().to_string()]);
+
+    let encoding = run(&["-m", "encode", "--size-report", "test_data/golden.toml"]);
+    assert_eq!(encoding.status.code(), Some(2));
+}
+
+#[test]
+fn encode_streamed_json() {
+    let json = run(&["--hex", SIMPLE_HEX, "-f", "json"]).stdout;
+    let expected = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &json);
+    assert_eq!(expected.status.code(), Some(0), "{:?}", expected);
+
+    // a byte order mark is skipped, as it is when sniffing the format
+    let with_bom = [&b"\xef\xbb\xbf"[..], &json].concat();
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &with_bom);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(output.stdout, expected.stdout);
+    let output = run_with_stdin(&["-m", "encode", "-"], &with_bom);
+    assert_eq!(output.stdout, expected.stdout, "sniffed as JSON");
+
+    // reading stops at the end of the document, but anything after it is still an error
+    let trailing = [&json[..], b"\n{}"].concat();
+    let output = run_with_stdin(&["-m", "encode", "-f", "json", "-"], &trailing);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("trailing characters"));
+}
+
+#[test]
+fn resolve_defaults_from_mods_and_files() {
+    let mods = temp_path("mods");
+    std::fs::create_dir_all(mods.join("my-mod_1.0.0")).unwrap();
+    std::fs::write(
+        mods.join("my-mod_1.0.0/settings.lua"),
+        r#"data:extend({{type = "bool-setting", name = "my-bool-setting", setting_type = "startup", default_value = true}})"#,
+    )
+    .unwrap();
+    // the string setting is declared by a zipped mod, written by Python's zipfile
+    std::fs::copy(
+        "test_data/zipped-mod_1.0.0.zip",
+        mods.join("zipped-mod_1.0.0.zip"),
+    )
+    .unwrap();
+    std::fs::write(mods.join("broken-mod_1.0.0.zip"), b"PK").unwrap();
+    let output = run(&[
+        "--hex",
+        SIMPLE_HEX,
+        "-f",
+        "json",
+        "--resolve-defaults",
+        mods.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let settings: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let setting = &settings["startup"]["my-string-setting"];
+    assert_eq!(setting["value"], "deadbeef");
+    assert_eq!(setting["default"], "cafe");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Couldn't read zipped mod"), "{}", stderr);
+    assert!(stderr.contains("broken-mod_1.0.0.zip"), "{}", stderr);
+    assert!(!stderr.contains("zipped-mod_1.0.0.zip"), "{}", stderr);
+
+    let output = run(&[
+        "--hex",
+        SIMPLE_HEX,
+        "-f",
+        "json",
+        "--resolve-defaults",
+        "test_data/complex-settings.dat",
+    ]);
+    let settings: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(settings["startup"]["my-string-setting"]
+        .get("default")
+        .is_none());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 settings have no known default"));
+    std::fs::remove_dir_all(mods).unwrap();
+}
+
+#[test]
+fn batch_decode_compressed_and_failed_files() {
+    let dir = temp_path("batch-inputs");
+    let out = temp_path("batch-outputs");
+    std::fs::create_dir_all(&dir).unwrap();
+    let dat = std::fs::read("test_data/golden.dat").unwrap();
+    // the version can be read from the truncated file, but not its settings
+    std::fs::write(dir.join("a-truncated.dat"), &dat[..dat.len() / 2]).unwrap();
+    let output = run(&[
+        "--gzip",
+        "--passthrough",
+        "test_data/golden.dat",
+        dir.join("b.dat.gz").to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+
+    let output = run(&[
+        "--output-template",
+        "{version}.json",
+        "-f",
+        "json",
+        dir.join("*").to_str().unwrap(),
+        out.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 of 2 files failed"), "{}", stderr);
+    assert!(!stderr.contains("already written"), "{}", stderr);
+    let written = std::fs::read_dir(&out).unwrap().next().unwrap().unwrap();
+    let decoded: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(written.path()).unwrap()).unwrap();
+    assert!(decoded["startup"].as_object().is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(out).unwrap();
+}
+
+#[test]
+fn batch_decode_with_output_template() {
+    let dir = temp_path("batch");
+    let output = run(&[
+        "--output-template",
+        "{version}/{stem}.{format}",
+        "-f",
+        "json",
+        "test_data/*.dat",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let complex = dir.join("1.1.82.4/complex-settings.json");
+    let decoded: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&complex).unwrap()).unwrap();
+    assert!(decoded["startup"].as_object().is_some());
+    assert!(dir.join("1.1.82.4/golden.json").is_file());
+    assert_eq!(
+        std::fs::read_dir(&dir).unwrap().count(),
+        2,
+        "one directory per version"
+    );
+
+    let output = run(&[
+        "--output-template",
+        "all.json",
+        "-f",
+        "json",
+        "test_data",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 of 4 files failed"));
+
+    let output = run(&[
+        "--output-template",
+        "{name}.json",
+        "-f",
+        "json",
+        "test_data",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    std::fs::remove_dir_all(dir).unwrap();
+}